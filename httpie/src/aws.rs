@@ -0,0 +1,364 @@
+use std::{fs, path::PathBuf};
+
+use anyhow::{anyhow, Result};
+use openssl::{
+    hash::{hash, MessageDigest},
+    pkey::PKey,
+    sign::Signer,
+};
+use reqwest::header::{self, HeaderName, HeaderValue};
+use time::OffsetDateTime;
+
+use crate::cli::*;
+
+/// 签名的算法名, 也是Authorization的scheme
+const ALGORITHM: &str = "AWS4-HMAC-SHA256";
+
+/// 这些请求头可能被代理修改, 和botocore一样不参与签名
+const UNSIGNED_HEADERS: [&str; 3] = ["authorization", "user-agent", "expect"];
+
+/// AWS的访问密钥
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct AwsCredentials {
+    pub(crate) access_key: String,
+    pub(crate) secret_key: String,
+    pub(crate) session_token: Option<String>,
+}
+
+impl AwsCredentials {
+    /// 依次使用 -a ACCESS_KEY:SECRET_KEY, 环境变量和 ~/.aws/credentials
+    pub(crate) fn load(opt: &App) -> Result<Self> {
+        let mut credentials = match &opt.auth {
+            Some(Secret(s)) => {
+                let (access_key, secret_key) = s
+                    .split_once(':')
+                    .ok_or_else(|| anyhow!("--auth-type aws4 needs -a ACCESS_KEY:SECRET_KEY"))?;
+                Self { access_key: access_key.to_string(), secret_key: secret_key.to_string(), session_token: None }
+            }
+            None => match Self::from_env() {
+                Some(credentials) => credentials,
+                None => Self::from_file()?,
+            },
+        };
+        if let Some(Secret(token)) = &opt.aws_session_token {
+            credentials.session_token = Some(token.clone());
+        }
+        Ok(credentials)
+    }
+
+    fn from_env() -> Option<Self> {
+        let var = |name| std::env::var(name).ok().filter(|v| !v.is_empty());
+        Some(Self {
+            access_key: var("AWS_ACCESS_KEY_ID")?,
+            secret_key: var("AWS_SECRET_ACCESS_KEY")?,
+            session_token: var("AWS_SESSION_TOKEN"),
+        })
+    }
+
+    /// AWS_SHARED_CREDENTIALS_FILE 或者 ~/.aws/credentials 中 AWS_PROFILE (默认为default) 这一节
+    fn from_file() -> Result<Self> {
+        let path = match std::env::var_os("AWS_SHARED_CREDENTIALS_FILE") {
+            Some(path) => PathBuf::from(path),
+            None => dirs::home_dir()
+                .ok_or_else(|| anyhow!("Failed to find the home directory"))?
+                .join(".aws")
+                .join("credentials"),
+        };
+        let profile = std::env::var("AWS_PROFILE").unwrap_or_else(|_| "default".to_string());
+        let content = fs::read_to_string(&path).map_err(|e| {
+            anyhow!(
+                "Failed to read AWS credentials {}: {} (set AWS_ACCESS_KEY_ID and AWS_SECRET_ACCESS_KEY instead)",
+                path.display(),
+                e
+            )
+        })?;
+        Self::parse(&content, &profile)
+            .map_err(|e| anyhow!("Failed to read AWS credentials {}: {}", path.display(), e))
+    }
+
+    /// credentials文件是INI格式
+    pub(crate) fn parse(content: &str, profile: &str) -> Result<Self> {
+        let mut section = None;
+        let (mut access_key, mut secret_key, mut session_token) = (None, None, None);
+        for line in content.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+                continue;
+            }
+            if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                section = Some(name.trim().to_string());
+                continue;
+            }
+            if section.as_deref() != Some(profile) {
+                continue;
+            }
+            if let Some((key, value)) = line.split_once('=') {
+                let value = Some(value.trim().to_string());
+                match key.trim() {
+                    "aws_access_key_id" => access_key = value,
+                    "aws_secret_access_key" => secret_key = value,
+                    "aws_session_token" => session_token = value,
+                    _ => {}
+                }
+            }
+        }
+        match (access_key, secret_key) {
+            (Some(access_key), Some(secret_key)) => Ok(Self { access_key, secret_key, session_token }),
+            _ => Err(anyhow!("profile {} has no aws_access_key_id and aws_secret_access_key", profile)),
+        }
+    }
+}
+
+/// --auth-type aws4: 用AWS Signature Version 4签名请求
+/// 在设置完请求头和body之后签名, 之后reqwest添加的请求头 (例如Cookie) 不参与签名
+pub(crate) struct AwsSigner {
+    pub(crate) credentials: AwsCredentials,
+    pub(crate) region: String,
+    pub(crate) service: String,
+}
+
+impl AwsSigner {
+    pub(crate) fn from_args(opt: &App) -> Result<Self> {
+        let region = opt
+            .aws_region
+            .clone()
+            .or_else(|| std::env::var("AWS_REGION").ok())
+            .or_else(|| std::env::var("AWS_DEFAULT_REGION").ok())
+            .ok_or_else(|| anyhow!("--auth-type aws4 needs --aws-region or AWS_REGION"))?;
+        let service = opt
+            .aws_service
+            .clone()
+            .ok_or_else(|| anyhow!("--auth-type aws4 needs --aws-service, for example s3 or execute-api"))?;
+        Ok(Self { credentials: AwsCredentials::load(opt)?, region, service })
+    }
+
+    pub(crate) fn sign(&self, req: &mut reqwest::Request) -> Result<()> {
+        self.sign_at(req, OffsetDateTime::now_utc())
+    }
+
+    pub(crate) fn sign_at(&self, req: &mut reqwest::Request, now: OffsetDateTime) -> Result<()> {
+        let payload = match req.body().map(|body| body.as_bytes()) {
+            Some(Some(bytes)) => sha256_hex(bytes),
+            Some(None) => return Err(anyhow!("--auth-type aws4 can't sign a streamed body yet")),
+            None => sha256_hex(b""),
+        };
+        let amz_date = format!(
+            "{:04}{:02}{:02}T{:02}{:02}{:02}Z",
+            now.year(),
+            u8::from(now.month()),
+            now.day(),
+            now.hour(),
+            now.minute(),
+            now.second()
+        );
+        let date = &amz_date[..8];
+
+        // 查询参数按照签名时的编码重新写入URL, 这样发送的和签名的完全一样
+        let query = canonical_query(req.url());
+        req.url_mut().set_query(Some(&query).filter(|q| !q.is_empty()).map(|q| q.as_str()));
+        let headers = req.headers_mut();
+        headers.insert(HeaderName::from_static("x-amz-date"), HeaderValue::from_str(&amz_date)?);
+        headers.insert(HeaderName::from_static("x-amz-content-sha256"), HeaderValue::from_str(&payload)?);
+        if let Some(token) = &self.credentials.session_token {
+            headers.insert(HeaderName::from_static("x-amz-security-token"), HeaderValue::from_str(token)?);
+        }
+
+        let (canonical_headers, signed_headers) = canonical_headers(req)?;
+        let canonical_request = format!(
+            "{}\n{}\n{}\n{}\n{}\n{}",
+            req.method(),
+            canonical_uri(req.url(), self.service == "s3"),
+            query,
+            canonical_headers,
+            signed_headers,
+            payload
+        );
+        let scope = format!("{}/{}/{}/aws4_request", date, self.region, self.service);
+        let string_to_sign = format!(
+            "{}\n{}\n{}\n{}",
+            ALGORITHM,
+            amz_date,
+            scope,
+            sha256_hex(canonical_request.as_bytes())
+        );
+
+        let mut key = hmac(format!("AWS4{}", self.credentials.secret_key).as_bytes(), date)?;
+        for part in [&self.region, &self.service, "aws4_request"].iter() {
+            key = hmac(&key, part)?;
+        }
+        let signature = hex(&hmac(&key, &string_to_sign)?);
+        let authorization = format!(
+            "{} Credential={}/{}, SignedHeaders={}, Signature={}",
+            ALGORITHM, self.credentials.access_key, scope, signed_headers, signature
+        );
+        req.headers_mut().insert(header::AUTHORIZATION, HeaderValue::from_str(&authorization)?);
+        Ok(())
+    }
+}
+
+/// 路径的每一段都按照RFC 3986编码, 除了S3以外的服务要编码两次
+fn canonical_uri(url: &reqwest::Url, s3: bool) -> String {
+    let path = url.path();
+    let path = if path.is_empty() { "/" } else { path };
+    path.split('/')
+        .map(|segment| {
+            let encoded = uri_encode(&percent_decode(segment));
+            if s3 {
+                encoded
+            } else {
+                uri_encode(&encoded)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// 按照名字和值排序, 空格编码为%20而不是+
+fn canonical_query(url: &reqwest::Url) -> String {
+    let mut pairs = url
+        .query_pairs()
+        .map(|(k, v)| (uri_encode(&k), uri_encode(&v)))
+        .collect::<Vec<_>>();
+    pairs.sort();
+    pairs.into_iter().map(|(k, v)| format!("{}={}", k, v)).collect::<Vec<_>>().join("&")
+}
+
+/// 小写的名字排序, 值去掉首尾空白, 中间连续的空白变成一个空格, 同名的值用逗号连接
+fn canonical_headers(req: &reqwest::Request) -> Result<(String, String)> {
+    let mut headers: Vec<(String, Vec<String>)> = Vec::new();
+    // 命令行中给出的 Host:xxx 优先
+    let host = match (req.headers().get(header::HOST), req.url().host_str(), req.url().port()) {
+        (Some(host), _, _) => String::from_utf8_lossy(host.as_bytes()).trim().to_string(),
+        (None, Some(host), Some(port)) => format!("{}:{}", host, port),
+        (None, Some(host), None) => host.to_string(),
+        (None, None, _) => return Err(anyhow!("{} has no host", req.url())),
+    };
+    headers.push(("host".to_string(), vec![host]));
+    for (name, value) in req.headers() {
+        if UNSIGNED_HEADERS.contains(&name.as_str()) || name == header::HOST {
+            continue;
+        }
+        let value = String::from_utf8_lossy(value.as_bytes());
+        let value = value.split_whitespace().collect::<Vec<_>>().join(" ");
+        match headers.iter_mut().find(|(n, _)| n == name.as_str()) {
+            Some((_, values)) => values.push(value),
+            None => headers.push((name.as_str().to_string(), vec![value])),
+        }
+    }
+    headers.sort();
+    let canonical = headers.iter().map(|(name, values)| format!("{}:{}\n", name, values.join(","))).collect();
+    let signed = headers.iter().map(|(name, _)| name.as_str()).collect::<Vec<_>>().join(";");
+    Ok((canonical, signed))
+}
+
+/// 只保留 A-Z a-z 0-9 - _ . ~, 其它字节都编码为 %XX
+fn uri_encode(s: &str) -> String {
+    s.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes.get(i + 1..i + 3).and_then(|h| std::str::from_utf8(h).ok());
+        match (bytes[i], hex.and_then(|h| u8::from_str_radix(h, 16).ok())) {
+            (b'%', Some(b)) => {
+                decoded.push(b);
+                i += 3;
+            }
+            (b, _) => {
+                decoded.push(b);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn hmac(key: &[u8], data: &str) -> Result<Vec<u8>> {
+    let key = PKey::hmac(key)?;
+    let mut signer = Signer::new(MessageDigest::sha256(), &key)?;
+    signer.update(data.as_bytes())?;
+    Ok(signer.sign_to_vec()?)
+}
+
+fn sha256_hex(data: &[u8]) -> String {
+    hex(&hash(MessageDigest::sha256(), data).expect("SHA-256 is always available"))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 2015-08-30T12:36:00Z
+    fn example_date() -> OffsetDateTime {
+        OffsetDateTime::from_unix_timestamp(1440938160).unwrap()
+    }
+
+    fn signer(service: &str, session_token: Option<&str>) -> AwsSigner {
+        AwsSigner {
+            credentials: AwsCredentials {
+                access_key: "AKIDEXAMPLE".to_string(),
+                secret_key: "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY".to_string(),
+                session_token: session_token.map(String::from),
+            },
+            region: "us-east-1".to_string(),
+            service: service.to_string(),
+        }
+    }
+
+    /// 期望的签名由botocore计算
+    #[test]
+    fn signatures_match_botocore() {
+        let client = reqwest::Client::new();
+        let mut req = client
+            .post("https://example.amazonaws.com/my path/x")
+            .query(&[("b", "2"), ("a", "x y"), ("a", "!")])
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(r#"{"k":"v"}"#)
+            .build()
+            .unwrap();
+        signer("execute-api", Some("TOKEN")).sign_at(&mut req, example_date()).unwrap();
+        assert_eq!(req.url().query(), Some("a=%21&a=x%20y&b=2"));
+        assert_eq!(req.headers()["x-amz-date"], "20150830T123600Z");
+        assert_eq!(req.headers()["x-amz-security-token"], "TOKEN");
+        assert_eq!(
+            req.headers()[header::AUTHORIZATION],
+            "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/execute-api/aws4_request, \
+             SignedHeaders=content-type;host;x-amz-content-sha256;x-amz-date;x-amz-security-token, \
+             Signature=04ad9bfbbd921d2dd9eacce10de725607faa6b65af801ddf12dd73b8e755135e"
+        );
+
+        let mut req = client.get("https://examplebucket.s3.amazonaws.com/photos/my cat.jpg?acl").build().unwrap();
+        signer("s3", None).sign_at(&mut req, example_date()).unwrap();
+        assert_eq!(
+            req.headers()["x-amz-content-sha256"],
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert_eq!(
+            req.headers()[header::AUTHORIZATION],
+            "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/s3/aws4_request, \
+             SignedHeaders=host;x-amz-content-sha256;x-amz-date, \
+             Signature=a1b4f9a84d1ffcd9699b60878f85107a93f9d2ae0717c6d3d41708471f80af88"
+        );
+    }
+
+    #[test]
+    fn credentials_are_read_from_the_profile() {
+        let content = "[default]\naws_access_key_id = A\naws_secret_access_key = B\n\n[work]\n\
+                       aws_access_key_id=C\naws_secret_access_key=D\naws_session_token=E\n";
+        let work = AwsCredentials::parse(content, "work").unwrap();
+        assert_eq!((work.access_key.as_str(), work.session_token.as_deref()), ("C", Some("E")));
+        assert_eq!(AwsCredentials::parse(content, "default").unwrap().secret_key, "B");
+        assert!(AwsCredentials::parse(content, "missing").is_err());
+    }
+}
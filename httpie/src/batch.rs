@@ -0,0 +1,168 @@
+use std::{
+    collections::HashSet,
+    io,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Result};
+use colored::Colorize;
+use futures_util::{stream, StreamExt};
+use reqwest::{StatusCode, Url};
+
+use crate::{cli::*, download::*, error::*, output::*, request::*};
+
+/// 由URL得到的文件名最长的字节数, 超过时截断
+const MAX_FILENAME: usize = 120;
+
+/// 一个URL的结果
+struct Fetched {
+    status: StatusCode,
+    size: u64,
+    saved: Option<PathBuf>,
+}
+
+/// get --url-file: 最多同时发送 -C 个请求, 每完成一个打印一行状态码, 大小和耗时
+///
+/// 连接失败和不是2xx的状态码都算失败, 最后列出所有失败的URL, 有失败时返回 RequestsFailed
+pub(crate) async fn run(ctx: &Context, args: &Get, out: &mut impl Output) -> Result<()> {
+    if let Some(dir) = &args.output_dir {
+        tokio::fs::create_dir_all(dir)
+            .await
+            .map_err(|e| anyhow!("Failed to create {}: {}", dir.display(), e))?;
+    }
+    // 在开始之前就确定所有的文件名, 这样同时进行的请求不会写到同一个文件
+    let mut taken = HashSet::new();
+    let jobs = args
+        .urls
+        .iter()
+        .map(|url| {
+            let path = args.output_dir.as_ref().map(|dir| output_path(dir, url, &mut taken));
+            (url.clone(), path)
+        })
+        .collect::<Vec<_>>();
+    let mut results = stream::iter(jobs)
+        .map(|(url, path)| async move {
+            let started = Instant::now();
+            let result = fetch(ctx, args, &url, path.as_deref()).await;
+            (url, result, started.elapsed())
+        })
+        .buffer_unordered(ctx.concurrency);
+
+    let mut failures = Vec::new();
+    let mut done = 0;
+    while let Some((url, result, elapsed)) = results.next().await {
+        done += 1;
+        let time = format_duration(elapsed).dimmed();
+        match result {
+            Ok(fetched) => {
+                let saved = match &fetched.saved {
+                    Some(path) => format!(" -> {}", path.display()),
+                    None => String::new(),
+                };
+                let size = format!("{} bytes", fetched.size);
+                writeln!(out, "{} {} {} {}{}", styled_status(fetched.status), size, time, url, saved)?;
+                if !fetched.status.is_success() {
+                    failures.push((url, fetched.status.to_string()));
+                }
+            }
+            Err(e) => {
+                writeln!(out, "{} {} {}", "FAIL".red().bold(), time, url)?;
+                failures.push((url, format!("{:#}", e)));
+            }
+        }
+        if args.fail_fast && !failures.is_empty() {
+            // 丢掉stream就取消了还在进行的请求
+            break;
+        }
+    }
+    drop(results);
+    ctx.save_records()?;
+
+    let skipped = args.urls.len() - done;
+    let mut summary = format!("{} succeeded, {} failed", done - failures.len(), failures.len());
+    if skipped > 0 {
+        summary.push_str(&format!(", {} skipped after the first failure", skipped));
+    }
+    writeln!(out, "{}", if failures.is_empty() { summary.green() } else { summary.red() })?;
+    for (url, reason) in failures.iter() {
+        writeln!(out, "{}", format!("  {}: {}", url, reason).red())?;
+    }
+    match failures.len() {
+        0 => Ok(()),
+        n => Err(RequestsFailed(n).into()),
+    }
+}
+
+async fn fetch(ctx: &Context, args: &Get, url: &str, path: Option<&Path>) -> Result<Fetched> {
+    let resp = match ctx.send(ctx.client.get(url), &args.items, &mut io::sink()).await? {
+        Some(resp) => resp,
+        None => return Err(anyhow!("--offline does not send the request")),
+    };
+    let status = resp.status();
+    match path.filter(|_| status.is_success()) {
+        Some(path) => {
+            let mut file = tokio::fs::File::create(path)
+                .await
+                .map_err(|e| anyhow!("Failed to create {}: {}", path.display(), e))?;
+            let size = write_body(ctx, &mut file, resp).await?;
+            Ok(Fetched { status, size, saved: Some(path.to_path_buf()) })
+        }
+        None => {
+            let size = read_body(ctx, resp).await?.len() as u64;
+            Ok(Fetched { status, size, saved: None })
+        }
+    }
+}
+
+/// 由URL得到的文件名, 例如 https://example.com/users/1?page=2 是 example.com_users_1_page=2
+/// 只保留字母, 数字和 . - _ =, 和已经用过的或者已经存在的文件重名时加上 -1, -2, ...
+fn output_path(dir: &Path, url: &str, taken: &mut HashSet<PathBuf>) -> PathBuf {
+    let name = match Url::parse(url) {
+        Ok(url) => {
+            let mut name = format!("{}{}", url.host_str().unwrap_or_default(), url.path());
+            if let Some(query) = url.query() {
+                name.push('_');
+                name.push_str(query);
+            }
+            name
+        }
+        Err(_) => url.to_string(),
+    };
+    let mut name = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || ".-_=".contains(c) { c } else { '_' })
+        .collect::<String>();
+    name = name.trim_matches(|c| c == '_' || c == '.').to_string();
+    name.truncate(MAX_FILENAME);
+    if name.is_empty() {
+        name = "index".to_string();
+    }
+    let path = dir.join(&name);
+    let path = (0..)
+        .map(|i| if i == 0 { path.clone() } else { dir.join(format!("{}-{}", name, i)) })
+        .find(|path| !taken.contains(path) && !path.exists())
+        .unwrap();
+    taken.insert(path.clone());
+    path
+}
+
+fn format_duration(elapsed: Duration) -> String {
+    format!("{}ms", elapsed.as_millis())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn output_paths_are_path_safe_and_unique() {
+        let dir = Path::new("/nonexistent-httpie-out");
+        let mut taken = HashSet::new();
+        let mut path = |url: &str| output_path(dir, url, &mut taken).display().to_string();
+        assert_eq!(path("https://example.com/users/1?page=2"), "/nonexistent-httpie-out/example.com_users_1_page=2");
+        assert_eq!(path("https://example.com/../etc/passwd"), "/nonexistent-httpie-out/example.com_etc_passwd");
+        assert_eq!(path("https://example.com/users/1?page=2"), "/nonexistent-httpie-out/example.com_users_1_page=2-1");
+        assert_eq!(path("http://example.com/"), "/nonexistent-httpie-out/example.com");
+    }
+}
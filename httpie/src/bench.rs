@@ -0,0 +1,306 @@
+use std::{
+    collections::BTreeMap,
+    fmt,
+    io::{self, Write},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Result};
+use colored::Colorize;
+use futures_util::StreamExt;
+use indicatif::{HumanBytes, ProgressBar, ProgressStyle};
+use reqwest::StatusCode;
+use tokio::{sync::Semaphore, task::JoinSet};
+
+use crate::{cli::*, error::*, request::*};
+
+/// 没有得到响应的请求的分类, 错误的状态码单独统计
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum Failure {
+    Timeout,
+    Connect,
+    Other,
+}
+
+impl Failure {
+    pub(crate) fn of(e: &anyhow::Error) -> Self {
+        let connect = e.chain().any(|e| {
+            e.downcast_ref::<reqwest::Error>()
+                .is_some_and(|e| e.is_connect())
+        });
+        if is_timeout(e) {
+            Failure::Timeout
+        } else if connect {
+            Failure::Connect
+        } else {
+            Failure::Other
+        }
+    }
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Failure::Timeout => "timeout",
+            Failure::Connect => "connect",
+            Failure::Other => "other",
+        };
+        f.write_str(name)
+    }
+}
+
+/// --repeat 的统计结果, 连接失败等错误不计入延迟
+///
+/// 只包含排序后的结果, 请求完成的顺序不影响打印出来的统计
+#[derive(Debug, Default)]
+pub(crate) struct Stats {
+    /// 每个响应从发送到读取完body的耗时
+    pub(crate) latencies: Vec<Duration>,
+    pub(crate) statuses: BTreeMap<u16, u64>,
+    /// 没有得到响应的请求, 按分类和错误信息统计
+    pub(crate) errors: BTreeMap<(Failure, String), u64>,
+    /// 收到的body的字节数 (压缩过的大小)
+    pub(crate) bytes: u64,
+    pub(crate) elapsed: Duration,
+}
+
+impl Stats {
+    pub(crate) fn add(&mut self, result: Result<(StatusCode, u64)>, latency: Duration) {
+        match result {
+            Ok((status, size)) => {
+                self.latencies.push(latency);
+                *self.statuses.entry(status.as_u16()).or_default() += 1;
+                self.bytes += size;
+            }
+            // 同一个错误的上下文都一样, 只保留最根本的原因
+            Err(e) => {
+                let key = (Failure::of(&e), e.root_cause().to_string());
+                *self.errors.entry(key).or_default() += 1;
+            }
+        }
+    }
+
+    pub(crate) fn non_success(&self) -> u64 {
+        let success = 200..300;
+        self.statuses.iter().filter(|(status, _)| !success.contains(*status)).map(|(_, n)| n).sum()
+    }
+
+    pub(crate) fn print(&self, out: &mut impl Write) -> io::Result<()> {
+        let label = |name: &str| format!("{:<14}", name).bold();
+        let completed = self.latencies.len() as u64;
+        let failed = self.errors.values().sum::<u64>();
+        writeln!(
+            out,
+            "{}{} completed, {} non-2xx, {} failed",
+            label("Requests:"),
+            completed,
+            self.non_success(),
+            failed
+        )?;
+        writeln!(out, "{}{:.3}s", label("Total time:"), self.elapsed.as_secs_f64())?;
+        let secs = self.elapsed.as_secs_f64();
+        let rate = if secs > 0.0 { completed as f64 / secs } else { 0.0 };
+        writeln!(out, "{}{:.2}", label("Requests/sec:"), rate)?;
+        writeln!(out, "{}{}", label("Transferred:"), HumanBytes(self.bytes))?;
+
+        let mut sorted = self.latencies.clone();
+        sorted.sort();
+        if let (Some(min), Some(max)) = (sorted.first(), sorted.last()) {
+            let mean = sorted.iter().sum::<Duration>() / sorted.len() as u32;
+            writeln!(out, "{}", "Latency:".bold())?;
+            for (name, value) in [
+                ("min", *min),
+                ("mean", mean),
+                ("median", percentile(&sorted, 50.0)),
+                ("p95", percentile(&sorted, 95.0)),
+                ("p99", percentile(&sorted, 99.0)),
+                ("max", *max),
+            ] {
+                writeln!(out, "  {:<8}{}", name, millis(value))?;
+            }
+            writeln!(out, "{}", "Histogram:".bold())?;
+            let buckets = histogram(&sorted, 10);
+            let most = buckets.iter().map(|(_, n)| *n).max().unwrap_or(1);
+            for (upper, n) in buckets {
+                let bar = "■".repeat((n * 40).div_ceil(most) as usize);
+                writeln!(out, "  {:>10}  {:<40} {}", millis(upper), bar, n)?;
+            }
+        }
+        if !self.statuses.is_empty() {
+            writeln!(out, "{}", "Status codes:".bold())?;
+            for (status, n) in self.statuses.iter() {
+                writeln!(out, "  {:<8}{}", status, n)?;
+            }
+        }
+        if !self.errors.is_empty() {
+            writeln!(out, "{}", "Errors:".bold())?;
+            for ((failure, error), n) in self.errors.iter() {
+                writeln!(out, "  {:<10}{:<8}{}", failure.to_string(), n, error)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn millis(d: Duration) -> String {
+    format!("{:.2}ms", d.as_secs_f64() * 1000.0)
+}
+
+/// 把min和max之间平均分成最多n段, 返回每段的上限和其中的请求数, sorted必须已经排好序
+pub(crate) fn histogram(sorted: &[Duration], n: u32) -> Vec<(Duration, u64)> {
+    let (Some(&min), Some(&max)) = (sorted.first(), sorted.last()) else {
+        return Vec::new();
+    };
+    if min == max {
+        return vec![(max, sorted.len() as u64)];
+    }
+    let width = (max - min) / n;
+    let mut buckets = (1..=n).map(|i| (min + width * i, 0)).collect::<Vec<_>>();
+    // 整除的误差可能让最后一段的上限比max小一点
+    buckets[n as usize - 1].0 = max;
+    for latency in sorted {
+        let i = buckets.iter().position(|(upper, _)| latency <= upper).unwrap_or(n as usize - 1);
+        buckets[i].1 += 1;
+    }
+    buckets
+}
+
+/// 按照nearest-rank取百分位数, sorted必须已经排好序并且不为空
+pub(crate) fn percentile(sorted: &[Duration], p: f64) -> Duration {
+    let rank = (p / 100.0 * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+/// --repeat 模式: 使用同一个client (复用连接) 发送count次请求, 不打印响应, 最后打印统计结果
+///
+/// 每个请求在单独的task中发送, 同时进行的请求数由 --concurrency 限制
+/// Ctrl-C 时停止发送并取消未完成的请求, 打印已经完成的请求的统计
+/// 这些请求不会记录到HAR文件和历史中
+pub(crate) async fn run(
+    ctx: &Arc<Context>,
+    cmd: &SubCommand,
+    count: u64,
+    out: &mut impl Write,
+) -> Result<()> {
+    let (req, items) = build_request(ctx, cmd).await?;
+    let req = ctx.build(req, items)?;
+    if req.try_clone().is_none() {
+        return Err(anyhow!("--repeat can't be used with a streamed body, e.g. key@path uploads"));
+    }
+
+    let bar = if ctx.progress() { ProgressBar::new(count) } else { ProgressBar::hidden() };
+    if let Ok(style) = ProgressStyle::with_template("{bar:30} {pos}/{len} {per_sec} ETA {eta}") {
+        bar.set_style(style);
+    }
+    let mut stats = Stats::default();
+    let semaphore = Arc::new(Semaphore::new(ctx.concurrency));
+    let mut tasks = JoinSet::new();
+    let interrupted = tokio::signal::ctrl_c();
+    tokio::pin!(interrupted);
+    let mut stopped = false;
+    let start = Instant::now();
+    for i in 0..count {
+        // 有并发时 --delay 是两次开始发送之间的间隔
+        if i > 0 && !ctx.repeat_delay.is_zero() {
+            tokio::select! {
+                _ = tokio::time::sleep(ctx.repeat_delay) => {}
+                _ = &mut interrupted => { stopped = true; break }
+            }
+        }
+        let permit = tokio::select! {
+            permit = semaphore.clone().acquire_owned() => permit?,
+            _ = &mut interrupted => { stopped = true; break }
+        };
+        while let Some(done) = tasks.try_join_next() {
+            let (result, latency) = done?;
+            stats.add(result, latency);
+            bar.inc(1);
+        }
+        let (ctx, req) = (ctx.clone(), req.try_clone().unwrap());
+        tasks.spawn(async move {
+            let sent = Instant::now();
+            let result = send_once(&ctx, req).await;
+            drop(permit);
+            (result, sent.elapsed())
+        });
+    }
+    if stopped {
+        tasks.abort_all();
+    }
+    loop {
+        tokio::select! {
+            done = tasks.join_next() => match done {
+                Some(Ok((result, latency))) => {
+                    stats.add(result, latency);
+                    bar.inc(1);
+                }
+                Some(Err(e)) if e.is_cancelled() => {}
+                Some(Err(e)) => return Err(e.into()),
+                None => break,
+            },
+            _ = &mut interrupted, if !stopped => {
+                stopped = true;
+                tasks.abort_all();
+            }
+        }
+    }
+    stats.elapsed = start.elapsed();
+    bar.finish_and_clear();
+
+    if stopped && ctx.warnings() {
+        let done = stats.latencies.len() as u64 + stats.errors.values().sum::<u64>();
+        eprintln!("{}", format!("Interrupted after {} of {} requests", done, count).yellow());
+    }
+    stats.print(out)?;
+    Ok(())
+}
+
+/// 发送一次请求并读取完body, 返回状态码和body的字节数
+async fn send_once(ctx: &Context, req: reqwest::Request) -> Result<(StatusCode, u64)> {
+    let resp = ctx.execute(req).await?;
+    let status = resp.status();
+    let mut size = 0;
+    let mut stream = resp.bytes_stream();
+    while let Some(chunk) = stream.next().await {
+        size += chunk?.len() as u64;
+    }
+    Ok((status, size))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn percentile_uses_nearest_rank() {
+        let sorted = (1..=100).map(Duration::from_millis).collect::<Vec<_>>();
+        assert_eq!(percentile(&sorted, 50.0), Duration::from_millis(50));
+        assert_eq!(percentile(&sorted, 95.0), Duration::from_millis(95));
+        assert_eq!(percentile(&sorted, 99.0), Duration::from_millis(99));
+        assert_eq!(percentile(&sorted[..1], 99.0), Duration::from_millis(1));
+    }
+
+    #[test]
+    fn non_success_statuses_and_errors_are_counted_separately() {
+        let mut stats = Stats::default();
+        let ms = Duration::from_millis;
+        stats.add(Ok((StatusCode::OK, 10)), ms(1));
+        stats.add(Ok((StatusCode::NOT_FOUND, 5)), ms(2));
+        stats.add(Ok((StatusCode::FOUND, 0)), ms(3));
+        stats.add(Err(anyhow!("connection refused").context("error sending request")), ms(4));
+        assert_eq!(stats.latencies, [ms(1), ms(2), ms(3)]);
+        assert_eq!(stats.non_success(), 2);
+        assert_eq!(stats.bytes, 15);
+        assert_eq!(stats.errors[&(Failure::Other, "connection refused".to_string())], 1);
+    }
+
+    #[test]
+    fn histogram_buckets_cover_min_to_max() {
+        let sorted = [1, 2, 2, 3, 10].map(Duration::from_millis);
+        let buckets = histogram(&sorted, 3);
+        assert_eq!(buckets.iter().map(|(_, n)| *n).collect::<Vec<_>>(), [4, 0, 1]);
+        assert_eq!(buckets[2].0, Duration::from_millis(10));
+        assert_eq!(histogram(&sorted[..1], 3), [(Duration::from_millis(1), 1)]);
+    }
+}
@@ -0,0 +1,607 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use colored::Colorize;
+use mime::Mime;
+use reqwest::{
+    header::{self, HeaderMap, HeaderName, HeaderValue},
+    Method, Response, ResponseBuilderExt, StatusCode, Url, Version,
+};
+use serde::{Deserialize, Serialize};
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+
+use crate::{config::*, output::*, request::*, sse::*};
+
+/// 超过这个大小的body不缓存, 下次请求时也就不会带上验证器
+pub(crate) const MAX_CACHED_BODY: usize = 10 * 1024 * 1024;
+
+/// 缓存中的一个URL, 保存为 etag-cache 目录下的一个JSON文件
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct CacheEntry {
+    pub(crate) url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) etag: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) last_modified: Option<String>,
+    /// 保存这个响应的时间
+    pub(crate) time: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) content_type: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) content_encoding: Option<String>,
+    /// 收到的原始body, 可能是压缩过的, 使用base64编码
+    pub(crate) body: String,
+}
+
+impl CacheEntry {
+    /// 把缓存下来的验证器加到请求中, 命令行中已经给出的不会被替换
+    pub(crate) fn add_validators(&self, headers: &mut HeaderMap) {
+        let validators = [
+            (header::IF_NONE_MATCH, &self.etag),
+            (header::IF_MODIFIED_SINCE, &self.last_modified),
+        ];
+        for (name, value) in validators {
+            let value = value.as_deref().and_then(|v| HeaderValue::from_str(v).ok());
+            if let (Some(value), false) = (value, headers.contains_key(&name)) {
+                headers.insert(name, value);
+            }
+        }
+    }
+
+    /// 解压之后的body
+    pub(crate) fn body(&self) -> Result<Vec<u8>> {
+        let body = STANDARD
+            .decode(&self.body)
+            .map_err(|e| anyhow!("Failed to decode the cached body of {}: {}", self.url, e))?;
+        match &self.content_encoding {
+            Some(encoding) => decode_body(encoding, &body),
+            None => Ok(body),
+        }
+    }
+}
+
+/// --etag-cache 时记录GET请求的URL和这个URL的缓存, 读取完body后写入缓存
+pub(crate) struct EtagCache {
+    pub(crate) dir: PathBuf,
+    pub(crate) show_cached: bool,
+    pub(crate) pending: Mutex<Option<PendingCache>>,
+}
+
+#[derive(Default)]
+pub(crate) struct PendingCache {
+    pub(crate) url: String,
+    /// 发送请求时缓存中已有的内容, 用来处理304
+    pub(crate) cached: Option<CacheEntry>,
+    /// 收到了带验证器的200响应, 读取完body后保存
+    pub(crate) fresh: Option<CacheEntry>,
+    pub(crate) body: Vec<u8>,
+    /// 收到了200响应但是不能缓存, 旧的缓存已经失效了
+    pub(crate) invalidate: bool,
+}
+
+impl EtagCache {
+    pub(crate) fn new(dir: PathBuf, show_cached: bool) -> Self {
+        Self { dir, show_cached, pending: Mutex::new(None) }
+    }
+
+    pub(crate) fn path(&self, url: &str) -> PathBuf {
+        hashed_path(&self.dir, url)
+    }
+
+    /// 读取缓存, 文件损坏时当作没有缓存
+    pub(crate) fn load(&self, url: &str) -> Option<CacheEntry> {
+        let content = fs::read_to_string(self.path(url)).ok()?;
+        serde_json::from_str::<CacheEntry>(&content).ok().filter(|entry| entry.url == url)
+    }
+
+    /// 发送之前调用, 有缓存时加上 If-None-Match 和 If-Modified-Since, 只处理GET请求
+    pub(crate) fn request(&self, req: &mut reqwest::Request) {
+        let mut pending = self.pending.lock().unwrap();
+        if req.method() != Method::GET {
+            *pending = None;
+            return;
+        }
+        let url = req.url().to_string();
+        let cached = self.load(&url);
+        if let Some(entry) = &cached {
+            entry.add_validators(req.headers_mut());
+        }
+        *pending = Some(PendingCache { url, cached, ..Default::default() });
+    }
+
+    /// 收到响应头时调用, 只有200会替换缓存
+    pub(crate) fn response(&self, resp: &Response) {
+        let mut pending = self.pending.lock().unwrap();
+        let Some(pending) = pending.as_mut() else {
+            return;
+        };
+        if resp.status() != StatusCode::OK {
+            return;
+        }
+        let header = |name| {
+            resp.headers()
+                .get(name)
+                .and_then(|v: &HeaderValue| v.to_str().ok())
+                .map(|v| v.to_string())
+        };
+        let (etag, last_modified) = (header(header::ETAG), header(header::LAST_MODIFIED));
+        if etag.is_none() && last_modified.is_none() {
+            pending.invalidate = true;
+            return;
+        }
+        let time = OffsetDateTime::now_utc().replace_nanosecond(0).ok();
+        pending.fresh = Some(CacheEntry {
+            url: pending.url.clone(),
+            etag,
+            last_modified,
+            time: time.and_then(|t| t.format(&Rfc3339).ok()).unwrap_or_default(),
+            content_type: header(header::CONTENT_TYPE),
+            content_encoding: header(header::CONTENT_ENCODING),
+            body: String::new(),
+        });
+    }
+
+    /// 追加读取到的body, 太大时放弃缓存
+    pub(crate) fn body(&self, chunk: &[u8]) {
+        let mut pending = self.pending.lock().unwrap();
+        let Some(pending) = pending.as_mut().filter(|p| p.fresh.is_some()) else {
+            return;
+        };
+        if pending.body.len() + chunk.len() > MAX_CACHED_BODY {
+            pending.fresh = None;
+            pending.body = Vec::new();
+            pending.invalidate = true;
+        } else {
+            pending.body.extend_from_slice(chunk);
+        }
+    }
+
+    /// 服务器返回304时得到发送请求时使用的缓存
+    pub(crate) fn not_modified(&self, resp: &Response) -> Option<CacheEntry> {
+        if resp.status() != StatusCode::NOT_MODIFIED {
+            return None;
+        }
+        let pending = self.pending.lock().unwrap();
+        pending.as_ref().and_then(|p| p.cached.clone())
+    }
+
+    /// 读取完body后写入或者删除缓存文件
+    pub(crate) fn save(&self) -> Result<()> {
+        let Some(pending) = self.pending.lock().unwrap().take() else {
+            return Ok(());
+        };
+        let path = self.path(&pending.url);
+        if let Some(mut entry) = pending.fresh {
+            entry.body = STANDARD.encode(&pending.body);
+            fs::create_dir_all(&self.dir)
+                .map_err(|e| anyhow!("Failed to create {}: {}", self.dir.display(), e))?;
+            let content = serde_json::to_string_pretty(&entry)?;
+            fs::write(&path, content)
+                .map_err(|e| anyhow!("Failed to write {}: {}", path.display(), e))?;
+        } else if pending.invalidate {
+            remove_entry(&path)?;
+        }
+        Ok(())
+    }
+}
+
+/// 文件名是key的SHA-256, 这样任何URL都可以作为文件名
+pub(crate) fn hashed_path(dir: &Path, key: &str) -> PathBuf {
+    let hash = openssl::sha::sha256(key.as_bytes());
+    let name = hash.iter().map(|b| format!("{:02x}", b)).collect::<String>();
+    dir.join(format!("{}.json", name))
+}
+
+fn remove_entry(path: &Path) -> Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => {
+            Err(anyhow!("Failed to remove {}: {}", path.display(), e))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// 缓存目录, 在配置目录中
+pub(crate) fn etag_cache_dir() -> Result<PathBuf> {
+    Ok(config_dir()?.join("etag-cache"))
+}
+
+/// --etag-cache-clear, 删除所有缓存的响应, 返回删除的个数
+pub fn clear_etag_cache() -> Result<usize> {
+    let dir = etag_cache_dir()?;
+    let entries = match fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(anyhow!("Failed to read {}: {}", dir.display(), e)),
+    };
+    let mut removed = 0;
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.extension().is_some_and(|ext| ext == "json") {
+            remove_entry(&path)?;
+            removed += 1;
+        }
+    }
+    Ok(removed)
+}
+
+/// --cache 中保存的一个响应, 同一个URL按照Vary可以有多个
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct CachedResponse {
+    /// Vary中的请求头和保存时请求中的值
+    pub(crate) vary: Vec<(String, Option<String>)>,
+    pub(crate) status: u16,
+    pub(crate) version: String,
+    pub(crate) headers: Vec<(String, String)>,
+    /// 收到的原始body, 可能是压缩过的, 使用base64编码
+    pub(crate) body: String,
+    /// 保存或者重新验证的时间, Unix时间戳
+    pub(crate) stored_at: u64,
+}
+
+/// 一个URL的缓存文件
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub(crate) struct CacheFile {
+    pub(crate) key: String,
+    pub(crate) variants: Vec<CachedResponse>,
+}
+
+impl CachedResponse {
+    fn header(&self, name: &HeaderName) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name.as_str()))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// 请求中Vary列出的请求头和保存时的一样
+    pub(crate) fn matches(&self, headers: &HeaderMap) -> bool {
+        self.vary.iter().all(|(name, value)| {
+            headers.get(name.as_str()).and_then(|v| v.to_str().ok()) == value.as_deref()
+        })
+    }
+
+    /// 从收到时开始算的age, 加上响应中的Age
+    pub(crate) fn age(&self, now: u64) -> u64 {
+        let initial = self.header(&header::AGE).and_then(|v| v.trim().parse().ok()).unwrap_or(0);
+        now.saturating_sub(self.stored_at) + initial
+    }
+
+    /// Cache-Control的max-age, 没有时使用Expires和Date的差
+    pub(crate) fn max_age(&self) -> Option<u64> {
+        let directives = cache_directives(self.header(&header::CACHE_CONTROL));
+        if directives.iter().any(|(name, _)| name == "no-cache") {
+            return Some(0);
+        }
+        if let Some((_, Some(secs))) = directives.iter().find(|(name, _)| name == "max-age") {
+            return secs.parse().ok();
+        }
+        let date = |name| self.header(&name).and_then(|v| httpdate::parse_http_date(v).ok());
+        let (expires, date) = (date(header::EXPIRES)?, date(header::DATE)?);
+        Some(expires.duration_since(date).map_or(0, |d| d.as_secs()))
+    }
+
+    pub(crate) fn is_fresh(&self, now: u64) -> bool {
+        self.max_age().is_some_and(|max_age| self.age(now) < max_age)
+    }
+
+    /// 重新验证时使用的 If-None-Match 和 If-Modified-Since
+    pub(crate) fn add_validators(&self, headers: &mut HeaderMap) {
+        let etag = self.header(&header::ETAG).and_then(|v| HeaderValue::from_str(v).ok());
+        if let Some(etag) = etag {
+            headers.insert(header::IF_NONE_MATCH, etag);
+        }
+        let modified = self.header(&header::LAST_MODIFIED).and_then(|v| HeaderValue::from_str(v).ok());
+        if let Some(modified) = modified {
+            headers.insert(header::IF_MODIFIED_SINCE, modified);
+        }
+    }
+
+    /// 304响应中的响应头替换掉保存的同名响应头
+    pub(crate) fn refresh(&mut self, headers: &HeaderMap, now: u64) {
+        for name in headers.keys() {
+            if name == header::CONTENT_LENGTH {
+                continue;
+            }
+            self.headers.retain(|(n, _)| !n.eq_ignore_ascii_case(name.as_str()));
+            for value in headers.get_all(name).iter().filter_map(|v| v.to_str().ok()) {
+                self.headers.push((name.to_string(), value.to_string()));
+            }
+        }
+        self.stored_at = now;
+    }
+
+    /// 把缓存转换成Response, 之后和收到的响应一样打印
+    pub(crate) fn to_response(&self, url: &Url) -> Result<Response> {
+        let version = match self.version.as_str() {
+            "HTTP/1.0" => Version::HTTP_10,
+            "HTTP/2.0" => Version::HTTP_2,
+            "HTTP/3.0" => Version::HTTP_3,
+            _ => Version::HTTP_11,
+        };
+        let mut builder = hyper::Response::builder().status(self.status).version(version).url(url.clone());
+        for (name, value) in self.headers.iter() {
+            builder = builder.header(name.as_str(), value.as_str());
+        }
+        let body = STANDARD.decode(&self.body).map_err(|e| anyhow!("Invalid cached body: {}", e))?;
+        Ok(Response::from(builder.body(body)?))
+    }
+}
+
+/// 解析Cache-Control, 指令名转成小写, 值去掉引号
+pub(crate) fn cache_directives(value: Option<&str>) -> Vec<(String, Option<String>)> {
+    value
+        .unwrap_or_default()
+        .split(',')
+        .filter_map(|directive| {
+            let (name, value) = match directive.split_once('=') {
+                Some((name, value)) => (name, Some(value.trim().trim_matches('"').to_string())),
+                None => (directive, None),
+            };
+            let name = name.trim().to_ascii_lowercase();
+            Some((name, value)).filter(|(name, _)| !name.is_empty())
+        })
+        .collect()
+}
+
+pub(crate) fn unix_now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
+/// --cache: 按照Cache-Control缓存GET请求的响应, 新鲜的直接使用, 过期的重新验证
+/// 整个目录超过max_size时删除最久没有使用的响应
+pub(crate) struct ResponseCache {
+    pub(crate) dir: PathBuf,
+    /// --cache-bypass 时不使用缓存, 但是仍然保存新的响应
+    pub(crate) bypass: bool,
+    pub(crate) max_size: u64,
+}
+
+impl ResponseCache {
+    fn load(&self, path: &Path, key: &str) -> CacheFile {
+        fs::read_to_string(path)
+            .ok()
+            .and_then(|content| serde_json::from_str::<CacheFile>(&content).ok())
+            .filter(|file| file.key == key)
+            .unwrap_or_else(|| CacheFile { key: key.to_string(), variants: Vec::new() })
+    }
+
+    fn write(&self, path: &Path, file: &CacheFile) -> Result<()> {
+        fs::create_dir_all(&self.dir)
+            .map_err(|e| anyhow!("Failed to create {}: {}", self.dir.display(), e))?;
+        fs::write(path, serde_json::to_string(file)?)
+            .map_err(|e| anyhow!("Failed to write {}: {}", path.display(), e))?;
+        self.evict()
+    }
+
+    /// 按照修改时间删除最旧的文件, 命中缓存时会更新文件的修改时间
+    pub(crate) fn evict(&self) -> Result<()> {
+        let mut files = fs::read_dir(&self.dir)
+            .map_err(|e| anyhow!("Failed to read {}: {}", self.dir.display(), e))?
+            .flatten()
+            .filter_map(|entry| {
+                let meta = entry.metadata().ok()?;
+                Some((meta.modified().ok()?, meta.len(), entry.path()))
+            })
+            .collect::<Vec<_>>();
+        let mut total = files.iter().map(|(_, len, _)| len).sum::<u64>();
+        files.sort();
+        for (_, len, path) in files {
+            if total <= self.max_size {
+                break;
+            }
+            remove_entry(&path)?;
+            total -= len;
+        }
+        Ok(())
+    }
+
+    fn touch(path: &Path) {
+        if let Ok(file) = fs::File::options().write(true).open(path) {
+            let _ = file.set_modified(SystemTime::now());
+        }
+    }
+
+    /// 代替 Context::execute, 只处理GET请求, 命令行中已经有条件请求头时不使用缓存
+    pub(crate) async fn execute(&self, ctx: &Context, mut req: reqwest::Request) -> Result<Response> {
+        let conditional = req.headers().contains_key(header::IF_NONE_MATCH)
+            || req.headers().contains_key(header::IF_MODIFIED_SINCE);
+        if req.method() != Method::GET || conditional {
+            return ctx.execute(req).await;
+        }
+        let url = req.url().clone();
+        let key = format!("{} {}", req.method(), url);
+        let path = hashed_path(&self.dir, &key);
+        let mut file = self.load(&path, &key);
+        let now = unix_now();
+        let found = file.variants.iter().position(|v| v.matches(req.headers())).filter(|_| !self.bypass);
+        if let Some(cached) = found.map(|i| &file.variants[i]) {
+            if cached.is_fresh(now) {
+                Self::touch(&path);
+                if ctx.notes() {
+                    eprintln!("{}", format!("from cache, age {}s", cached.age(now)).dimmed());
+                }
+                return cached.to_response(&url);
+            }
+            cached.add_validators(req.headers_mut());
+        }
+
+        let vary = req.headers().clone();
+        let resp = ctx.execute(req).await?;
+        if let (Some(i), StatusCode::NOT_MODIFIED) = (found, resp.status()) {
+            let cached = &mut file.variants[i];
+            cached.refresh(resp.headers(), now);
+            let resp = cached.to_response(&url)?;
+            self.write(&path, &file)?;
+            if ctx.notes() {
+                eprintln!("{}", "from cache, revalidated".dimmed());
+            }
+            return Ok(resp);
+        }
+        if !storable(ctx, &resp) {
+            return Ok(resp);
+        }
+
+        // 要保存的响应先读取整个body, 再和缓存一样转换成Response
+        let status = resp.status().as_u16();
+        let version = format!("{:?}", resp.version());
+        let headers = resp
+            .headers()
+            .iter()
+            .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+            .collect::<Vec<_>>();
+        let names = resp.headers().get_all(header::VARY).iter().filter_map(|v| v.to_str().ok());
+        let vary = names
+            .flat_map(|names| names.split(','))
+            .map(|name| name.trim().to_ascii_lowercase())
+            .filter(|name| !name.is_empty())
+            .map(|name| {
+                let value = vary.get(name.as_str()).and_then(|v| v.to_str().ok()).map(Into::into);
+                (name, value)
+            })
+            .collect();
+        let body = resp.bytes().await?;
+        let cached = CachedResponse { vary, status, version, headers, body: STANDARD.encode(&body), stored_at: now };
+        let resp = cached.to_response(&url)?;
+        file.variants.retain(|v| v.vary != cached.vary);
+        file.variants.push(cached);
+        self.write(&path, &file)?;
+        Ok(resp)
+    }
+}
+
+/// 只缓存200响应, no-store, Vary: * 和没有新鲜度也没有验证器的不缓存
+fn storable(ctx: &Context, resp: &Response) -> bool {
+    let headers = resp.headers();
+    let value = |name| headers.get(name).and_then(|v: &HeaderValue| v.to_str().ok());
+    let directives = cache_directives(value(header::CACHE_CONTROL));
+    let has = |directive: &str| directives.iter().any(|(name, _)| name == directive);
+    let too_large = resp.content_length().is_some_and(|len| len > MAX_CACHED_BODY as u64);
+    let vary_all = value(header::VARY).is_some_and(|v| v.split(',').any(|name| name.trim() == "*"));
+    let cacheable = has("max-age")
+        || has("no-cache")
+        || headers.contains_key(header::EXPIRES)
+        || headers.contains_key(header::ETAG)
+        || headers.contains_key(header::LAST_MODIFIED);
+    resp.status() == StatusCode::OK
+        && cacheable
+        && !has("no-store")
+        && !vary_all
+        && !too_large
+        && !ctx.stream
+        && !is_event_stream(resp)
+}
+
+/// 响应缓存的目录, 在配置目录中
+pub(crate) fn response_cache_dir() -> Result<PathBuf> {
+    Ok(config_dir()?.join("response-cache"))
+}
+
+/// 304响应: 提示使用的是哪个时间的缓存, --show-cached 时打印缓存的body
+pub(crate) fn print_not_modified(
+    ctx: &Context,
+    out: &mut impl Output,
+    resp: Response,
+    entry: CacheEntry,
+) -> Result<()> {
+    let meta = Meta::new(ctx, &resp);
+    if ctx.print.response_headers {
+        print_head(out, &resp)?;
+    }
+    if ctx.notes() {
+        eprintln!("{}", format!("Not Modified (cached copy from {})", entry.time).dimmed());
+    }
+    let show = ctx.etag_cache.as_ref().is_some_and(|cache| cache.show_cached);
+    let mut size = 0;
+    if show && ctx.print.response_body {
+        let body = entry.body()?;
+        let mime = entry.content_type.as_deref().and_then(|v| v.parse::<Mime>().ok());
+        let text = decode_text(&body, mime.as_ref(), ctx.charset);
+        print_body(ctx, out, mime, &text)?;
+        size = body.len() as u64;
+    }
+    meta.print(size);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry() -> CacheEntry {
+        CacheEntry {
+            url: "http://localhost/x".into(),
+            etag: Some("\"v1\"".into()),
+            last_modified: None,
+            time: String::new(),
+            content_type: None,
+            content_encoding: None,
+            body: STANDARD.encode("hello"),
+        }
+    }
+
+    #[test]
+    fn validators_do_not_replace_headers_from_the_command_line() {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::IF_NONE_MATCH, HeaderValue::from_static("\"v0\""));
+        let mut entry = entry();
+        entry.last_modified = Some("Wed, 21 Oct 2015 07:28:00 GMT".into());
+        entry.add_validators(&mut headers);
+        assert_eq!(headers[header::IF_NONE_MATCH], "\"v0\"");
+        assert_eq!(headers[header::IF_MODIFIED_SINCE], "Wed, 21 Oct 2015 07:28:00 GMT");
+        assert_eq!(entry.body().unwrap(), b"hello");
+    }
+
+    #[test]
+    fn entries_for_other_urls_are_ignored() {
+        let dir = std::env::temp_dir().join(format!("httpie-etag-cache-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let cache = EtagCache::new(dir.clone(), false);
+        let entry = entry();
+        // 模拟文件名冲突, 文件中的URL和请求的不一样
+        fs::write(cache.path("http://localhost/y"), serde_json::to_string(&entry).unwrap())
+            .unwrap();
+        fs::write(cache.path(&entry.url), serde_json::to_string(&entry).unwrap()).unwrap();
+        let other = cache.load("http://localhost/y");
+        let found = cache.load(&entry.url);
+        fs::remove_dir_all(dir).unwrap();
+        assert!(other.is_none());
+        assert_eq!(found.unwrap().etag.as_deref(), Some("\"v1\""));
+    }
+
+    #[test]
+    fn freshness_follows_cache_control_and_expires() {
+        let cached = |headers: &[(&str, &str)]| CachedResponse {
+            vary: vec![("accept".into(), Some("text/html".into()))],
+            status: 200,
+            version: "HTTP/1.1".into(),
+            headers: headers.iter().map(|(n, v)| (n.to_string(), v.to_string())).collect(),
+            body: String::new(),
+            stored_at: 1000,
+        };
+        let resp = cached(&[("Cache-Control", "public, max-age=\"60\""), ("age", "10")]);
+        assert_eq!(resp.max_age(), Some(60));
+        assert_eq!(resp.age(1042), 52);
+        assert!(resp.is_fresh(1049));
+        assert!(!resp.is_fresh(1050));
+
+        let resp = cached(&[
+            ("date", "Wed, 21 Oct 2015 07:28:00 GMT"),
+            ("expires", "Wed, 21 Oct 2015 07:30:00 GMT"),
+        ]);
+        assert_eq!(resp.max_age(), Some(120));
+        assert_eq!(cached(&[("cache-control", "max-age=60, no-cache")]).max_age(), Some(0));
+        assert_eq!(cached(&[("etag", "\"v1\"")]).max_age(), None);
+
+        let mut headers = HeaderMap::new();
+        assert!(!resp.matches(&headers));
+        headers.insert(header::ACCEPT, HeaderValue::from_static("text/html"));
+        assert!(resp.matches(&headers));
+    }
+}
@@ -0,0 +1,221 @@
+use std::{collections::BTreeMap, fs, path::Path, sync::Arc, time::Instant};
+
+use anyhow::{anyhow, Result};
+use colored::Colorize;
+use reqwest::StatusCode;
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::{cli::*, error::*, output::*, request::*, vars::*};
+
+/// 请求集合文件, 例如
+///
+/// ```yaml
+/// vars:
+///   base: https://api.example.com
+/// requests:
+///   - name: health
+///     url: "{base}/health"
+///   - name: login
+///     method: POST
+///     url: "{base}/login"
+///     items: ["user=tom", "X-Trace:smoke"]
+///     status: 201
+/// ```
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CollectionFile {
+    /// 文件中的变量, 会被命令行中的 --var 覆盖
+    #[serde(default)]
+    vars: BTreeMap<String, Value>,
+    requests: Vec<FileRequest>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FileRequest {
+    name: String,
+    #[serde(default = "default_method")]
+    method: String,
+    url: String,
+    /// 和命令行中的请求项语法相同
+    #[serde(default)]
+    items: Vec<String>,
+    /// 期望的状态码, 没有给出时期望2xx
+    status: Option<u16>,
+}
+
+fn default_method() -> String {
+    "GET".to_string()
+}
+
+/// 集合中的一个请求, 已经替换了变量并展开了URL
+#[derive(Debug)]
+pub struct CollectionRequest {
+    pub(crate) name: String,
+    pub(crate) cmd: SubCommand,
+    pub(crate) status: Option<StatusCode>,
+}
+
+impl CollectionRequest {
+    fn passed(&self, status: StatusCode) -> bool {
+        match self.status {
+            Some(expected) => status == expected,
+            None => status.is_success(),
+        }
+    }
+}
+
+/// run 子命令要在创建client之前读取集合文件, 这样 --var 和 --default-scheme 都已经确定了
+///
+/// 变量找不到或者请求项有错误时一个请求都不发送
+pub fn load_collection(opt: &mut App) -> Result<()> {
+    let SubCommand::Run(args) = &mut opt.subcommand else {
+        return Ok(());
+    };
+    if opt.chunked {
+        return Err(anyhow!("--chunked can't be used with run"));
+    }
+    // 集合中的请求只使用文件中的请求项, 不读取标准输入
+    opt.ignore_stdin = true;
+    let file = read_collection(&args.file)?;
+    let names = file.requests.iter().map(|req| req.name.as_str()).collect::<Vec<_>>();
+    if let Some(name) = args.names.iter().find(|name| !names.contains(&name.as_str())) {
+        return Err(anyhow!(
+            "No request named {} in {}, available: {}",
+            name,
+            args.file.display(),
+            names.join(", ")
+        ));
+    }
+
+    // 命令行中的 --var 在后面, 同名时优先
+    let mut vars = file
+        .vars
+        .into_iter()
+        .map(|(k, v)| match v {
+            Value::String(v) => RequestItem::Body(KvPair { k, v }),
+            v => RequestItem::RawJson(k, v),
+        })
+        .collect::<Vec<_>>();
+    vars.extend(opt.vars.iter().cloned());
+    let scheme = opt.default_scheme.clone().unwrap_or_else(|| "http".to_string());
+    let selected = file
+        .requests
+        .into_iter()
+        .filter(|req| args.names.is_empty() || args.names.contains(&req.name));
+    let mut requests = Vec::new();
+    for req in selected {
+        let context = |e: anyhow::Error| anyhow!("{}: request {}: {}", args.file.display(), req.name, e);
+        let items = req
+            .items
+            .iter()
+            .map(|item| parse_request_item(item))
+            .collect::<Result<Vec<_>>>()
+            .map_err(context)?;
+        let method = parse_method(&req.method).map_err(context)?;
+        let mut cmd = SubCommand::Request(Request { method, url: req.url.clone(), items });
+        substitute_vars(&mut cmd, &vars).map_err(context)?;
+        if let Some(url) = cmd.url_mut() {
+            *url = expand_url(url, &scheme).map_err(context)?;
+        }
+        let status = match req.status {
+            Some(status) => Some(StatusCode::from_u16(status).map_err(|e| context(e.into()))?),
+            None => None,
+        };
+        requests.push(CollectionRequest { name: req.name, cmd, status });
+    }
+    args.requests = requests;
+    Ok(())
+}
+
+fn read_collection(path: &Path) -> Result<CollectionFile> {
+    let content = fs::read_to_string(path).map_err(|e| anyhow!("Failed to read {}: {}", path.display(), e))?;
+    parse_collection(path, &content)
+}
+
+/// .yaml 和 .yml 为YAML格式, 其他为TOML格式
+fn parse_collection(path: &Path, content: &str) -> Result<CollectionFile> {
+    let yaml = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("yaml") || ext.eq_ignore_ascii_case("yml"));
+    let file = match yaml {
+        true => serde_yaml::from_str(content).map_err(|e| e.to_string()),
+        false => toml::from_str(content).map_err(|e| e.to_string()),
+    };
+    file.map_err(|e| anyhow!("Failed to parse {}: {}", path.display(), e))
+}
+
+/// 发送一个请求, 把响应头和body都写到out中, 失败时才打印出来
+async fn fetch_one(ctx: &Context, cmd: &SubCommand, out: &mut Vec<u8>) -> Result<StatusCode> {
+    let (req, items) = build_request(ctx, cmd).await?;
+    let resp = match ctx.send(req, items, out).await? {
+        Some(resp) => resp,
+        None => return Err(anyhow!("--offline does not send the request")),
+    };
+    let status = resp.status();
+    print_head(out, &resp)?;
+    print_resp_body(ctx, out, resp).await?;
+    Ok(status)
+}
+
+/// run 子命令: 按顺序发送集合中的请求, 每个请求打印一行结果, 失败的请求打印完整的响应
+/// 有请求失败时返回 RequestsFailed, 这样可以在CI中使用
+pub(crate) async fn run(ctx: &Arc<Context>, args: &Run, out: &mut impl Output) -> Result<()> {
+    let width = args.requests.iter().map(|req| req.name.len()).max().unwrap_or(0);
+    let mut failed = 0;
+    for req in args.requests.iter() {
+        let (method, url) = match &req.cmd {
+            SubCommand::Request(cmd) => (cmd.method.as_str(), cmd.url.as_str()),
+            _ => continue,
+        };
+        let started = Instant::now();
+        let mut output = Vec::new();
+        let result = fetch_one(ctx, &req.cmd, &mut output).await;
+        let elapsed = format!("{}ms", started.elapsed().as_millis()).dimmed();
+        let passed = matches!(&result, Ok(status) if req.passed(*status));
+        let mark = if passed { "PASS".green() } else { "FAIL".red().bold() };
+        let outcome = match &result {
+            Ok(status) => match req.status {
+                Some(expected) if expected != *status => {
+                    format!("{} (expected {})", styled_status(*status), expected.as_u16())
+                }
+                None if !passed => format!("{} (expected 2xx)", styled_status(*status)),
+                _ => styled_status(*status).to_string(),
+            },
+            Err(e) => format!("{}", e).red().to_string(),
+        };
+        writeln!(out, "{} {:width$} {} {} {} {}", mark, req.name, method, url, outcome, elapsed, width = width)?;
+        if !passed {
+            failed += 1;
+            out.write_all(&output)?;
+            writeln!(out)?;
+        }
+    }
+    ctx.save_records()?;
+    let summary = format!("{} passed, {} failed", args.requests.len() - failed, failed);
+    writeln!(out, "{}", if failed > 0 { summary.red() } else { summary.green() })?;
+    match failed {
+        0 => Ok(()),
+        _ => Err(RequestsFailed(failed).into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn collections_are_read_from_yaml_and_toml() {
+        let yaml = "vars:\n  id: 42\nrequests:\n  - name: user\n    url: \"{base}/users/{id}\"\n    status: 404\n";
+        let toml = "[vars]\nid = 42\n\n[[requests]]\nname = \"user\"\nurl = \"{base}/users/{id}\"\nstatus = 404\n";
+        for (name, content) in [("smoke.yaml", yaml), ("smoke.toml", toml)].iter() {
+            let file = parse_collection(Path::new(name), content).unwrap();
+            assert_eq!(file.vars["id"], 42);
+            assert_eq!(file.requests[0].method, "GET");
+            assert_eq!(file.requests[0].url, "{base}/users/{id}");
+            assert_eq!(file.requests[0].status, Some(404));
+        }
+    }
+}
@@ -0,0 +1,118 @@
+use std::{io::Write, str::FromStr};
+
+use anyhow::{anyhow, Result};
+use reqwest::header::{self, HeaderValue};
+
+use crate::cli::*;
+
+/// 小于这个大小的body压缩之后省不了多少, 甚至更大, 不压缩
+pub(crate) const MIN_COMPRESS_SIZE: usize = 1024;
+
+/// 这些扩展名的文件本身已经是压缩过的格式, 再压缩没有意义
+const COMPRESSED_EXTENSIONS: &[&str] = &[
+    "gz", "tgz", "zst", "br", "xz", "bz2", "zip", "7z", "rar", "jpg", "jpeg", "png", "gif", "webp",
+    "mp3", "mp4", "webm",
+];
+
+/// --compress 的取值, 请求body的压缩格式
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum BodyCompression {
+    Gzip,
+    Zstd,
+}
+
+impl BodyCompression {
+    /// Content-Encoding 中的名字
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            Self::Gzip => "gzip",
+            Self::Zstd => "zstd",
+        }
+    }
+
+    pub(crate) fn compress(&self, body: &[u8]) -> Result<Vec<u8>> {
+        match self {
+            Self::Gzip => {
+                let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(body)?;
+                Ok(encoder.finish()?)
+            }
+            Self::Zstd => Ok(zstd::stream::encode_all(body, 0)?),
+        }
+    }
+}
+
+impl FromStr for BodyCompression {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "gzip" => Ok(Self::Gzip),
+            "zstd" => Ok(Self::Zstd),
+            _ => Err(anyhow!("Unknown compression {}, expected gzip or zstd", s)),
+        }
+    }
+}
+
+/// 压缩序列化好的body并设置Content-Encoding, Content-Length由发送时按照压缩后的body计算
+/// 以下情况不压缩: body是stream, 小于 MIN_COMPRESS_SIZE, 已经有Content-Encoding,
+/// 或者 @path 的文件已经是压缩过的格式
+pub(crate) fn compress_body(
+    req: &mut reqwest::Request,
+    items: &[RequestItem],
+    compression: BodyCompression,
+) -> Result<()> {
+    if req.headers().contains_key(header::CONTENT_ENCODING) {
+        return Ok(());
+    }
+    let compressed_file = items.iter().any(|item| match item {
+        RequestItem::RawBodyFile(path) => path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| COMPRESSED_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str())),
+        _ => false,
+    });
+    let body = match req.body().and_then(|body| body.as_bytes()) {
+        Some(body) if body.len() >= MIN_COMPRESS_SIZE && !compressed_file => body,
+        _ => return Ok(()),
+    };
+    let compressed = compression.compress(body)?;
+    req.headers_mut().insert(header::CONTENT_ENCODING, HeaderValue::from_static(compression.name()));
+    req.headers_mut().remove(header::CONTENT_LENGTH);
+    *req.body_mut() = Some(compressed.into());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::decode_body;
+
+    fn post(body: Vec<u8>) -> reqwest::Request {
+        let mut req = reqwest::Request::new(reqwest::Method::POST, "http://localhost/".parse().unwrap());
+        *req.body_mut() = Some(body.into());
+        req
+    }
+
+    #[test]
+    fn large_bodies_are_compressed_and_small_ones_left_alone() {
+        let body = br#"{"message": "hello"}"#.repeat(100);
+        for compression in [BodyCompression::Gzip, BodyCompression::Zstd].iter() {
+            let mut req = post(body.clone());
+            compress_body(&mut req, &[], *compression).unwrap();
+            assert_eq!(req.headers()[header::CONTENT_ENCODING], compression.name());
+            let sent = req.body().and_then(|b| b.as_bytes()).unwrap();
+            assert!(sent.len() < body.len());
+            assert_eq!(decode_body(compression.name(), sent).unwrap(), body);
+        }
+
+        let mut small = post(b"{}".to_vec());
+        compress_body(&mut small, &[], BodyCompression::Gzip).unwrap();
+        assert!(!small.headers().contains_key(header::CONTENT_ENCODING));
+
+        let mut archive = post(body);
+        let items = ["@backup.tar.gz".parse().unwrap()];
+        compress_body(&mut archive, &items, BodyCompression::Gzip).unwrap();
+        assert!(!archive.headers().contains_key(header::CONTENT_ENCODING));
+    }
+}
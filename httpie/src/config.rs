@@ -0,0 +1,123 @@
+use std::{collections::BTreeMap, fs, io, path::PathBuf};
+
+use anyhow::{anyhow, Result};
+use reqwest::header::{HeaderName, HeaderValue};
+use serde::Deserialize;
+
+use crate::{cli::*, output::*};
+
+/// 配置文件 <配置目录>/httpie-rs/config.toml 的内容, 所有的设置都是可选的
+/// 只作为默认值, 命令行中指定的参数优先
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct ConfigFile {
+    /// [default_headers], 每个请求都发送的请求头, 会覆盖内置的 User-Agent
+    /// 优先级: 命令行中的请求头 > 会话中的请求头 > 这里的请求头 > 内置的默认请求头,
+    /// 命令行中的 Header: 可以去掉其中的任何一个; 以前的 [headers] 仍然可以使用
+    #[serde(alias = "default_headers", alias = "headers")]
+    pub(crate) default_headers: BTreeMap<String, String>,
+    pub(crate) check_status: bool,
+    pub(crate) follow: bool,
+    pub(crate) timeout: Option<f64>,
+    pub(crate) proxy: Option<String>,
+    pub(crate) style: Option<String>,
+    pub(crate) default_scheme: Option<String>,
+    pub(crate) sort_keys: bool,
+}
+
+impl ConfigFile {
+    pub(crate) fn path() -> Result<PathBuf> {
+        Ok(config_dir()?.join("config.toml"))
+    }
+
+    /// 读取配置文件并合并到命令行参数中, 文件不存在时什么都不做
+    pub fn load(opt: &mut App) -> Result<()> {
+        // 不发送请求的子命令不需要配置, config 和 completions 在配置文件有错误时也要能用
+        // 其他子命令都使用配置, 包括没有URL参数的 get --url-file, run 和 replay
+        if let SubCommand::Config(_) | SubCommand::Completions(_) | SubCommand::History(_) | SubCommand::Serve(_) =
+            opt.subcommand
+        {
+            return Ok(());
+        }
+        let path = match Self::path() {
+            Ok(path) => path,
+            Err(_) => return Ok(()),
+        };
+        let content = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(anyhow!("Failed to read {}: {}", path.display(), e)),
+        };
+        // toml的错误信息中有行号和列号
+        let config: Self = toml::from_str(&content)
+            .map_err(|e| anyhow!("Invalid config file {}: {}", path.display(), e))?;
+        config
+            .apply(opt)
+            .map_err(|e| e.context(format!("Invalid config file {}", path.display())))
+    }
+
+    pub(crate) fn apply(self, opt: &mut App) -> Result<()> {
+        for (name, value) in self.default_headers.iter() {
+            let name = HeaderName::from_bytes(name.as_bytes())
+                .map_err(|e| anyhow!("Invalid header name {}: {}", name, e))?;
+            let value = HeaderValue::from_str(value)
+                .map_err(|e| anyhow!("Invalid value for header {}: {}", name, e))?;
+            opt.config_headers.insert(name, value);
+        }
+        opt.check_status |= self.check_status;
+        opt.sort_keys |= self.sort_keys;
+        // --no-follow 仍然会覆盖它
+        opt.follow |= self.follow;
+        if opt.timeout.is_none() {
+            if let Some(timeout) = self.timeout {
+                opt.timeout = Some(parse_seconds(&timeout.to_string())?);
+            }
+        }
+        // --no-proxy 时不使用任何代理
+        if opt.proxy.is_empty() && !opt.no_proxy {
+            opt.proxy = self.proxy.as_deref().map(str::parse).transpose()?.into_iter().collect();
+        }
+        if let Some(style) = self.style.filter(|_| opt.style.is_none()) {
+            if !STYLES.contains(&style.as_str()) {
+                let expected = STYLES.join(", ");
+                return Err(anyhow!("Unknown style {}, expected one of {}", style, expected));
+            }
+            opt.style = Some(style);
+        }
+        if let Some(scheme) = self.default_scheme.filter(|_| opt.default_scheme.is_none()) {
+            if scheme != "http" && scheme != "https" {
+                return Err(anyhow!("Invalid default-scheme {}, expected http or https", scheme));
+            }
+            opt.default_scheme = Some(scheme);
+        }
+        Ok(())
+    }
+}
+
+pub fn run(args: &Config) -> Result<()> {
+    match args.action {
+        ConfigAction::Path => println!("{}", ConfigFile::path()?.display()),
+    }
+    Ok(())
+}
+
+/// 配置文件所在的目录, Linux下为 ~/.config/httpie-rs
+pub(crate) fn config_dir() -> Result<PathBuf> {
+    dirs::config_dir()
+        .map(|dir| dir.join("httpie-rs"))
+        .ok_or_else(|| anyhow!("Failed to find the config directory"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_headers_table_accepts_the_old_name() {
+        for table in ["default_headers", "default-headers", "headers"].iter() {
+            let content = format!("[{}]\nX-Tenant = \"acme\"\n", table);
+            let config: ConfigFile = toml::from_str(&content).unwrap();
+            assert_eq!(config.default_headers["X-Tenant"], "acme");
+        }
+    }
+}
@@ -0,0 +1,193 @@
+use std::path::Path;
+
+use reqwest::{
+    header::{self, HeaderName},
+    Method,
+};
+
+use crate::{cli::*, request::*};
+
+/// --curl-redact 时替换掉的请求头, 名字中包含 token, secret 或者 api-key 的也会替换
+pub(crate) const SECRET_HEADERS: &[HeaderName] =
+    &[header::AUTHORIZATION, header::PROXY_AUTHORIZATION, header::COOKIE];
+
+pub(crate) const REDACTED: &str = "<redacted>";
+
+/// 生成和请求等价的curl命令, 每个选项一行
+pub(crate) fn curl_command(ctx: &Context, req: &reqwest::Request, items: &[RequestItem]) -> String {
+    let mut args = vec!["curl".to_string()];
+    let body = req.body().map(|body| body.as_bytes());
+    let multipart = items.iter().any(|item| matches!(item, RequestItem::Upload(..)));
+    let raw_file = items.iter().find_map(|item| match item {
+        RequestItem::RawBodyFile(path) => Some(path),
+        _ => None,
+    });
+
+    // 有body时curl默认使用POST, 其他时候默认使用GET
+    let has_body = multipart || matches!(body, Some(Some(bytes)) if !bytes.is_empty());
+    match req.method() {
+        // -X HEAD 会让curl等待永远不会到来的body
+        &Method::HEAD => args.push("--head".to_string()),
+        &Method::GET if !has_body => {}
+        &Method::POST if has_body => {}
+        method => args.push(format!("-X {}", quote(method.as_str().as_bytes()))),
+    }
+    let mut url = req.url().clone();
+    if ctx.curl_redact && url.password().is_some() {
+        let _ = url.set_password(Some(REDACTED));
+    }
+    args.push(quote(url.as_str().as_bytes()));
+
+    let mut compressed = false;
+    for (name, value) in ctx.request_headers(req).iter() {
+        match *name {
+            // curl会自己设置这些请求头, multipart的boundary也是curl生成的
+            header::HOST | header::CONTENT_LENGTH => continue,
+            header::CONTENT_TYPE if multipart => continue,
+            header::ACCEPT_ENCODING => compressed = value != "identity",
+            _ => {}
+        }
+        let value = if ctx.curl_redact && is_secret(name) {
+            redact(name, value.as_bytes())
+        } else {
+            value.as_bytes().to_vec()
+        };
+        let mut line = format!("{}: ", name).into_bytes();
+        line.extend_from_slice(&value);
+        args.push(format!("-H {}", quote(&line)));
+    }
+    // 发送了Accept-Encoding时让curl解压body, 否则输出的是压缩过的数据
+    if compressed {
+        args.push("--compressed".to_string());
+    }
+
+    if multipart {
+        for item in items {
+            match item {
+                // --form-string 不会把值开头的 @ 和 < 当作文件
+                RequestItem::Body(pair) => {
+                    let field = format!("{}={}", pair.k, pair.v);
+                    args.push(format!("--form-string {}", quote(field.as_bytes())));
+                }
+                // key=@path 以文本字段发送文件的内容
+                RequestItem::BodyFile(key, path) => {
+                    let field = format!("{}=<{}", key, form_path(path));
+                    args.push(format!("-F {}", quote(field.as_bytes())));
+                }
+                RequestItem::Upload(key, path, mime) => {
+                    let mut field = format!("{}=@{}", key, form_path(path));
+                    if let Some(mime) = mime {
+                        field.push_str(&format!(";type={}", mime));
+                    }
+                    args.push(format!("-F {}", quote(field.as_bytes())));
+                }
+                _ => {}
+            }
+        }
+    } else if let (Some(path), Some(_)) = (raw_file, body) {
+        // 文件原样作为body, 让curl自己读取, 这样二进制文件也没有问题
+        let arg = format!("@{}", path.display());
+        args.push(format!("--data-binary {}", quote(arg.as_bytes())));
+    } else if let Some(Some(bytes)) = body {
+        if !bytes.is_empty() {
+            // 以@开头的值会被 --data-binary 当作文件名, --data-raw 不会
+            args.push(format!("--data-raw {}", quote(bytes)));
+        }
+    }
+
+    if ctx.follow {
+        args.push("--location".to_string());
+    }
+    if ctx.http2 {
+        args.push("--http2-prior-knowledge".to_string());
+    }
+    if ctx.insecure {
+        args.push("--insecure".to_string());
+    }
+    if let Some(path) = &ctx.unix_socket {
+        let path = path.display().to_string();
+        args.push(format!("--unix-socket {}", quote(path.as_bytes())));
+    }
+    args.join(" \\\n  ")
+}
+
+pub(crate) fn is_secret(name: &HeaderName) -> bool {
+    let name = name.as_str();
+    SECRET_HEADERS.iter().any(|secret| secret == name)
+        || ["token", "secret", "api-key", "apikey"].iter().any(|word| name.contains(word))
+}
+
+/// 替换请求头的值, Authorization中的认证方式仍然保留, 例如 Bearer <redacted>
+pub(crate) fn redact(name: &HeaderName, value: &[u8]) -> Vec<u8> {
+    let value = String::from_utf8_lossy(value);
+    let auth = name == header::AUTHORIZATION || name == header::PROXY_AUTHORIZATION;
+    match value.split_once(' ') {
+        Some((scheme, _)) if auth => format!("{} {}", scheme, REDACTED).into_bytes(),
+        _ => REDACTED.as_bytes().to_vec(),
+    }
+}
+
+/// -F 中含有 ; , 或者 " 的文件名要加上双引号
+pub(crate) fn form_path(path: &Path) -> String {
+    let path = path.display().to_string();
+    if path.contains([';', ',', '"']) {
+        format!("\"{}\"", path.replace('\\', "\\\\").replace('"', "\\\""))
+    } else {
+        path
+    }
+}
+
+/// 按照shell的规则引用参数, 只包含安全字符时不加引号
+///
+/// 使用单引号, 其中的单引号写成 '\'', 换行和unicode在单引号中可以直接出现
+/// 不是合法的UTF-8时使用 $'...' 并把每个字节写成 \xHH
+pub(crate) fn quote(arg: &[u8]) -> String {
+    let safe = |b: &u8| b.is_ascii_alphanumeric() || b"-_./:=@,+%".contains(b);
+    if !arg.is_empty() && arg.iter().all(safe) {
+        return String::from_utf8_lossy(arg).into_owned();
+    }
+    match std::str::from_utf8(arg) {
+        Ok(s) => format!("'{}'", s.replace('\'', "'\\''")),
+        Err(_) => {
+            let mut quoted = String::from("$'");
+            for &b in arg {
+                match b {
+                    b'\'' | b'\\' => quoted.push_str(&format!("\\{}", b as char)),
+                    0x20..=0x7e => quoted.push(b as char),
+                    _ => quoted.push_str(&format!("\\x{:02x}", b)),
+                }
+            }
+            quoted.push('\'');
+            quoted
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quote_leaves_safe_arguments_alone() {
+        assert_eq!(quote(b"https://example.com/a?b=1"), "'https://example.com/a?b=1'");
+        assert_eq!(quote(b"https://example.com/a"), "https://example.com/a");
+        assert_eq!(quote(b""), "''");
+    }
+
+    #[test]
+    fn quote_escapes_single_quotes_newlines_and_unicode() {
+        assert_eq!(quote(b"it's"), r"'it'\''s'");
+        assert_eq!(quote(b"a\nb"), "'a\nb'");
+        assert_eq!(quote("你好 $HOME".as_bytes()), "'你好 $HOME'");
+        assert_eq!(quote(b"\xff'\n"), r"$'\xff\'\x0a'");
+    }
+
+    #[test]
+    fn authorization_scheme_is_kept_when_redacting() {
+        let bearer = redact(&header::AUTHORIZATION, b"Bearer abc");
+        assert_eq!(bearer, b"Bearer <redacted>");
+        assert_eq!(redact(&header::COOKIE, b"a=1; b=2"), REDACTED.as_bytes());
+        assert!(is_secret(&HeaderName::from_static("x-api-key")));
+        assert!(!is_secret(&header::ACCEPT));
+    }
+}
@@ -0,0 +1,219 @@
+use std::io::Write;
+
+use anyhow::{anyhow, Result};
+use colored::Colorize;
+use reqwest::{header::HeaderMap, Response, Url};
+use serde_json::Value;
+use similar::{ChangeTag, TextDiff};
+
+use crate::{cli::*, error::*, output::*, request::*};
+
+/// 两个响应中不一样的一处, 只在一边出现时另一边为None
+#[derive(Debug, PartialEq)]
+pub(crate) struct Difference {
+    pub(crate) name: String,
+    pub(crate) left: Option<String>,
+    pub(crate) right: Option<String>,
+}
+
+impl Difference {
+    fn print(&self, out: &mut impl Write) -> std::io::Result<()> {
+        if let Some(left) = &self.left {
+            writeln!(out, "{}", format!("- {}: {}", self.name, left).red())?;
+        }
+        if let Some(right) = &self.right {
+            writeln!(out, "{}", format!("+ {}: {}", self.name, right).green())?;
+        }
+        Ok(())
+    }
+}
+
+/// 比较响应头, 同名的多个值用 `, ` 连起来比较
+pub(crate) fn diff_headers(left: &HeaderMap, right: &HeaderMap, ignored: &[String]) -> Vec<Difference> {
+    let value = |headers: &HeaderMap, name| {
+        let values = headers
+            .get_all(name)
+            .iter()
+            .map(|v| String::from_utf8_lossy(v.as_bytes()).into_owned())
+            .collect::<Vec<_>>();
+        Some(values.join(", ")).filter(|_| !values.is_empty())
+    };
+    let mut names = left.keys().collect::<Vec<_>>();
+    names.extend(right.keys().filter(|name| !left.contains_key(*name)));
+    names
+        .into_iter()
+        .filter(|name| !ignored.iter().any(|ignored| ignored == name.as_str()))
+        .filter_map(|name| {
+            let (l, r) = (value(left, name), value(right, name));
+            Some(Difference { name: name.to_string(), left: l, right: r }).filter(|d| d.left != d.right)
+        })
+        .collect()
+}
+
+/// 逐个key比较JSON, 不考虑对象中key的顺序, path是JSON Pointer
+pub(crate) fn diff_json(
+    path: &str,
+    left: Option<&Value>,
+    right: Option<&Value>,
+    ignored: &[String],
+    diffs: &mut Vec<Difference>,
+) {
+    let under = |p: &String| path.strip_prefix(p.as_str()).is_some_and(|rest| rest.starts_with('/'));
+    if ignored.iter().any(|p| path == p || under(p)) {
+        return;
+    }
+    let child = |key: &str| format!("{}/{}", path, key.replace('~', "~0").replace('/', "~1"));
+    match (left, right) {
+        (Some(Value::Object(l)), Some(Value::Object(r))) => {
+            for key in l.keys().chain(r.keys().filter(|key| !l.contains_key(*key))) {
+                diff_json(&child(key), l.get(key), r.get(key), ignored, diffs);
+            }
+        }
+        (Some(Value::Array(l)), Some(Value::Array(r))) => {
+            for i in 0..l.len().max(r.len()) {
+                diff_json(&child(&i.to_string()), l.get(i), r.get(i), ignored, diffs);
+            }
+        }
+        (l, r) if l == r => {}
+        (l, r) => diffs.push(Difference {
+            name: if path.is_empty() { "/".to_string() } else { path.to_string() },
+            left: l.map(Value::to_string),
+            right: r.map(Value::to_string),
+        }),
+    }
+}
+
+/// 读取完body的响应
+struct Fetched {
+    url: Url,
+    status: String,
+    headers: HeaderMap,
+    text: String,
+}
+
+async fn fetch(ctx: &Context, req: reqwest::Request) -> Result<Fetched> {
+    let url = req.url().clone();
+    let resp: Response = ctx.execute(req).await?;
+    let status = format!("{:?} {}", resp.version(), resp.status());
+    let headers = resp.headers().clone();
+    let mime = get_content_type(&resp).ok().flatten();
+    let body = read_body(ctx, resp).await?;
+    let text = decode_text(&body, mime.as_ref(), ctx.charset);
+    Ok(Fetched { url, status, headers, text })
+}
+
+/// diff 子命令: 同时请求两个URL, 依次打印状态码, 响应头和body的差异
+/// 有差异时返回 ResponsesDiffer, 这样可以在CI中使用
+pub(crate) async fn run(ctx: &Context, args: &Diff, out: &mut impl Write) -> Result<()> {
+    let req = ctx.client.request(args.method.clone(), &args.url);
+    let empty = EmptyBody::Omit;
+    let req = with_body(req, &args.items, ctx.stdin_body(), empty, false, &ctx.upload).await?;
+    let left = ctx.build(req, &args.items)?;
+    let mut right = left
+        .try_clone()
+        .ok_or_else(|| anyhow!("diff can't be used with a streamed body, e.g. key@path uploads"))?;
+    *right.url_mut() = args.other.parse()?;
+    let (left, right) = tokio::join!(fetch(ctx, left), fetch(ctx, right));
+    let (left, right) = (left?, right?);
+
+    writeln!(out, "{}", format!("--- {} {}", args.method, left.url).bold())?;
+    writeln!(out, "{}", format!("+++ {} {}", args.method, right.url).bold())?;
+    let mut count = 0;
+    if left.status != right.status {
+        writeln!(out, "{}", "Status:".bold())?;
+        writeln!(out, "{}", format!("- {}", left.status).red())?;
+        writeln!(out, "{}", format!("+ {}", right.status).green())?;
+        count += 1;
+    }
+    let ignored = args.ignore_headers.iter().map(|name| name.to_string()).collect::<Vec<_>>();
+    let headers = diff_headers(&left.headers, &right.headers, &ignored);
+    if !headers.is_empty() {
+        writeln!(out, "{}", "Headers:".bold())?;
+        for diff in headers.iter() {
+            diff.print(out)?;
+        }
+        count += headers.len();
+    }
+
+    let json = (serde_json::from_str::<Value>(&left.text), serde_json::from_str::<Value>(&right.text));
+    if let (Ok(l), Ok(r)) = json {
+        let ignored = args.ignore_paths.iter().map(|path| path.to_pointer()).collect::<Vec<_>>();
+        let mut diffs = Vec::new();
+        diff_json("", Some(&l), Some(&r), &ignored, &mut diffs);
+        if !diffs.is_empty() {
+            writeln!(out, "{}", "Body:".bold())?;
+            for diff in diffs.iter() {
+                diff.print(out)?;
+            }
+            count += diffs.len();
+        }
+    } else if left.text != right.text {
+        writeln!(out, "{}", "Body:".bold())?;
+        let diff = TextDiff::from_lines(&left.text, &right.text);
+        for hunk in diff.unified_diff().context_radius(3).iter_hunks() {
+            writeln!(out, "{}", hunk.header().to_string().cyan())?;
+            for change in hunk.iter_changes() {
+                let line = change.to_string_lossy();
+                let line = line.trim_end_matches('\n');
+                match change.tag() {
+                    ChangeTag::Delete => writeln!(out, "{}", format!("-{}", line).red())?,
+                    ChangeTag::Insert => writeln!(out, "{}", format!("+{}", line).green())?,
+                    ChangeTag::Equal => writeln!(out, " {}", line)?,
+                }
+            }
+        }
+        count += diff.iter_all_changes().filter(|c| c.tag() != ChangeTag::Equal).count();
+    }
+
+    if count == 0 {
+        if ctx.notes() {
+            eprintln!("{}", "No differences".dimmed());
+        }
+        return Ok(());
+    }
+    Err(ResponsesDiffer(count).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn json_diff_ignores_key_order_and_ignored_paths() {
+        let left = json!({"a": 1, "b": {"c": [1, 2], "at": "x"}, "gone": true});
+        let right = json!({"b": {"at": "y", "c": [1, 3, 4]}, "a": 1, "new": null});
+        let mut diffs = Vec::new();
+        diff_json("", Some(&left), Some(&right), &["/b/at".to_string()], &mut diffs);
+        let diff = |name: &str, l: Option<&str>, r: Option<&str>| Difference {
+            name: name.into(),
+            left: l.map(Into::into),
+            right: r.map(Into::into),
+        };
+        assert_eq!(
+            diffs,
+            [
+                diff("/b/c/1", Some("2"), Some("3")),
+                diff("/b/c/2", None, Some("4")),
+                diff("/gone", Some("true"), None),
+                diff("/new", None, Some("null")),
+            ]
+        );
+    }
+
+    #[test]
+    fn header_diff_skips_ignored_headers() {
+        let mut left = HeaderMap::new();
+        left.insert("date", "Mon".parse().unwrap());
+        left.insert("server", "old".parse().unwrap());
+        left.append("vary", "accept".parse().unwrap());
+        let mut right = left.clone();
+        right.insert("date", "Tue".parse().unwrap());
+        right.append("vary", "origin".parse().unwrap());
+        right.remove("server");
+        let diffs = diff_headers(&left, &right, &["date".to_string()]);
+        assert_eq!(diffs.len(), 2);
+        assert_eq!(diffs[0].name, "server");
+        assert_eq!(diffs[1].right.as_deref(), Some("accept, origin"));
+    }
+}
@@ -0,0 +1,294 @@
+use std::sync::Mutex;
+
+use anyhow::{anyhow, Result};
+use openssl::hash::{hash, MessageDigest};
+use reqwest::{
+    header::{self, HeaderValue},
+    Response, StatusCode,
+};
+
+use crate::request::*;
+
+/// Digest认证支持的摘要算法, -sess 时HA1中还包含nonce和cnonce
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Algorithm {
+    Md5,
+    Md5Sess,
+    Sha256,
+    Sha256Sess,
+}
+
+impl Algorithm {
+    fn parse(s: &str) -> Result<Self> {
+        match s.to_ascii_uppercase().as_str() {
+            "MD5" => Ok(Self::Md5),
+            "MD5-SESS" => Ok(Self::Md5Sess),
+            "SHA-256" => Ok(Self::Sha256),
+            "SHA-256-SESS" => Ok(Self::Sha256Sess),
+            _ => Err(anyhow!("Unsupported Digest algorithm {}", s)),
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Md5 => "MD5",
+            Self::Md5Sess => "MD5-sess",
+            Self::Sha256 => "SHA-256",
+            Self::Sha256Sess => "SHA-256-sess",
+        }
+    }
+
+    fn hash(&self, data: &[u8]) -> String {
+        let digest = match self {
+            Self::Md5 | Self::Md5Sess => MessageDigest::md5(),
+            Self::Sha256 | Self::Sha256Sess => MessageDigest::sha256(),
+        };
+        let bytes = hash(digest, data).expect("MD5 and SHA-256 are always available");
+        bytes.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+}
+
+/// 服务器的 WWW-Authenticate: Digest ... 质询
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Challenge {
+    pub(crate) realm: String,
+    pub(crate) nonce: String,
+    pub(crate) opaque: Option<String>,
+    pub(crate) algorithm: Algorithm,
+    /// 服务器提供的qop, 为空时使用RFC 2069的旧格式
+    pub(crate) qop: Vec<String>,
+    /// nonce过期了, 密码本身没有错
+    pub(crate) stale: bool,
+}
+
+/// 解析 key=value, key="quoted, value" 这样用逗号分隔的参数
+fn auth_params(s: &str) -> Vec<(String, String)> {
+    let mut params = Vec::new();
+    let mut rest = s.trim();
+    while !rest.is_empty() {
+        let Some((key, after)) = rest.split_once('=') else {
+            break;
+        };
+        let key = key.trim().trim_start_matches(',').trim().to_ascii_lowercase();
+        let after = after.trim_start();
+        let (value, tail) = match after.strip_prefix('"') {
+            Some(quoted) => {
+                let mut value = String::new();
+                let mut chars = quoted.char_indices();
+                let mut end = quoted.len();
+                while let Some((i, c)) = chars.next() {
+                    match c {
+                        '\\' => value.extend(chars.next().map(|(_, c)| c)),
+                        '"' => {
+                            end = i + 1;
+                            break;
+                        }
+                        c => value.push(c),
+                    }
+                }
+                (value, &quoted[end..])
+            }
+            None => {
+                let end = after.find(',').unwrap_or(after.len());
+                (after[..end].trim().to_string(), &after[end..])
+            }
+        };
+        params.push((key, value));
+        rest = tail.trim_start().trim_start_matches(',').trim_start();
+    }
+    params
+}
+
+impl Challenge {
+    /// 从响应中找到Digest质询, 没有时返回None, 缺少参数时报错
+    pub(crate) fn from_response(resp: &Response) -> Result<Option<Self>> {
+        let challenge = resp
+            .headers()
+            .get_all(header::WWW_AUTHENTICATE)
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .find_map(|v| {
+                let (scheme, params) = v.trim().split_once(' ')?;
+                Some(params).filter(|_| scheme.eq_ignore_ascii_case("digest"))
+            });
+        challenge.map(Self::parse).transpose()
+    }
+
+    pub(crate) fn parse(params: &str) -> Result<Self> {
+        let params = auth_params(params);
+        let get = |name: &str| params.iter().find(|(k, _)| k == name).map(|(_, v)| v.clone());
+        let required = |name: &str| {
+            get(name).ok_or_else(|| anyhow!("Invalid Digest challenge from the server: missing {}", name))
+        };
+        Ok(Self {
+            realm: required("realm")?,
+            nonce: required("nonce")?,
+            opaque: get("opaque"),
+            algorithm: get("algorithm").map(|a| Algorithm::parse(&a)).transpose()?.unwrap_or(Algorithm::Md5),
+            qop: get("qop")
+                .map(|qop| qop.split(',').map(|q| q.trim().to_ascii_lowercase()).collect())
+                .unwrap_or_default(),
+            stale: get("stale").is_some_and(|s| s.eq_ignore_ascii_case("true")),
+        })
+    }
+
+    /// 计算Authorization请求头, 优先使用qop=auth, 只提供auth-int时对body做摘要
+    pub(crate) fn authorize(
+        &self,
+        user: &str,
+        password: &str,
+        req: &reqwest::Request,
+        nc: u32,
+        cnonce: &str,
+    ) -> Result<HeaderValue> {
+        let qop = ["auth", "auth-int"].iter().copied().find(|q| self.qop.iter().any(|offered| offered == q));
+        if !self.qop.is_empty() && qop.is_none() {
+            return Err(anyhow!("Unsupported Digest qop {}", self.qop.join(", ")));
+        }
+        let h = |s: &str| self.algorithm.hash(s.as_bytes());
+        let uri = request_target(req.url());
+        let mut ha1 = h(&format!("{}:{}:{}", user, self.realm, password));
+        if matches!(self.algorithm, Algorithm::Md5Sess | Algorithm::Sha256Sess) {
+            ha1 = h(&format!("{}:{}:{}", ha1, self.nonce, cnonce));
+        }
+        let ha2 = match qop {
+            Some("auth-int") => {
+                let body = match req.body().map(|body| body.as_bytes()) {
+                    Some(Some(bytes)) => bytes,
+                    Some(None) => {
+                        return Err(anyhow!("Digest qop=auth-int can't be used with a streamed body"))
+                    }
+                    None => &[],
+                };
+                h(&format!("{}:{}:{}", req.method(), uri, self.algorithm.hash(body)))
+            }
+            _ => h(&format!("{}:{}", req.method(), uri)),
+        };
+        let nc = format!("{:08x}", nc);
+        let response = match qop {
+            Some(qop) => h(&format!("{}:{}:{}:{}:{}:{}", ha1, self.nonce, nc, cnonce, qop, ha2)),
+            None => h(&format!("{}:{}:{}", ha1, self.nonce, ha2)),
+        };
+
+        let quote = |s: &str| format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""));
+        let mut value = format!(
+            "Digest username={}, realm={}, nonce={}, uri={}, algorithm={}, response={}",
+            quote(user),
+            quote(&self.realm),
+            quote(&self.nonce),
+            quote(&uri),
+            self.algorithm.name(),
+            quote(&response)
+        );
+        if let Some(qop) = qop {
+            value.push_str(&format!(", qop={}, nc={}, cnonce={}", qop, nc, quote(cnonce)));
+        }
+        if let Some(opaque) = &self.opaque {
+            value.push_str(&format!(", opaque={}", quote(opaque)));
+        }
+        Ok(HeaderValue::from_str(&value)?)
+    }
+}
+
+/// --auth-type digest: 第一个请求收到401后按照质询重新发送
+/// 之后的请求直接使用同一个nonce, nc逐个增加
+pub(crate) struct DigestAuth {
+    pub(crate) user: String,
+    pub(crate) password: String,
+    /// 上一次的质询和已经使用的nc
+    pub(crate) state: Mutex<Option<(Challenge, u32)>>,
+}
+
+impl DigestAuth {
+    pub(crate) fn new(user: String, password: String) -> Self {
+        Self { user, password, state: Mutex::new(None) }
+    }
+
+    fn authorize(&self, req: &mut reqwest::Request) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        let Some((challenge, nc)) = state.as_mut() else {
+            return Ok(());
+        };
+        *nc += 1;
+        let header = challenge.authorize(&self.user, &self.password, req, *nc, &cnonce())?;
+        req.headers_mut().insert(header::AUTHORIZATION, header);
+        Ok(())
+    }
+
+    pub(crate) async fn execute(&self, ctx: &Context, mut req: reqwest::Request) -> Result<Response> {
+        // 命令行中的 Authorization:xxx 优先
+        let explicit = req.headers().contains_key(header::AUTHORIZATION);
+        if !explicit {
+            self.authorize(&mut req)?;
+        }
+        let retry = req.try_clone().filter(|_| !explicit);
+        let resp = ctx.execute_with_retries(req).await?;
+        let (Some(mut retry), StatusCode::UNAUTHORIZED) = (retry, resp.status()) else {
+            return Ok(resp);
+        };
+        let Some(challenge) = Challenge::from_response(&resp)? else {
+            return Ok(resp);
+        };
+        {
+            let mut state = self.state.lock().unwrap();
+            // 同一个nonce还被拒绝, 说明用户名或密码错误
+            let same = state.as_ref().is_some_and(|(old, _)| old.nonce == challenge.nonce);
+            if same && !challenge.stale {
+                return Ok(resp);
+            }
+            *state = Some((challenge, 0));
+        }
+        self.authorize(&mut retry)?;
+        ctx.execute_with_retries(retry).await
+    }
+}
+
+/// 客户端随机数
+fn cnonce() -> String {
+    let mut bytes = [0; 16];
+    openssl::rand::rand_bytes(&mut bytes).expect("OpenSSL random generator failed");
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// RFC 7616 3.9.1 中的例子
+    const CHALLENGE: &str = r#"realm="http-auth@example.org", qop="auth, auth-int", algorithm=SHA-256, nonce="7ypf/xlj9XXwfDPEoM4URrv/xwf94BcCAzFZH4GiTo0v", opaque="FQhe/qaU925kfnzjCev0ciny7QMkPqMAFRtzCUYo5tdS""#;
+    const CNONCE: &str = "f2/wE4q74E6zIJEtWaHKaf5wv/H5QzzpXusqGemxURZJ";
+
+    fn response(header: &HeaderValue) -> String {
+        let params = auth_params(header.to_str().unwrap().strip_prefix("Digest ").unwrap());
+        params.into_iter().find(|(k, _)| k == "response").unwrap().1
+    }
+
+    #[test]
+    fn responses_match_the_rfc_examples() {
+        let req = reqwest::Client::new().get("http://www.example.org/dir/index.html").build().unwrap();
+        let mut challenge = Challenge::parse(CHALLENGE).unwrap();
+        assert_eq!(challenge.qop, ["auth", "auth-int"]);
+        let header = challenge.authorize("Mufasa", "Circle of Life", &req, 1, CNONCE).unwrap();
+        assert_eq!(response(&header), "753927fa0e85d155564e2e272a28d1802ca10daf4496794697cf8db5856cb6c1");
+        assert!(header.to_str().unwrap().contains(", qop=auth, nc=00000001, cnonce="));
+
+        challenge.algorithm = Algorithm::Md5;
+        let header = challenge.authorize("Mufasa", "Circle of Life", &req, 1, CNONCE).unwrap();
+        assert_eq!(response(&header), "8ca523f5e9506fed4657c9700eebdbec");
+    }
+
+    #[test]
+    fn auth_int_hashes_the_body_and_missing_parameters_are_named() {
+        let req = reqwest::Client::new().post("http://localhost/x").body("{}").build().unwrap();
+        let challenge = Challenge::parse(r#"realm="r", nonce="n", qop="auth-int""#).unwrap();
+        let header = challenge.authorize("u", "p", &req, 2, "c").unwrap();
+        let h = |s: &str| Algorithm::Md5.hash(s.as_bytes());
+        let ha2 = h(&format!("POST:/x:{}", h("{}")));
+        let expected = h(&format!("{}:n:00000002:c:auth-int:{}", h("u:r:p"), ha2));
+        assert_eq!(response(&header), expected);
+
+        let err = Challenge::parse(r#"realm="r", qop="auth""#).unwrap_err();
+        assert_eq!(err.to_string(), "Invalid Digest challenge from the server: missing nonce");
+        assert!(Challenge::parse(r#"realm="r", nonce="n", algorithm=SHA-512"#).is_err());
+    }
+}
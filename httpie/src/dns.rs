@@ -0,0 +1,117 @@
+use std::{
+    error::Error,
+    net::{IpAddr, SocketAddr},
+};
+
+use anyhow::{anyhow, Result};
+use hyper::client::connect::dns::Name;
+use reqwest::{
+    dns::{Addrs, Resolve, Resolving},
+    Url,
+};
+
+use crate::cli::*;
+
+/// -4 / -6 限定的地址族
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum IpFamily {
+    V4,
+    V6,
+}
+
+impl IpFamily {
+    pub(crate) fn from_args(opt: &App) -> Result<Option<Self>> {
+        let family = match (opt.ipv4, opt.ipv6) {
+            (true, _) => Self::V4,
+            (_, true) => Self::V6,
+            _ => return Ok(None),
+        };
+        if let Some(ip) = opt.interface.filter(|ip| !family.matches(ip)) {
+            return Err(anyhow!("--interface {} can't be used with {}", ip, family.flag()));
+        }
+        Ok(Some(family))
+    }
+
+    pub(crate) fn matches(&self, ip: &IpAddr) -> bool {
+        match self {
+            Self::V4 => ip.is_ipv4(),
+            Self::V6 => ip.is_ipv6(),
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Self::V4 => "IPv4",
+            Self::V6 => "IPv6",
+        }
+    }
+
+    fn flag(&self) -> &'static str {
+        match self {
+            Self::V4 => "-4",
+            Self::V6 => "-6",
+        }
+    }
+
+    /// 只保留这个地址族的地址, 一个都没有时报错, 不会退回到另一个地址族
+    pub(crate) fn filter(
+        &self,
+        host: &str,
+        addrs: impl Iterator<Item = SocketAddr>,
+    ) -> Result<Vec<SocketAddr>> {
+        let addrs = addrs.filter(|addr| self.matches(&addr.ip())).collect::<Vec<_>>();
+        if addrs.is_empty() {
+            return Err(anyhow!("no {} address for {}", self.name(), host));
+        }
+        Ok(addrs)
+    }
+
+    /// URL中直接写的IP地址不经过DNS查询, 单独检查
+    pub(crate) fn check_url(&self, url: &Url) -> Result<()> {
+        let host = url.host_str().unwrap_or_default();
+        let Ok(ip) = host.trim_start_matches('[').trim_end_matches(']').parse::<IpAddr>() else {
+            return Ok(());
+        };
+        match self.matches(&ip) {
+            true => Ok(()),
+            false => Err(anyhow!("{} is not an {} address ({})", ip, self.name(), self.flag())),
+        }
+    }
+}
+
+/// -4 / -6 时reqwest使用的DNS解析器, 用系统的getaddrinfo查询后过滤
+pub(crate) struct FamilyResolver(pub(crate) IpFamily);
+
+impl Resolve for FamilyResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let family = self.0;
+        Box::pin(async move {
+            let host = name.as_str();
+            let addrs = tokio::net::lookup_host((host, 0)).await?;
+            let addrs = family
+                .filter(host, addrs)
+                .map_err(|e| Box::<dyn Error + Send + Sync>::from(e.to_string()))?;
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn addresses_of_the_other_family_are_dropped_without_fallback() {
+        let addrs = ["127.0.0.1:80", "[::1]:80"].iter().map(|a| a.parse().unwrap()).collect::<Vec<_>>();
+        let v6 = IpFamily::V6.filter("localhost", addrs.iter().copied()).unwrap();
+        assert_eq!(v6, [addrs[1]]);
+        let err = IpFamily::V6.filter("example.com", addrs[..1].iter().copied()).unwrap_err();
+        assert_eq!(err.to_string(), "no IPv6 address for example.com");
+
+        let url = |s: &str| s.parse::<Url>().unwrap();
+        assert!(IpFamily::V4.check_url(&url("http://127.0.0.1/")).is_ok());
+        assert!(IpFamily::V4.check_url(&url("http://example.com/")).is_ok());
+        let err = IpFamily::V4.check_url(&url("http://[::1]:8080/")).unwrap_err();
+        assert_eq!(err.to_string(), "::1 is not an IPv4 address (-4)");
+    }
+}
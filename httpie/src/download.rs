@@ -0,0 +1,338 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+use anyhow::{anyhow, Result};
+use colored::Colorize;
+use futures_util::StreamExt;
+use indicatif::{HumanBytes, ProgressBar, ProgressStyle};
+use reqwest::{header, Response, StatusCode};
+use tokio::io::AsyncWriteExt;
+
+use crate::{cli::*, output::*, request::*};
+
+/// 继续下载时的状态
+#[derive(Debug)]
+pub(crate) struct Resume {
+    pub(crate) mode: ContinueMode,
+    /// 已经下载的字节数, 也就是Range的起始位置
+    pub(crate) offset: u64,
+}
+
+impl Resume {
+    /// 找到之前下载了一部分的文件, 没有 .part 文件时从 -o 指定的文件继续
+    pub(crate) fn prepare(output: &Path, mode: ContinueMode) -> Result<Self> {
+        let part = partial_path(output);
+        if !part.exists() && output.exists() {
+            fs::rename(output, &part)
+                .map_err(|e| anyhow!("Failed to rename {}: {}", output.display(), e))?;
+        }
+        let offset = fs::metadata(&part).map(|m| m.len()).unwrap_or(0);
+        Ok(Self { mode, offset })
+    }
+}
+
+/// --download 模式: 把body保存到文件中并显示下载进度
+pub(crate) async fn download(ctx: &Context, resp: Response) -> Result<u64> {
+    let path = match &ctx.output {
+        Some(path) => path.clone(),
+        None => unique_path(PathBuf::from(download_filename(&resp))),
+    };
+    // 先写入 .part 文件, 完成后再改名, 这样中断时留下的文件一看就知道是不完整的
+    let part = partial_path(&path);
+    let (offset, total) = match &ctx.resume {
+        Some(resume) => match resumed_range(ctx, resume, &resp)? {
+            Some(range) => range,
+            None => {
+                fs::rename(&part, &path)
+                    .map_err(|e| anyhow!("Failed to rename {}: {}", part.display(), e))?;
+                if ctx.notes() {
+                    eprintln!("{}", format!("{} is already complete", path.display()).green());
+                }
+                return Ok(0);
+            }
+        },
+        None => (0, resp.content_length()),
+    };
+    let file = if offset > 0 {
+        tokio::fs::OpenOptions::new().append(true).open(&part).await
+    } else {
+        tokio::fs::File::create(&part).await
+    };
+    let mut file = file.map_err(|e| anyhow!("Failed to open {}: {}", part.display(), e))?;
+
+    let bar = if ctx.progress() { download_progress(total) } else { ProgressBar::hidden() };
+    bar.set_position(offset);
+    let start = Instant::now();
+    let result = tokio::select! {
+        result = write_stream(ctx, &mut file, resp, &bar) => result,
+        _ = tokio::signal::ctrl_c() => Err(anyhow!("Interrupted")),
+    };
+    bar.finish_and_clear();
+    drop(file);
+    let incomplete = || {
+        format!("Download incomplete, the partial file is kept at {}", part.display())
+    };
+    let written = result.map_err(|e| e.context(incomplete()))?;
+    let size = offset + written;
+    if let Some(total) = total {
+        if size != total {
+            return Err(anyhow!("Got {} bytes, expected {}", size, total).context(incomplete()));
+        }
+    }
+
+    fs::rename(&part, &path)
+        .map_err(|e| anyhow!("Failed to rename {}: {}", part.display(), e))?;
+    let elapsed = start.elapsed().as_secs_f64();
+    let summary = format!(
+        "Done. {} saved to {} in {:.1}s ({}/s)",
+        HumanBytes(size),
+        path.display(),
+        elapsed,
+        HumanBytes((written as f64 / elapsed.max(0.001)) as u64)
+    );
+    if ctx.notes() {
+        eprintln!("{}", summary.green());
+    }
+    Ok(written)
+}
+
+/// 根据继续下载时服务器的响应确定从哪里开始写入, 以及文件的总大小
+/// 返回None表示文件已经下载完了
+pub(crate) fn resumed_range(
+    ctx: &Context,
+    resume: &Resume,
+    resp: &Response,
+) -> Result<Option<(u64, Option<u64>)>> {
+    let content_range = resp
+        .headers()
+        .get(header::CONTENT_RANGE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(parse_content_range);
+    match resp.status() {
+        StatusCode::RANGE_NOT_SATISFIABLE => match content_range {
+            // 416 的 Content-Range 格式为 bytes */<总大小>
+            Some((_, Some(total))) if total != resume.offset => Err(anyhow!(
+                "The local file has {} bytes, but the remote file only has {} bytes",
+                resume.offset,
+                total
+            )),
+            _ => Ok(None),
+        },
+        StatusCode::PARTIAL_CONTENT if resume.offset > 0 => match content_range {
+            Some((Some(start), total)) if start == resume.offset => Ok(Some((start, total))),
+            _ => Err(anyhow!(
+                "The server returned an unexpected Content-Range, expected it to start at {}",
+                resume.offset
+            )),
+        },
+        _ if resume.offset > 0 && resume.mode == ContinueMode::Strict => Err(anyhow!(
+            "The server doesn't support resuming downloads (got {} instead of 206)",
+            resp.status()
+        )),
+        _ if resume.offset > 0 => {
+            let warning = "warning: the server doesn't support resuming downloads, restarting";
+            if ctx.warnings() {
+                eprintln!("{}", warning.yellow());
+            }
+            Ok(Some((0, resp.content_length())))
+        }
+        _ => Ok(Some((0, resp.content_length()))),
+    }
+}
+
+/// 解析 Content-Range: bytes <起始>-<结束>/<总大小>, 返回起始位置和总大小, 不知道时为None
+pub(crate) fn parse_content_range(value: &str) -> Option<(Option<u64>, Option<u64>)> {
+    let (range, total) = value.strip_prefix("bytes ")?.split_once('/')?;
+    let start = match range.trim() {
+        "*" => None,
+        range => Some(range.split_once('-')?.0.parse().ok()?),
+    };
+    let total = match total.trim() {
+        "*" => None,
+        total => Some(total.parse().ok()?),
+    };
+    Some((start, total))
+}
+
+/// 下载过程中使用的临时文件
+pub(crate) fn partial_path(path: &Path) -> PathBuf {
+    let mut part = path.as_os_str().to_os_string();
+    part.push(".part");
+    PathBuf::from(part)
+}
+
+pub(crate) async fn write_stream(
+    ctx: &Context,
+    file: &mut tokio::fs::File,
+    resp: Response,
+    bar: &ProgressBar,
+) -> Result<u64> {
+    let mut size = 0;
+    let mut stream = resp.bytes_stream();
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk?;
+        ctx.record_body(&chunk);
+        file.write_all(&chunk).await?;
+        size += chunk.len() as u64;
+        bar.inc(chunk.len() as u64);
+    }
+    file.flush().await?;
+    Ok(size)
+}
+
+/// 下载进度条, 知道Content-Length时显示百分比和剩余时间
+pub(crate) fn download_progress(total: Option<u64>) -> ProgressBar {
+    let (bar, template) = match total {
+        Some(total) => (
+            ProgressBar::new(total),
+            "{bar:30} {bytes}/{total_bytes} {percent}% {bytes_per_sec} ETA {eta}",
+        ),
+        None => (ProgressBar::new_spinner(), "{spinner} {bytes} {bytes_per_sec} {elapsed}"),
+    };
+    if let Ok(style) = ProgressStyle::with_template(template) {
+        bar.set_style(style);
+    }
+    bar
+}
+
+/// 下载时使用的文件名: 优先使用Content-Disposition中的, 其次是URL路径的最后一段
+pub(crate) fn download_filename(resp: &Response) -> String {
+    let name = resp
+        .headers()
+        .get(header::CONTENT_DISPOSITION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(disposition_filename)
+        .or_else(|| {
+            resp.url()
+                .path_segments()
+                .and_then(|mut segments| segments.next_back())
+                .map(|name| name.to_string())
+        })
+        .unwrap_or_default();
+    // 不能让服务器通过文件名把文件写到其他目录中
+    let name = name.rsplit(['/', '\\']).next().unwrap_or_default();
+    let name = name.trim().trim_start_matches('.');
+    if !name.is_empty() {
+        return name.to_string();
+    }
+
+    let extension = resp
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.split(';').next())
+        .and_then(|v| mime_guess::get_mime_extensions_str(v.trim()))
+        .and_then(|extensions| extensions.first());
+    match extension {
+        Some(extension) => format!("index.{}", extension),
+        None => "index".to_string(),
+    }
+}
+
+/// 从Content-Disposition中取出文件名, filename* 优先于 filename
+pub(crate) fn disposition_filename(value: &str) -> Option<String> {
+    let mut filename = None;
+    for param in value.split(';').map(|v| v.trim()) {
+        let (key, value) = match param.split_once('=') {
+            Some((key, value)) => (key.trim().to_ascii_lowercase(), value.trim()),
+            None => continue,
+        };
+        match key.as_str() {
+            // 格式是 charset'language'百分号编码的文件名, 例如 UTF-8''%e2%82%ac.txt
+            "filename*" => {
+                if let Some((_, encoded)) = value.rsplit_once('\'') {
+                    return Some(percent_decode(encoded));
+                }
+            }
+            "filename" => filename = Some(value.trim_matches('"').to_string()),
+            _ => {}
+        }
+    }
+    filename
+}
+
+pub(crate) fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes.get(i + 1..i + 3).and_then(|hex| std::str::from_utf8(hex).ok());
+        match hex.map(|hex| u8::from_str_radix(hex, 16)) {
+            Some(Ok(b)) if bytes[i] == b'%' => {
+                decoded.push(b);
+                i += 3;
+            }
+            _ => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// 文件已经存在时在文件名后面加上 -1, -2, ... 直到不冲突为止
+pub(crate) fn unique_path(path: PathBuf) -> PathBuf {
+    if !path.exists() {
+        return path;
+    }
+    (1..)
+        .map(|i| PathBuf::from(format!("{}-{}", path.display(), i)))
+        .find(|path| !path.exists())
+        .unwrap()
+}
+
+/// 把body原样写入 -o 指定的文件, 不做格式化和着色
+/// 写入失败时删除只写了一部分的文件
+pub(crate) async fn save_body(ctx: &Context, path: &Path, resp: Response) -> Result<u64> {
+    let mut file = tokio::fs::File::create(path)
+        .await
+        .map_err(|e| anyhow!("Failed to create {}: {}", path.display(), e))?;
+    match write_body(ctx, &mut file, resp).await {
+        Ok(size) => {
+            let note = format!("Saved {} bytes to {}", size, path.display());
+            if ctx.notes() {
+                eprintln!("{}", note.dimmed());
+            }
+            Ok(size)
+        }
+        Err(e) => {
+            drop(file);
+            // 只删除普通文件, 不要删掉 /dev/stdout 之类的设备文件
+            if fs::metadata(path).is_ok_and(|m| m.is_file()) {
+                let _ = tokio::fs::remove_file(path).await;
+            }
+            Err(e.context(format!(
+                "Failed to save the response body to {}, removed the partial file",
+                path.display()
+            )))
+        }
+    }
+}
+
+pub(crate) async fn write_body(ctx: &Context, file: &mut tokio::fs::File, mut resp: Response) -> Result<u64> {
+    let mut size = 0;
+    match content_encoding(&resp) {
+        // 解压需要完整的body
+        Some(encoding) if ctx.compression != Compression::None => {
+            let body = resp.bytes().await?;
+            ctx.record_body(&body);
+            let body = decode_body(&encoding, &body)?;
+            file.write_all(&body).await?;
+            size = body.len() as u64;
+        }
+        // 其他情况一边下载一边写入, 不需要把整个body放在内存中
+        _ => {
+            while let Some(chunk) = resp.chunk().await? {
+                ctx.record_body(&chunk);
+                file.write_all(&chunk).await?;
+                size += chunk.len() as u64;
+            }
+        }
+    }
+    file.flush().await?;
+    Ok(size)
+}
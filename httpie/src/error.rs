@@ -0,0 +1,137 @@
+use std::{io, path::Path};
+
+use anyhow::anyhow;
+use reqwest::{StatusCode, Url};
+
+/// 把连接Unix socket时的错误转换成更容易看懂的信息
+pub(crate) fn unix_socket_error(path: &Path, e: hyper::Error) -> anyhow::Error {
+    let mut source = std::error::Error::source(&e);
+    while let Some(err) = source {
+        if let Some(err) = err.downcast_ref::<io::Error>() {
+            let path = path.display();
+            return match err.kind() {
+                io::ErrorKind::NotFound => anyhow!("Unix socket {} doesn't exist", path),
+                io::ErrorKind::PermissionDenied => {
+                    anyhow!("Permission denied when connecting to unix socket {}", path)
+                }
+                io::ErrorKind::ConnectionRefused => {
+                    anyhow!("Connection refused by unix socket {}, is the daemon running?", path)
+                }
+                _ => anyhow!("Failed to connect to unix socket {}: {}", path, err),
+            };
+        }
+        source = err.source();
+    }
+    e.into()
+}
+
+/// 请求超时时的退出码
+pub const EXIT_TIMEOUT: i32 = 2;
+
+/// --check-status 时响应的状态码表示出错
+#[derive(Debug)]
+pub struct ErrorStatus(pub StatusCode);
+
+impl ErrorStatus {
+    /// 和httpie一样, 3xx为3, 4xx为4, 5xx为5
+    pub fn exit_code(&self) -> i32 {
+        (self.0.as_u16() / 100) as i32
+    }
+}
+
+impl std::fmt::Display for ErrorStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "HTTP {}", self.0)
+    }
+}
+
+impl std::error::Error for ErrorStatus {}
+
+/// diff 子命令比较的两个响应不一样时的退出码, 和diff命令相同
+pub const EXIT_DIFFERENT: i32 = 1;
+
+/// diff 子命令发现了差异, 差异已经打印出来了, 这里只记录有几处
+#[derive(Debug)]
+pub struct ResponsesDiffer(pub usize);
+
+impl std::fmt::Display for ResponsesDiffer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "The responses have {} differences", self.0)
+    }
+}
+
+impl std::error::Error for ResponsesDiffer {}
+
+/// run 子命令中有请求的状态码不符合期望, 结果已经打印出来了, 这里只记录有几个
+#[derive(Debug)]
+pub struct RequestsFailed(pub usize);
+
+impl std::fmt::Display for RequestsFailed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} requests failed", self.0)
+    }
+}
+
+impl std::error::Error for RequestsFailed {}
+
+/// --expect-status 等检查没有通过时的退出码, 和其他错误区分开
+pub const EXIT_ASSERTION: i32 = 7;
+
+/// --expect-status, --expect-header 或 --expect-body-contains 没有通过, 每一项是期望和实际的值
+#[derive(Debug)]
+pub struct AssertionsFailed(pub Vec<String>);
+
+impl std::fmt::Display for AssertionsFailed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0.join("\n"))
+    }
+}
+
+impl std::error::Error for AssertionsFailed {}
+
+pub fn is_timeout(e: &anyhow::Error) -> bool {
+    e.chain().any(|e| {
+        e.downcast_ref::<reqwest::Error>()
+            .is_some_and(|e| e.is_timeout())
+    })
+}
+
+/// 通过代理的CONNECT隧道访问https时代理返回了407, reqwest只给出一个字符串错误
+pub fn is_proxy_auth_required(e: &anyhow::Error) -> bool {
+    e.chain().any(|e| e.to_string() == "proxy authentication required")
+}
+
+/// 连接时绑定的本地地址不属于这台机器
+pub fn is_addr_not_available(e: &anyhow::Error) -> bool {
+    e.chain().any(|e| {
+        e.downcast_ref::<io::Error>()
+            .is_some_and(|e| e.kind() == io::ErrorKind::AddrNotAvailable)
+    })
+}
+
+/// 连接失败或者超时, 可以重试
+pub fn is_retryable(e: &anyhow::Error) -> bool {
+    e.chain().any(|e| {
+        e.downcast_ref::<reqwest::Error>()
+            .is_some_and(|e| e.is_connect() || e.is_timeout())
+    })
+}
+
+/// 重定向的次数超过了 --max-redirects
+#[derive(Debug)]
+pub(crate) struct TooManyRedirects {
+    pub(crate) max: usize,
+    pub(crate) chain: Vec<Url>,
+}
+
+impl std::fmt::Display for TooManyRedirects {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "too many redirects (--max-redirects={}), visited:", self.max)?;
+        for url in self.chain.iter() {
+            writeln!(f, "  {}", url)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for TooManyRedirects {}
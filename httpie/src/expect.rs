@@ -0,0 +1,164 @@
+use std::{str::FromStr, sync::Mutex};
+
+use anyhow::{anyhow, Result};
+use reqwest::{
+    header::{HeaderMap, HeaderName},
+    Response, StatusCode,
+};
+
+use crate::{cli::*, error::*, output::*};
+
+/// 诊断信息中最多显示的body字符数
+const BODY_PREVIEW: usize = 200;
+
+/// --expect-header 的值, 例如 `Content-Type: application/json`
+#[derive(Debug, Clone)]
+pub(crate) struct ExpectHeader {
+    name: HeaderName,
+    value: String,
+}
+
+impl FromStr for ExpectHeader {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, value) = s
+            .split_once(':')
+            .ok_or_else(|| anyhow!("Invalid header {}, expected Name: value", s))?;
+        let name = HeaderName::from_bytes(name.trim().as_bytes())
+            .map_err(|e| anyhow!("Invalid header name {}: {}", name, e))?;
+        Ok(Self { name, value: value.trim().to_string() })
+    }
+}
+
+impl ExpectHeader {
+    /// 值为空时只要求有这个响应头; 不区分大小写, 并且忽略 ; 之后的参数, 例如 charset
+    fn matches(&self, headers: &HeaderMap) -> bool {
+        headers.get_all(&self.name).iter().any(|value| {
+            let value = String::from_utf8_lossy(value.as_bytes());
+            let value = value.trim();
+            self.value.is_empty()
+                || value.eq_ignore_ascii_case(&self.value)
+                || value.split(';').next().is_some_and(|v| v.trim().eq_ignore_ascii_case(&self.value))
+        })
+    }
+}
+
+/// 收到的响应, 读完body之后检查
+struct Received {
+    status: StatusCode,
+    headers: HeaderMap,
+    encoding: Option<String>,
+    body: Vec<u8>,
+}
+
+/// --expect-status, --expect-header 和 --expect-body-contains
+pub(crate) struct Expectations {
+    status: Vec<StatusCode>,
+    headers: Vec<ExpectHeader>,
+    body: Vec<String>,
+    received: Mutex<Option<Received>>,
+}
+
+impl Expectations {
+    /// 没有任何检查时为None
+    pub(crate) fn from_args(opt: &App) -> Option<Self> {
+        if opt.expect_status.is_empty() && opt.expect_header.is_empty() && opt.expect_body_contains.is_empty() {
+            return None;
+        }
+        Some(Self {
+            status: opt.expect_status.clone(),
+            headers: opt.expect_header.clone(),
+            body: opt.expect_body_contains.clone(),
+            received: Mutex::new(None),
+        })
+    }
+
+    pub(crate) fn needs_body(&self) -> bool {
+        !self.body.is_empty()
+    }
+
+    /// 状态码和响应头是否符合, --retry 时不符合的响应会重试
+    pub(crate) fn head_matches(&self, resp: &Response) -> bool {
+        (self.status.is_empty() || self.status.contains(&resp.status()))
+            && self.headers.iter().all(|header| header.matches(resp.headers()))
+    }
+
+    pub(crate) fn response(&self, resp: &Response) {
+        *self.received.lock().unwrap() = Some(Received {
+            status: resp.status(),
+            headers: resp.headers().clone(),
+            encoding: content_encoding(resp),
+            body: Vec::new(),
+        });
+    }
+
+    pub(crate) fn body(&self, chunk: &[u8]) {
+        if let Some(received) = self.received.lock().unwrap().as_mut().filter(|_| self.needs_body()) {
+            received.body.extend_from_slice(chunk);
+        }
+    }
+
+    /// 检查最后一个响应, 有不符合的时返回 AssertionsFailed, 其中是每一处的期望和实际值
+    pub(crate) fn verify(&self) -> Result<()> {
+        let received = self.received.lock().unwrap();
+        let Some(received) = received.as_ref() else {
+            return Ok(());
+        };
+        let mut failures = Vec::new();
+        if !self.status.is_empty() && !self.status.contains(&received.status) {
+            let expected = self.status.iter().map(|s| s.as_u16().to_string()).collect::<Vec<_>>();
+            failures.push(format!("expected status {}, got {}", expected.join(" or "), received.status));
+        }
+        for header in self.headers.iter().filter(|header| !header.matches(&received.headers)) {
+            let actual = received
+                .headers
+                .get_all(&header.name)
+                .iter()
+                .map(|v| String::from_utf8_lossy(v.as_bytes()).into_owned())
+                .collect::<Vec<_>>();
+            failures.push(match actual.is_empty() {
+                true => format!("expected header {}: {}, got no {} header", header.name, header.value, header.name),
+                false => format!("expected header {}: {}, got {}", header.name, header.value, actual.join(", ")),
+            });
+        }
+        if self.needs_body() {
+            // 没有自动解压的body在这里解压, 解压失败时按照原样比较
+            let body = match &received.encoding {
+                Some(encoding) => decode_body(encoding, &received.body).unwrap_or_else(|_| received.body.clone()),
+                None => received.body.clone(),
+            };
+            let text = String::from_utf8_lossy(&body);
+            for expected in self.body.iter().filter(|expected| !text.contains(expected.as_str())) {
+                let mut preview = text.chars().take(BODY_PREVIEW).collect::<String>();
+                if text.chars().count() > BODY_PREVIEW {
+                    preview.push_str("...");
+                }
+                failures.push(format!("expected the body to contain {}, got: {}", expected, preview));
+            }
+        }
+        match failures.is_empty() {
+            true => Ok(()),
+            false => Err(AssertionsFailed(failures).into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::{self, HeaderValue};
+
+    #[test]
+    fn expected_headers_ignore_case_and_parameters() {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::CONTENT_TYPE, HeaderValue::from_static("application/json; charset=utf-8"));
+        let expect = |s: &str| s.parse::<ExpectHeader>().unwrap().matches(&headers);
+        assert!(expect("Content-Type: Application/JSON"));
+        assert!(expect("content-type: application/json; charset=utf-8"));
+        assert!(expect("Content-Type:"));
+        assert!(!expect("Content-Type: text/html"));
+        assert!(!expect("ETag:"));
+        assert!("no-colon".parse::<ExpectHeader>().is_err());
+    }
+}
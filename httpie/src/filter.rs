@@ -0,0 +1,194 @@
+use std::{fmt, io::Write, str::FromStr};
+
+use anyhow::{anyhow, Result};
+use mime::Mime;
+use reqwest::Response;
+use serde_json::Value;
+
+use crate::{output::*, request::*};
+
+/// --filter 中的一段, JSON Pointer 中的每一段都是Key, 用在数组上时当作下标
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Segment {
+    Key(String),
+    Index(usize),
+}
+
+/// --filter 的取值: JSON Pointer (/data/items/0/id) 或者点分隔的路径 (data.items[0].id)
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct JsonFilter {
+    pub(crate) pointer: bool,
+    pub(crate) segments: Vec<Segment>,
+}
+
+impl FromStr for JsonFilter {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // 空的JSON Pointer表示整个body
+        if s.is_empty() || s.starts_with('/') {
+            let segments = s
+                .split('/')
+                .skip(1)
+                .map(|token| Segment::Key(token.replace("~1", "/").replace("~0", "~")))
+                .collect();
+            return Ok(Self { pointer: true, segments });
+        }
+
+        let err = || anyhow!("Invalid filter {}, expected /json/pointer or a.b[0].c", s);
+        let mut segments = Vec::new();
+        for part in s.strip_prefix('.').unwrap_or(s).split('.') {
+            let (key, mut rest) = match part.find('[') {
+                Some(i) => part.split_at(i),
+                None => (part, ""),
+            };
+            if key.is_empty() && rest.is_empty() {
+                return Err(err());
+            }
+            if !key.is_empty() {
+                segments.push(Segment::Key(key.to_string()));
+            }
+            while !rest.is_empty() {
+                let (index, tail) = rest
+                    .strip_prefix('[')
+                    .and_then(|rest| rest.split_once(']'))
+                    .ok_or_else(err)?;
+                segments.push(Segment::Index(index.parse().map_err(|_| err())?));
+                rest = tail;
+            }
+        }
+        Ok(Self { pointer: false, segments })
+    }
+}
+
+impl fmt::Display for JsonFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_prefix(f, self.segments.len())
+    }
+}
+
+impl JsonFilter {
+    /// 打印前n段, 报错时用来指出是哪一段不存在
+    fn fmt_prefix(&self, f: &mut dyn fmt::Write, n: usize) -> fmt::Result {
+        for (i, segment) in self.segments[..n].iter().enumerate() {
+            match segment {
+                Segment::Key(key) if self.pointer => {
+                    write!(f, "/{}", key.replace('~', "~0").replace('/', "~1"))?
+                }
+                Segment::Index(index) if self.pointer => write!(f, "/{}", index)?,
+                Segment::Key(key) if i == 0 => f.write_str(key)?,
+                Segment::Key(key) => write!(f, ".{}", key)?,
+                Segment::Index(index) => write!(f, "[{}]", index)?,
+            }
+        }
+        Ok(())
+    }
+
+    /// 转换成JSON Pointer, diff --ignore-path 用它和差异的位置比较
+    pub(crate) fn to_pointer(&self) -> String {
+        let pointer = Self { pointer: true, segments: self.segments.clone() };
+        pointer.to_string()
+    }
+
+    fn prefix(&self, n: usize) -> String {
+        let mut s = String::new();
+        let _ = self.fmt_prefix(&mut s, n);
+        s
+    }
+
+    /// 取出路径对应的值, 不存在时的错误信息中包含出错的那一段
+    pub(crate) fn apply<'a>(&self, value: &'a Value) -> Result<&'a Value> {
+        let mut current = value;
+        for (i, segment) in self.segments.iter().enumerate() {
+            let next = match (segment, current) {
+                (Segment::Key(key), Value::Object(object)) => object.get(key),
+                (Segment::Index(index), Value::Array(array)) => array.get(*index),
+                (Segment::Key(key), Value::Array(array)) => {
+                    key.parse::<usize>().ok().and_then(|index| array.get(index))
+                }
+                _ => None,
+            };
+            current = next.ok_or_else(|| {
+                let kind = match current {
+                    Value::Object(_) => "object".to_string(),
+                    Value::Array(array) => format!("array of {} elements", array.len()),
+                    Value::String(_) => "string".to_string(),
+                    Value::Number(_) => "number".to_string(),
+                    Value::Bool(_) => "boolean".to_string(),
+                    Value::Null => "null".to_string(),
+                };
+                let parent = match self.prefix(i) {
+                    parent if parent.is_empty() => "the body".to_string(),
+                    parent => parent,
+                };
+                anyhow!("--filter {}: {} not found in {} ({})", self, self.prefix(i + 1), parent, kind)
+            })?;
+        }
+        Ok(current)
+    }
+}
+
+/// 字符串直接输出, 方便在shell中使用, 其他值输出为一行JSON
+pub(crate) fn format_filtered(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        value => value.to_string(),
+    }
+}
+
+/// application/json, 带参数的 application/json; charset=utf-8 以及 application/vnd.api+json 这种带 +json 后缀的类型
+pub(crate) fn is_json(mime: &Mime) -> bool {
+    mime.subtype() == mime::JSON || mime.suffix() == Some(mime::JSON)
+}
+
+/// --filter 时读取整个body, 只打印取出来的值, 返回body的字节数
+pub(crate) async fn print_filtered(
+    ctx: &Context,
+    out: &mut impl Write,
+    filter: &JsonFilter,
+    resp: Response,
+) -> Result<u64> {
+    let mime = get_content_type(&resp).ok().flatten();
+    if let Some(mime) = mime.as_ref().filter(|mime| !is_json(mime)) {
+        return Err(anyhow!("--filter needs a JSON response, but the Content-Type is {}", mime));
+    }
+    let body = read_body(ctx, resp).await?;
+    let value: Value = serde_json::from_slice(&body)
+        .map_err(|e| anyhow!("--filter needs a JSON response, but the body is not valid JSON: {}", e))?;
+    writeln!(out, "{}", format_filtered(filter.apply(&value)?))?;
+    Ok(body.len() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn dotted_paths_and_pointers_select_the_same_value() {
+        let value = json!({"data": {"items": [{"id": 7}, {"id": "a/b"}]}});
+        let dotted: JsonFilter = "data.items[1].id".parse().unwrap();
+        let pointer: JsonFilter = "/data/items/0/id".parse().unwrap();
+        assert_eq!(format_filtered(dotted.apply(&value).unwrap()), "a/b");
+        assert_eq!(format_filtered(pointer.apply(&value).unwrap()), "7");
+        assert_eq!(dotted.to_string(), "data.items[1].id");
+        assert_eq!("".parse::<JsonFilter>().unwrap().apply(&value).unwrap(), &value);
+        assert_eq!("/a~1b".parse::<JsonFilter>().unwrap().segments, [Segment::Key("a/b".into())]);
+        assert!("data..id".parse::<JsonFilter>().is_err());
+        assert!("items[x]".parse::<JsonFilter>().is_err());
+    }
+
+    #[test]
+    fn missing_paths_name_the_failing_segment() {
+        let value = json!({"data": {"items": [1, 2]}});
+        let filter: JsonFilter = "data.items[5]".parse().unwrap();
+        let err = filter.apply(&value).unwrap_err().to_string();
+        assert_eq!(
+            err,
+            "--filter data.items[5]: data.items[5] not found in data.items (array of 2 elements)"
+        );
+        let filter: JsonFilter = "/missing".parse().unwrap();
+        let err = filter.apply(&value).unwrap_err().to_string();
+        assert_eq!(err, "--filter /missing: /missing not found in the body (object)");
+    }
+}
@@ -0,0 +1,113 @@
+use std::io::{self, IsTerminal};
+
+use anyhow::{anyhow, Result};
+use colored::Colorize;
+use reqwest::{header, RequestBuilder, Response};
+use serde_json::{json, Value};
+use tokio::io::AsyncReadExt;
+
+use crate::{cli::*, output::*, request::*};
+
+/// 服务器可能只认识其中一种
+const GRAPHQL_ACCEPT: &str = "application/graphql-response+json, application/json;q=0.9";
+
+/// 构造 {"query": ..., "variables": {...}, "operationName": ...} 并用POST发送
+pub(crate) async fn graphql_request(ctx: &Context, args: &Graphql) -> Result<RequestBuilder> {
+    let query = match &args.query {
+        Some(query) => match query.strip_prefix('@') {
+            Some(path) => read_text_file(path.as_ref()).await?,
+            None => query.clone(),
+        },
+        None if ctx.ignore_stdin || io::stdin().is_terminal() => String::new(),
+        None => {
+            let mut query = String::new();
+            tokio::io::stdin().read_to_string(&mut query).await?;
+            query
+        }
+    };
+    if query.trim().is_empty() {
+        return Err(anyhow!("No GraphQL query, use --query or pipe it into stdin"));
+    }
+
+    let mut body = json!({ "query": query });
+    let variables = body_fields(&args.vars).await?;
+    if !variables.is_empty() {
+        body["variables"] = Value::Object(variables);
+    }
+    if let Some(name) = &args.operation_name {
+        body["operationName"] = Value::String(name.clone());
+    }
+    Ok(ctx.client.post(&args.url).header(header::ACCEPT, GRAPHQL_ACCEPT).json(&body))
+}
+
+/// 和普通的响应一样打印, body中有 errors 时再用红色把每个错误列在stderr上
+pub(crate) async fn print_graphql(ctx: &Context, out: &mut impl Output, resp: Response) -> Result<()> {
+    let meta = Meta::new(ctx, &resp);
+    let status = resp.status();
+    if ctx.print.response_headers {
+        print_head(out, &resp)?;
+    }
+    let mime = get_content_type(&resp).ok().flatten();
+    let body = read_body(ctx, resp).await?;
+    if ctx.print.response_body {
+        print_body(ctx, out, mime.clone(), &decode_text(&body, mime.as_ref(), ctx.charset))?;
+    }
+    meta.print(body.len() as u64);
+
+    let errors = serde_json::from_slice::<Value>(&body)
+        .ok()
+        .and_then(|body| body.get("errors").and_then(Value::as_array).cloned())
+        .unwrap_or_default();
+    if !errors.is_empty() && ctx.warnings() {
+        eprintln!("{}", format!("GraphQL errors ({}):", errors.len()).red().bold());
+        for error in errors.iter() {
+            eprintln!("{}", format!("  - {}", describe_error(error)).red());
+        }
+    }
+    ctx.check_status(status)
+}
+
+/// 错误信息加上出错的字段路径和查询语句中的位置, 例如 `not found (at user.posts.0, line 2:3)`
+pub(crate) fn describe_error(error: &Value) -> String {
+    let message = match error.get("message").and_then(Value::as_str) {
+        Some(message) => message.to_string(),
+        None => error.to_string(),
+    };
+    let mut at = Vec::new();
+    if let Some(path) = error.get("path").and_then(Value::as_array) {
+        let path = path
+            .iter()
+            .map(|segment| match segment {
+                Value::String(s) => s.clone(),
+                other => other.to_string(),
+            })
+            .collect::<Vec<_>>();
+        at.push(format!("at {}", path.join(".")));
+    }
+    if let Some(location) = error.pointer("/locations/0") {
+        if let (Some(line), Some(column)) = (location.get("line"), location.get("column")) {
+            at.push(format!("line {}:{}", line, column));
+        }
+    }
+    if at.is_empty() {
+        message
+    } else {
+        format!("{} ({})", message, at.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn errors_include_path_and_location() {
+        let error = json!({
+            "message": "not found",
+            "path": ["user", "posts", 0],
+            "locations": [{"line": 2, "column": 3}],
+        });
+        assert_eq!(describe_error(&error), "not found (at user.posts.0, line 2:3)");
+        assert_eq!(describe_error(&json!({"message": "denied"})), "denied");
+    }
+}
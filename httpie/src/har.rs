@@ -0,0 +1,363 @@
+use std::{
+    fs,
+    io,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use anyhow::{anyhow, Result};
+use base64::Engine;
+use mime::Mime;
+use reqwest::{header, header::HeaderMap, Response, StatusCode, Version};
+use serde::Serialize;
+use serde_json::Value;
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+
+use crate::output::*;
+
+/// --har 时记录一次请求和响应, 读取完body后追加到HAR文件中
+pub(crate) struct HarRecorder {
+    pub(crate) path: PathBuf,
+    pub(crate) entry: Mutex<Option<PendingEntry>>,
+}
+
+/// 还没有写入文件的记录, 响应的body在读取时逐块追加进来
+pub(crate) struct PendingEntry {
+    pub(crate) started: String,
+    pub(crate) request: HarRequest,
+    pub(crate) response: Option<PendingResponse>,
+    /// 收到的原始body, 可能是压缩过的
+    pub(crate) body: Vec<u8>,
+}
+
+pub(crate) struct PendingResponse {
+    pub(crate) status: StatusCode,
+    pub(crate) version: Version,
+    pub(crate) headers: HeaderMap,
+    pub(crate) remote: Option<String>,
+    pub(crate) timing: Option<Timing>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct HarEntry {
+    pub(crate) started_date_time: String,
+    /// 整个请求的耗时, 单位为毫秒
+    pub(crate) time: f64,
+    pub(crate) request: HarRequest,
+    pub(crate) response: HarResponse,
+    pub(crate) cache: Value,
+    pub(crate) timings: HarTimings,
+    #[serde(rename = "serverIPAddress", skip_serializing_if = "Option::is_none")]
+    pub(crate) server_ip_address: Option<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct HarRequest {
+    pub(crate) method: String,
+    pub(crate) url: String,
+    pub(crate) http_version: String,
+    pub(crate) cookies: Vec<NameValue>,
+    pub(crate) headers: Vec<NameValue>,
+    pub(crate) query_string: Vec<NameValue>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) post_data: Option<PostData>,
+    pub(crate) headers_size: i64,
+    pub(crate) body_size: i64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct PostData {
+    pub(crate) mime_type: String,
+    pub(crate) text: String,
+    /// HAR 1.2 中postData没有这个字段, 和content一样用它标记base64编码的二进制body
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) encoding: Option<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct HarResponse {
+    pub(crate) status: u16,
+    pub(crate) status_text: String,
+    pub(crate) http_version: String,
+    pub(crate) cookies: Vec<NameValue>,
+    pub(crate) headers: Vec<NameValue>,
+    pub(crate) content: HarContent,
+    #[serde(rename = "redirectURL")]
+    pub(crate) redirect_url: String,
+    pub(crate) headers_size: i64,
+    pub(crate) body_size: i64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct HarContent {
+    /// 解压后的大小
+    pub(crate) size: u64,
+    /// 解压节省的字节数, 没有压缩时不输出
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) compression: Option<i64>,
+    pub(crate) mime_type: String,
+    pub(crate) text: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) encoding: Option<String>,
+}
+
+/// 单位都是毫秒, -1 表示无法得到
+#[derive(Serialize)]
+pub(crate) struct HarTimings {
+    pub(crate) blocked: f64,
+    pub(crate) dns: f64,
+    pub(crate) connect: f64,
+    pub(crate) send: f64,
+    pub(crate) wait: f64,
+    pub(crate) receive: f64,
+    pub(crate) ssl: f64,
+}
+
+#[derive(Serialize)]
+pub(crate) struct NameValue {
+    pub(crate) name: String,
+    pub(crate) value: String,
+}
+
+impl HarRecorder {
+    pub(crate) fn new(path: PathBuf) -> Self {
+        Self { path, entry: Mutex::new(None) }
+    }
+
+    /// 发送前记录请求, headers是实际发送的请求头
+    pub(crate) fn request(&self, req: &reqwest::Request, headers: &HeaderMap) {
+        let url = req.url();
+        let mime_type = header_str(headers, header::CONTENT_TYPE).unwrap_or_default().to_string();
+        let body = req.body().map(|body| body.as_bytes());
+        let post_data = match body {
+            Some(Some(bytes)) if !bytes.is_empty() => {
+                let (text, encoding) = encode_body(mime_type.parse().ok().as_ref(), bytes);
+                Some(PostData { mime_type, text, encoding })
+            }
+            _ => None,
+        };
+        let request = HarRequest {
+            method: req.method().to_string(),
+            url: url.to_string(),
+            http_version: format!("{:?}", req.version()),
+            cookies: request_cookies(headers),
+            headers: name_values(headers),
+            query_string: url
+                .query_pairs()
+                .map(|(name, value)| NameValue { name: name.into(), value: value.into() })
+                .collect(),
+            post_data,
+            headers_size: -1,
+            body_size: match body {
+                Some(Some(bytes)) => bytes.len() as i64,
+                // 流式发送的body不知道大小
+                Some(None) => -1,
+                None => 0,
+            },
+        };
+        let started = OffsetDateTime::now_utc().format(&Rfc3339).unwrap_or_default();
+        *self.entry.lock().unwrap() =
+            Some(PendingEntry { started, request, response: None, body: Vec::new() });
+    }
+
+    /// 收到响应头时记录响应, body之后通过 body() 追加
+    pub(crate) fn response(&self, resp: &Response) {
+        if let Some(entry) = self.entry.lock().unwrap().as_mut() {
+            entry.response = Some(PendingResponse {
+                status: resp.status(),
+                version: resp.version(),
+                headers: resp.headers().clone(),
+                remote: resp.remote_addr().map(|addr| addr.ip().to_string()),
+                timing: resp.extensions().get::<Timing>().copied(),
+            });
+        }
+    }
+
+    pub(crate) fn body(&self, chunk: &[u8]) {
+        if let Some(entry) = self.entry.lock().unwrap().as_mut() {
+            entry.body.extend_from_slice(chunk);
+        }
+    }
+
+    /// 把记录追加到HAR文件中, 没有收到响应时 (例如 --offline) 什么都不做
+    pub(crate) fn save(&self) -> Result<()> {
+        let entry = match self.entry.lock().unwrap().take() {
+            Some(PendingEntry { started, request, response: Some(resp), body }) => {
+                HarEntry::new(started, request, resp, body)
+            }
+            _ => return Ok(()),
+        };
+
+        let mut har = read_har(&self.path)?;
+        let entries = har
+            .pointer_mut("/log/entries")
+            .and_then(Value::as_array_mut)
+            .ok_or_else(|| anyhow!("Failed to read {}: not a HAR file", self.path.display()))?;
+        entries.push(serde_json::to_value(entry)?);
+        let content = serde_json::to_string_pretty(&har)?;
+        fs::write(&self.path, content + "\n")
+            .map_err(|e| anyhow!("Failed to write {}: {}", self.path.display(), e))
+    }
+}
+
+impl HarEntry {
+    pub(crate) fn new(
+        started: String,
+        request: HarRequest,
+        resp: PendingResponse,
+        body: Vec<u8>,
+    ) -> Self {
+        let mime = header_str(&resp.headers, header::CONTENT_TYPE);
+        let parsed = mime.and_then(|v| v.parse::<Mime>().ok());
+        // content 中是解压后的body, 解压失败时保留原始的数据
+        let decoded = match header_str(&resp.headers, header::CONTENT_ENCODING) {
+            Some(encoding) => decode_body(encoding, &body).unwrap_or_else(|_| body.clone()),
+            None => body.clone(),
+        };
+        let (text, encoding) = encode_body(parsed.as_ref(), &decoded);
+        let compression = decoded.len() as i64 - body.len() as i64;
+        let content = HarContent {
+            size: decoded.len() as u64,
+            compression: Some(compression).filter(|&v| v != 0),
+            mime_type: mime.unwrap_or_default().to_string(),
+            text,
+            encoding,
+        };
+
+        let ms = |d: std::time::Duration| d.as_secs_f64() * 1000.0;
+        let (total, wait) = match resp.timing {
+            Some(timing) => (ms(timing.start.elapsed()), ms(timing.first_byte)),
+            None => (0.0, 0.0),
+        };
+        let timings = HarTimings {
+            blocked: -1.0,
+            dns: -1.0,
+            connect: -1.0,
+            send: 0.0,
+            wait,
+            receive: (total - wait).max(0.0),
+            ssl: -1.0,
+        };
+
+        let response = HarResponse {
+            status: resp.status.as_u16(),
+            status_text: resp.status.canonical_reason().unwrap_or_default().to_string(),
+            http_version: format!("{:?}", resp.version),
+            cookies: response_cookies(&resp.headers),
+            headers: name_values(&resp.headers),
+            content,
+            redirect_url: header_str(&resp.headers, header::LOCATION).unwrap_or_default().into(),
+            headers_size: -1,
+            body_size: body.len() as i64,
+        };
+        HarEntry {
+            started_date_time: started,
+            time: total,
+            request,
+            response,
+            cache: Value::Object(Default::default()),
+            timings,
+            server_ip_address: resp.remote,
+        }
+    }
+}
+
+/// 读取已有的HAR文件, 不存在时创建一个空的log
+pub(crate) fn read_har(path: &Path) -> Result<Value> {
+    match fs::read_to_string(path) {
+        Ok(content) => serde_json::from_str(&content)
+            .map_err(|e| anyhow!("Failed to read {}: {}", path.display(), e)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(serde_json::json!({
+            "log": {
+                "version": "1.2",
+                "creator": {
+                    "name": env!("CARGO_PKG_NAME"),
+                    "version": env!("CARGO_PKG_VERSION"),
+                },
+                "entries": [],
+            }
+        })),
+        Err(e) => Err(anyhow!("Failed to read {}: {}", path.display(), e)),
+    }
+}
+
+/// 文本原样保存, 二进制数据使用base64编码, 这时第二个值为 Some("base64")
+pub(crate) fn encode_body(mime: Option<&Mime>, body: &[u8]) -> (String, Option<String>) {
+    match std::str::from_utf8(body) {
+        Ok(text) if !is_binary(mime, body) => (text.to_string(), None),
+        _ => {
+            let text = base64::engine::general_purpose::STANDARD.encode(body);
+            (text, Some("base64".to_string()))
+        }
+    }
+}
+
+pub(crate) fn header_str(headers: &HeaderMap, name: header::HeaderName) -> Option<&str> {
+    headers.get(name).and_then(|v| v.to_str().ok())
+}
+
+pub(crate) fn name_values(headers: &HeaderMap) -> Vec<NameValue> {
+    headers
+        .iter()
+        .map(|(name, value)| NameValue {
+            name: name.to_string(),
+            value: String::from_utf8_lossy(value.as_bytes()).into_owned(),
+        })
+        .collect()
+}
+
+/// Cookie请求头中的 name=value; name2=value2
+pub(crate) fn request_cookies(headers: &HeaderMap) -> Vec<NameValue> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(';'))
+        .filter_map(cookie_pair)
+        .collect()
+}
+
+/// 每个Set-Cookie开头的 name=value, 忽略后面的属性
+pub(crate) fn response_cookies(headers: &HeaderMap) -> Vec<NameValue> {
+    headers
+        .get_all(header::SET_COOKIE)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .filter_map(|v| cookie_pair(v.split(';').next()?))
+        .collect()
+}
+
+pub(crate) fn cookie_pair(s: &str) -> Option<NameValue> {
+    let (name, value) = s.trim().split_once('=')?;
+    Some(NameValue { name: name.to_string(), value: value.to_string() })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn binary_bodies_are_base64_encoded() {
+        assert_eq!(encode_body(None, b"hello"), ("hello".to_string(), None));
+        let (text, encoding) = encode_body(None, &[0, 159, 146, 150]);
+        assert_eq!(text, "AJ+Slg==");
+        assert_eq!(encoding.as_deref(), Some("base64"));
+    }
+
+    #[test]
+    fn cookies_are_split_into_pairs() {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::COOKIE, "a=1; b=2".parse().unwrap());
+        headers.append(header::SET_COOKIE, "c=3; Path=/; HttpOnly".parse().unwrap());
+        let names = |cookies: Vec<NameValue>| {
+            cookies.into_iter().map(|c| format!("{}={}", c.name, c.value)).collect::<Vec<_>>()
+        };
+        assert_eq!(names(request_cookies(&headers)), ["a=1", "b=2"]);
+        assert_eq!(names(response_cookies(&headers)), ["c=3"]);
+    }
+}
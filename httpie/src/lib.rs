@@ -0,0 +1,49 @@
+//! 命令行HTTP客户端, 也可以作为库在其他程序中发送请求
+//!
+//! ```no_run
+//! # async fn example() -> anyhow::Result<()> {
+//! let resp = httpie::fetch(["httpie", "post", "localhost:3000/users", "name=tom"]).await?;
+//! println!("{} {}", resp.status, resp.json()?["id"]);
+//! # Ok(())
+//! # }
+//! ```
+
+mod aws;
+mod batch;
+mod bench;
+pub mod cache;
+pub mod cli;
+pub mod collection;
+mod compress;
+pub mod config;
+mod curl;
+mod diff;
+mod digest;
+mod dns;
+mod download;
+pub mod error;
+mod expect;
+mod filter;
+mod graphql;
+mod har;
+pub mod history;
+mod netrc;
+mod oauth2;
+mod output;
+mod paginate;
+pub mod repl;
+pub mod request;
+pub mod serve;
+mod session;
+mod sse;
+mod timings;
+mod tls;
+mod transcript;
+mod upload;
+mod vars;
+mod watch;
+mod ws;
+
+pub use cli::App;
+pub use output::{Output, StdOutput};
+pub use request::{fetch, Context, HttpResponse};
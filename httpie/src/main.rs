@@ -1,171 +1,103 @@
-use std::{collections::HashMap, hash, str::FromStr};
+use std::{
+    io::{self, IsTerminal},
+    process,
+    sync::Arc,
+};
 
-use anyhow::{anyhow, Result};
+use anyhow::Result;
 use colored::Colorize;
-use mime::Mime;
-use reqwest::{Client, Response, Url, header};
+use httpie::{
+    cache, collection,
+    cli::{self, App, SubCommand},
+    config::{self, ConfigFile},
+    error::{is_timeout, AssertionsFailed, ErrorStatus, RequestsFailed, ResponsesDiffer, EXIT_ASSERTION, EXIT_DIFFERENT, EXIT_TIMEOUT},
+    history, repl,
+    serve,
+    request::{self, Context},
+    StdOutput,
+};
 use structopt::StructOpt;
 
-// get 子命令
-
-/// feed get with an url and we will retrieve the response for you 
-#[derive(Debug, StructOpt)]
-pub struct Get {
-    /// HTTP请求的URL
-    #[structopt(parse(try_from_str = parse_url))]
-    url: String,
-}
-
-fn parse_url(s: &str) -> Result<String> {
-    // 检查URL是否合法
-    let _url : Url = s.parse()?;
-
-    Ok(s.into())
-}
-
-// post子命令。需要输入一个URL， 和若干可选的key=value, 用于提供json body
-
-/// feed post with an url and optional key=value pairs. We will post 
-/// as JSON , and retrieve the response for you
-#[derive(Debug, StructOpt)]
-pub struct Post {
-    /// HTTP 请求的URL
-    #[structopt(parse(try_from_str = parse_url))]
-    url: String,
-    /// HTTP 请求的body
-    #[structopt(parse(try_from_str = parse_kv_pair))]
-    body: Vec<KvPair>,
-}
-
-/// 命令行中的key=value 可以通过parse_kv_pair解析成KvPair结构
-#[derive(Debug)]
-struct KvPair {
-    k: String,
-    v: String,
-}
-
-
-impl FromStr for KvPair {
-    type Err = anyhow::Error;
-
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        // 使用 = 进行split, 这会得到一个迭代器
-        let mut split = s.split("=");
-        let err = || anyhow!(format!("Failed to parse {}", s));
-
-        Ok(Self {
-            // 从迭代器中取第一个结果作为key，迭代器返回Some(T)/None
-            // 我们将其转换成Ok(T)/Err(E), 然后用?处理错误
-            k: (split.next().ok_or_else(err)?).to_string(),
-            // 从迭代器中取第二个结果作为value
-            v: (split.next().ok_or_else(err)?).to_string(),
-        })
-    }
-}
-
-/// 因为为KyPair实现了FromStr,这里可以直接使用s.parse()得到KvPair
-fn parse_kv_pair(s: &str) -> Result<KvPair> {
-    Ok(s.parse()?)
-}
-
-// 子命令分别对应不同的HTTP方法，目前只支持get / post
-#[derive(Debug, StructOpt)]
-pub enum SubCommand {
-    #[structopt(name = "get")]
-    Get(Get),
-    #[structopt(name = "post")]
-    Post(Post),
-    // 其他方法
-}
-
-// 定义HTTPie的CLI的主入口，它包含若干子命令
-// 下面/// 的注释是文档，structopt会将其作为CLI的帮助
-
-/// A naive httpie implementation with Rust, can you imagine how easy it is?
-#[derive(Debug, StructOpt)]
-#[structopt(name = "httpie", author = "davirain.yin@gmail.com")]
-pub struct App {
-    #[structopt(subcommand)]
-    pub subcommand: SubCommand,
-}
-
 #[tokio::main]
-async fn main() -> Result<()> {
-    let opt = App::from_args();
-    println!("{:?}", opt);
-
-    let mut headers = header::HeaderMap::new();
-    headers.insert("X-POWERD-BY", "Rust".parse()?);
-    headers.insert(header::USER_AGENT, "Rust Httpie".parse()?);
-
-    // let client = Client::new();
-    let client = reqwest::Client::builder()
-        .default_headers(headers)
-        .build()?;
-
-    let result = match opt.subcommand {
-        SubCommand::Get(ref args) => get(client, args).await?,
-        SubCommand::Post(ref args) => post(client, args).await?,
-    };
-    
-    Ok(result)
-
-}
-
-async fn get(client: Client, args: &Get) -> Result<()> {
-    let resp = client.get(&args.url).send().await?;
-    Ok(print_resp(resp).await?)
-    // println!("{:?}",resp.text().await?);
-}
-
-async fn post(client: Client, args: &Post) -> Result<()> {
-    let mut body = HashMap::new();
-    for pair in args.body.iter() {
-        body.insert(&pair.k, &pair.v);
+async fn main() {
+    let mut opt = App::from_args();
+    // 配置文件有错误时直接退出, 而不是忽略它
+    if let Err(e) = ConfigFile::load(&mut opt) {
+        eprintln!("Error: {:?}", e);
+        process::exit(1);
     }
-    let resp = client.post(&args.url).json(&body).send().await?;
-    Ok(print_resp(resp).await?)
-}
-
-// 打印服务器版本号 + 状态码
-fn print_status(resp: &Response) {
-    let status = format!("{:?} {}", resp.version(), resp.status()).blue();
-    println!("{}\n", status);
-}
-
-// 打印服务器返回的HTTP header
-fn print_headers(resp: &Response) {
-    for (name, value) in resp.headers() {
-        println!("{}: {:?}", name.to_string().green(), value);
+    colored::control::set_override(opt.color_enabled());
+
+    let timeout = opt.timeout;
+    // -qq 时连错误信息也不显示, 只通过退出码判断
+    let silent = opt.quiet >= 2;
+    if let Err(e) = run(opt).await {
+        if let Some(status) = e.downcast_ref::<ErrorStatus>() {
+            // 响应已经打印到终端上了, 只有输出被重定向时才提示
+            if !io::stdout().is_terminal() && !silent {
+                eprintln!("{}", format!("warning: {}", status).yellow());
+            }
+            process::exit(status.exit_code());
+        }
+        // 差异已经打印出来了
+        if e.downcast_ref::<ResponsesDiffer>().is_some() {
+            process::exit(EXIT_DIFFERENT);
+        }
+        if let Some(failed) = e.downcast_ref::<AssertionsFailed>() {
+            if !silent {
+                for failure in failed.0.iter() {
+                    eprintln!("{}", format!("assertion failed: {}", failure).red());
+                }
+            }
+            process::exit(EXIT_ASSERTION);
+        }
+        // 每个请求的结果已经打印出来了
+        if e.downcast_ref::<RequestsFailed>().is_some() {
+            process::exit(1);
+        }
+        match timeout {
+            // 超时用单独的退出码, 方便脚本判断
+            Some(secs) if is_timeout(&e) => {
+                if !silent {
+                    eprintln!("Error: request timed out after {}s", secs);
+                }
+                process::exit(EXIT_TIMEOUT);
+            }
+            _ => {
+                if !silent {
+                    eprintln!("Error: {:?}", e);
+                }
+                process::exit(1);
+            }
+        }
     }
-
-    println!("");
 }
 
-/// 打印服务器返回的HTTP body
-fn print_body(m: Option<Mime>, body: &str) {
-    match m {
-        // 对于"application/json" pretty print
-        Some(v) if v == mime::APPLICATION_JSON  => {
-            println!("{}", jsonxf::pretty_print(body).unwrap().cyan());
+async fn run(mut opt: App) -> Result<()> {
+    if opt.etag_cache_clear {
+        let removed = cache::clear_etag_cache()?;
+        if opt.quiet == 0 {
+            eprintln!("{}", format!("Removed {} cached responses", removed).dimmed());
         }
-        _ => println!("{}", body),
     }
+    // 这些子命令不发送请求, 不需要创建client
+    match &opt.subcommand {
+        SubCommand::Config(args) => return config::run(args),
+        SubCommand::Completions(args) => return cli::completions(args),
+        SubCommand::History(args) => return history::list(args),
+        SubCommand::Serve(args) => return serve::run(args, opt.quiet).await,
+        _ => {}
+    }
+    history::load_replay(&mut opt)?;
+    collection::load_collection(&mut opt)?;
+    opt.load_url_file()?;
+    opt.load_items_files()?;
+    opt.substitute_vars()?;
+    opt.expand_url()?;
+    let ctx = Arc::new(Context::from_args(&opt)?);
+    // repl 中的每条命令都通过 request::run 发送
+    if let SubCommand::Repl(args) = &opt.subcommand {
+        return repl::run(&ctx, args, &mut StdOutput::new()).await;
+    }
+    request::run(&ctx, &opt.subcommand, &mut StdOutput::new()).await
 }
-
-async fn print_resp(resp: Response) -> Result<()> {
-    print_status(&resp);
-    print_headers(&resp);
-    let mime = get_content_type(&resp);
-    let body = resp.text().await?;
-    print_body(mime, body.as_str());
-
-    Ok(())
-}
-
-fn get_content_type(resp: &Response) -> Option<Mime>  {
-    resp
-        .headers()
-        .get(header::CONTENT_TYPE)
-        .map(|v| v.to_str().unwrap().parse().unwrap())
-}
\ No newline at end of file
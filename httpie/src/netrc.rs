@@ -0,0 +1,188 @@
+use std::{fs, io, path::PathBuf};
+
+use anyhow::{anyhow, Result};
+use colored::Colorize;
+use reqwest::Url;
+
+use crate::{cli::*, request::*};
+
+/// netrc中一台机器的登录信息
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct NetrcEntry {
+    pub(crate) login: String,
+    pub(crate) password: String,
+}
+
+/// ~/.netrc 的内容, 格式和curl, ftp的一样
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct Netrc {
+    pub(crate) machines: Vec<(String, NetrcEntry)>,
+    /// default 项, 没有匹配的machine时使用
+    pub(crate) default: Option<NetrcEntry>,
+}
+
+impl Netrc {
+    /// 记号之间可以是空格也可以是换行, 所以一项可以写在一行中, 也可以分成多行
+    /// macdef 定义的宏一直到空行结束, 直接跳过
+    pub(crate) fn parse(content: &str) -> Result<Self> {
+        let mut netrc = Netrc::default();
+        // 当前项的名字, None表示default
+        let mut current: Option<Option<String>> = None;
+        let (mut login, mut password) = (None, None);
+
+        let mut in_macro = false;
+        for (n, line) in content.lines().enumerate() {
+            if in_macro {
+                in_macro = !line.trim().is_empty();
+                continue;
+            }
+            if line.trim_start().starts_with('#') {
+                continue;
+            }
+            let mut tokens = line.split_whitespace();
+            while let Some(token) = tokens.next() {
+                let mut value = |name: &str| {
+                    tokens
+                        .next()
+                        .map(str::to_string)
+                        .ok_or_else(|| anyhow!("line {}: {} needs a value", n + 1, name))
+                };
+                match token {
+                    "machine" => {
+                        let machine = value("machine")?;
+                        netrc.push(current.take(), login.take(), password.take());
+                        current = Some(Some(machine));
+                    }
+                    "default" => {
+                        netrc.push(current.take(), login.take(), password.take());
+                        current = Some(None);
+                    }
+                    "login" => login = Some(value("login")?),
+                    "password" => password = Some(value("password")?),
+                    "account" => drop(value("account")?),
+                    "macdef" => {
+                        in_macro = true;
+                        break;
+                    }
+                    token => return Err(anyhow!("line {}: unknown token {}", n + 1, token)),
+                }
+            }
+        }
+        netrc.push(current, login, password);
+        Ok(netrc)
+    }
+
+    fn push(&mut self, machine: Option<Option<String>>, login: Option<String>, password: Option<String>) {
+        let entry = NetrcEntry { login: login.unwrap_or_default(), password: password.unwrap_or_default() };
+        match machine {
+            Some(Some(machine)) => self.machines.push((machine, entry)),
+            Some(None) => self.default = Some(entry),
+            None => {}
+        }
+    }
+
+    pub(crate) fn lookup(&self, host: &str) -> Option<&NetrcEntry> {
+        self.machines
+            .iter()
+            .find(|(machine, _)| machine.eq_ignore_ascii_case(host))
+            .map(|(_, entry)| entry)
+            .or(self.default.as_ref())
+    }
+}
+
+/// 环境变量 NETRC 或者 ~/.netrc
+pub(crate) fn netrc_path() -> Result<PathBuf> {
+    match std::env::var_os("NETRC") {
+        Some(path) => Ok(PathBuf::from(path)),
+        None => Ok(dirs::home_dir()
+            .ok_or_else(|| anyhow!("Failed to find the home directory"))?
+            .join(".netrc")),
+    }
+}
+
+/// 其他用户可以读取的netrc文件中的密码已经不安全了, 除非 --netrc-insecure, 否则不使用
+fn check_permissions(path: &std::path::Path, insecure: bool) -> Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = fs::metadata(path)?.permissions().mode();
+        if mode & 0o004 != 0 && !insecure {
+            return Err(anyhow!(
+                "{} is readable by other users, run chmod 600 {} or pass --netrc-insecure",
+                path.display(),
+                path.display()
+            ));
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = (path, insecure);
+    Ok(())
+}
+
+/// --auth-type netrc, 或者没有其他认证信息时, 使用netrc中请求的主机的登录信息
+/// --auth-type netrc 时找不到登录信息是错误, 自动查找时只是不使用
+pub(crate) fn netrc_auth(opt: &App, url: &str) -> Result<Option<Auth>> {
+    let explicit = opt.auth_type == AuthType::Netrc;
+    let Some(host) = Url::parse(url).ok().and_then(|url| url.host_str().map(str::to_string)) else {
+        return Ok(None);
+    };
+    let path = netrc_path()?;
+    let content = match fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound && !explicit => return Ok(None),
+        Err(e) => return Err(anyhow!("Failed to read {}: {}", path.display(), e)),
+    };
+    if let Err(e) = check_permissions(&path, opt.netrc_insecure) {
+        if explicit {
+            return Err(e);
+        }
+        if opt.quiet <= 1 {
+            eprintln!("{}", format!("warning: {}", e).yellow());
+        }
+        return Ok(None);
+    }
+    let netrc = Netrc::parse(&content).map_err(|e| anyhow!("Failed to parse {}: {}", path.display(), e))?;
+    match netrc.lookup(&host) {
+        Some(entry) => Ok(Some(Auth::Basic { user: entry.login.clone(), password: entry.password.clone() })),
+        None if explicit => Err(anyhow!("No entry for {} in {}", host, path.display())),
+        None => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn single_and_multi_line_entries_with_macros_and_default() {
+        let content = "machine api.example.com login alice password s3cret\n\
+                       # comment\n\
+                       machine other.example.com\n  login bob\n  account x\n  password hunter2\n\
+                       macdef init\n  machine fake login no password no\n\n\
+                       default login anon password guest\n";
+        let netrc = Netrc::parse(content).unwrap();
+        assert_eq!(netrc.machines.len(), 2);
+        let entry = |login: &str, password: &str| NetrcEntry { login: login.into(), password: password.into() };
+        assert_eq!(netrc.lookup("API.example.com"), Some(&entry("alice", "s3cret")));
+        assert_eq!(netrc.lookup("other.example.com"), Some(&entry("bob", "hunter2")));
+        assert_eq!(netrc.lookup("fake"), Some(&entry("anon", "guest")));
+
+        let err = Netrc::parse("machine a login").unwrap_err();
+        assert_eq!(err.to_string(), "line 1: login needs a value");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn world_readable_files_are_refused() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = std::env::temp_dir().join(format!("httpie-netrc-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("netrc");
+        fs::write(&path, "default login a password b\n").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+        assert!(check_permissions(&path, false).unwrap_err().to_string().contains("--netrc-insecure"));
+        assert!(check_permissions(&path, true).is_ok());
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o600)).unwrap();
+        assert!(check_permissions(&path, false).is_ok());
+    }
+}
@@ -0,0 +1,135 @@
+use std::{fs, path::PathBuf};
+
+use anyhow::{anyhow, Result};
+use colored::Colorize;
+use reqwest::{
+    header::{self, HeaderValue},
+    Url,
+};
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+
+use crate::{cache::*, config::*, request::*, session::*};
+
+/// 离过期还剩这么多秒时就重新获取, 避免请求发出去时token刚好过期
+const EXPIRY_MARGIN: u64 = 30;
+
+/// 缓存文件的内容
+#[derive(Debug, Serialize, Deserialize)]
+struct CachedToken {
+    /// token_url, client_id和scope, 文件名是它的哈希
+    key: String,
+    access_token: String,
+    /// 过期的Unix时间
+    expires_at: u64,
+}
+
+/// 令牌端点的响应, 只用到这两个字段
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: Option<u64>,
+}
+
+/// --oauth2-token-url: 用client_credentials授权取得access token, 作为Bearer token发送
+/// token缓存在配置目录中直到过期, 同一次运行中的多个请求只获取一次
+pub(crate) struct OAuth2Client {
+    pub(crate) token_url: Url,
+    pub(crate) client_id: String,
+    pub(crate) client_secret: String,
+    pub(crate) scope: Option<String>,
+    /// --oauth2-force-refresh 时不使用缓存的token
+    pub(crate) force_refresh: bool,
+    pub(crate) dir: PathBuf,
+    pub(crate) token: Mutex<Option<HeaderValue>>,
+}
+
+impl OAuth2Client {
+    fn key(&self) -> String {
+        format!("{} {} {}", self.token_url, self.client_id, self.scope.as_deref().unwrap_or(""))
+    }
+
+    /// 设置Authorization, 命令行中的 Authorization:xxx 优先
+    pub(crate) async fn authorize(&self, ctx: &Context, req: &mut reqwest::Request) -> Result<()> {
+        if req.headers().contains_key(header::AUTHORIZATION) {
+            return Ok(());
+        }
+        let mut token = self.token.lock().await;
+        let value = match &*token {
+            Some(value) => value.clone(),
+            None => {
+                let access_token = match self.cached().filter(|_| !self.force_refresh) {
+                    Some(access_token) => access_token,
+                    None => self.fetch(ctx).await?,
+                };
+                let value = HeaderValue::from_str(&format!("Bearer {}", access_token))?;
+                token.insert(value).clone()
+            }
+        };
+        req.headers_mut().insert(header::AUTHORIZATION, value);
+        Ok(())
+    }
+
+    fn cached(&self) -> Option<String> {
+        let content = fs::read_to_string(hashed_path(&self.dir, &self.key())).ok()?;
+        let cached = serde_json::from_str::<CachedToken>(&content).ok()?;
+        let valid = cached.key == self.key() && cached.expires_at > unix_now();
+        Some(cached.access_token).filter(|_| valid)
+    }
+
+    /// POST application/x-www-form-urlencoded 到令牌端点
+    async fn fetch(&self, ctx: &Context) -> Result<String> {
+        let mut form = vec![
+            ("grant_type", "client_credentials"),
+            ("client_id", &self.client_id),
+            ("client_secret", &self.client_secret),
+        ];
+        if let Some(scope) = &self.scope {
+            form.push(("scope", scope));
+        }
+        let resp = ctx
+            .client
+            .post(self.token_url.clone())
+            .header(header::ACCEPT, "application/json")
+            .form(&form)
+            .send()
+            .await
+            .map_err(|e| anyhow!("Failed to fetch OAuth2 token from {}: {}", self.token_url, e))?;
+        let status = resp.status();
+        let body = resp.text().await?;
+        if !status.is_success() {
+            return Err(anyhow!("Failed to fetch OAuth2 token from {}: {}\n{}", self.token_url, status, body));
+        }
+        let token: TokenResponse = serde_json::from_str(&body).map_err(|e| {
+            anyhow!("Failed to read OAuth2 token from {}: {}\n{}", self.token_url, e, body)
+        })?;
+
+        // 没有expires_in时不知道什么时候过期, 不缓存
+        if let Some(expires_in) = token.expires_in.filter(|secs| *secs > EXPIRY_MARGIN) {
+            let cached = CachedToken {
+                key: self.key(),
+                access_token: token.access_token.clone(),
+                expires_at: unix_now() + expires_in - EXPIRY_MARGIN,
+            };
+            if let Err(e) = self.save(&cached) {
+                if ctx.warnings() {
+                    eprintln!("{}", format!("warning: {}", e).yellow());
+                }
+            }
+        }
+        Ok(token.access_token)
+    }
+
+    fn save(&self, cached: &CachedToken) -> Result<()> {
+        fs::create_dir_all(&self.dir)
+            .map_err(|e| anyhow!("Failed to create {}: {}", self.dir.display(), e))?;
+        let path = hashed_path(&self.dir, &cached.key);
+        write_private_file(&path, serde_json::to_string(cached)?.as_bytes())
+            .map_err(|e| anyhow!("Failed to write {}: {}", path.display(), e))
+    }
+}
+
+/// 缓存的token放在配置目录下的 oauth2-tokens 中
+pub(crate) fn oauth2_token_dir() -> Result<PathBuf> {
+    Ok(config_dir()?.join("oauth2-tokens"))
+}
@@ -0,0 +1,1214 @@
+use std::{
+    fs::OpenOptions,
+    io::{self, IsTerminal, Read, Write},
+    path::Path,
+    sync::OnceLock,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Result};
+use colored::{ColoredString, Colorize};
+use encoding_rs::Encoding;
+use futures_util::StreamExt;
+use indicatif::HumanBytes;
+use mime::Mime;
+use reqwest::{header, Response, StatusCode, Url, Version};
+use serde::Serialize;
+use serde_json::Value;
+use syntect::{
+    easy::HighlightLines,
+    highlighting::ThemeSet,
+    parsing::SyntaxSet,
+    util::{as_24_bit_terminal_escaped, LinesWithEndings},
+};
+
+use crate::{cli::*, download::*, filter::*, request::*, sse::*, timings::*};
+
+/// 打印的目标, body写到它本身, 状态行, 请求行和头部写到 meta()
+/// 这样输出到终端时和以前一样, 重定向stdout时只有body进入管道
+pub trait Output: Write {
+    fn meta(&mut self) -> &mut dyn Write;
+}
+
+/// 写到内存或者丢弃时不区分body和其他信息
+impl Output for Vec<u8> {
+    fn meta(&mut self) -> &mut dyn Write {
+        self
+    }
+}
+
+impl Output for io::Sink {
+    fn meta(&mut self) -> &mut dyn Write {
+        self
+    }
+}
+
+/// 命令行使用的输出: body写到stdout, 其他信息写到stderr
+pub struct StdOutput {
+    stdout: io::Stdout,
+    stderr: io::Stderr,
+}
+
+impl StdOutput {
+    pub fn new() -> Self {
+        Self { stdout: io::stdout(), stderr: io::stderr() }
+    }
+}
+
+impl Default for StdOutput {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Write for StdOutput {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.stdout.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stdout.flush()
+    }
+}
+
+impl Output for StdOutput {
+    fn meta(&mut self) -> &mut dyn Write {
+        // 两个都是终端时保持输出的顺序
+        let _ = self.stdout.flush();
+        &mut self.stderr
+    }
+}
+
+// 跟随了重定向时打印最终请求的地址
+pub(crate) fn print_final_url(out: &mut impl Output, resp: &Response) -> io::Result<()> {
+    writeln!(out.meta(), "{} {}\n", "Final URL:".dimmed(), resp.url())
+}
+
+// 打印服务器版本号 + 状态码
+pub(crate) fn print_status(out: &mut impl Output, resp: &Response) -> io::Result<()> {
+    let version = format!("{:?}", resp.version()).dimmed();
+    writeln!(out.meta(), "{} {}\n", version, styled_status(resp.status()))
+}
+
+/// 状态码和原因短语按类别着色: 2xx绿色, 1xx和3xx青色, 4xx黄色, 5xx红色粗体
+pub(crate) fn styled_status(status: StatusCode) -> ColoredString {
+    let text = status.to_string();
+    match status.as_u16() {
+        200..=299 => text.green(),
+        400..=499 => text.yellow(),
+        500..=599 => text.red().bold(),
+        _ => text.cyan(),
+    }
+}
+
+// 打印服务器返回的HTTP header
+pub(crate) fn print_headers(out: &mut impl Output, resp: &Response) -> io::Result<()> {
+    let out = out.meta();
+    for (name, value) in resp.headers() {
+        writeln!(out, "{}: {:?}", name.to_string().green(), value)?;
+    }
+
+    writeln!(out)
+}
+
+/// 打印服务器返回的HTTP body
+pub(crate) fn print_body(
+    ctx: &Context,
+    out: &mut impl Write,
+    m: Option<Mime>,
+    body: &str,
+) -> io::Result<()> {
+    // 先格式化, 再根据类型选择高亮时使用的语法
+    // --pretty colors 时只高亮不格式化, body除了颜色之外和收到的完全一样
+    let format = ctx.pretty.format();
+    let (body, syntax) = match m {
+        Some(v) if is_json(&v) && !format => (body.to_string(), Some("json")),
+        Some(v) if is_xml(&v) && !format => (body.to_string(), Some("xml")),
+        // 对于JSON类型 pretty print, 不是合法的JSON时原样输出
+        Some(v) if is_json(&v) => match format_json(ctx, body) {
+            // 204 之类没有body的响应不需要输出空行
+            Ok(body) if body.is_empty() => return Ok(()),
+            Ok(body) => (body, Some("json")),
+            Err(_) => {
+                if ctx.warnings() {
+                    let warning =
+                        "warning: Content-Type is application/json but body is not valid JSON";
+                    eprintln!("{}", warning.yellow());
+                }
+                (body.to_string(), None)
+            }
+        },
+        // text/xml, application/xml 以及 application/rss+xml 这种带 +xml 后缀的类型
+        Some(v) if is_xml(&v) => {
+            // XML格式有问题时原样输出, 而不是报错
+            (pretty_print_xml(body).unwrap_or_else(|_| body.to_string()), Some("xml"))
+        }
+        Some(v) if v.subtype() == mime::HTML => (body.to_string(), Some("html")),
+        Some(v) if v.subtype() == mime::JAVASCRIPT => (body.to_string(), Some("js")),
+        Some(v) if v.subtype() == mime::CSS => (body.to_string(), Some("css")),
+        _ => (body.to_string(), None),
+    };
+
+    let highlighted = match syntax {
+        // 太大的body高亮要花好几秒, 直接输出
+        Some(syntax)
+            if body.len() <= MAX_HIGHLIGHT_SIZE
+                && ctx.pretty.colors()
+                && colored::control::SHOULD_COLORIZE.should_colorize() =>
+        {
+            highlight(&body, syntax, &ctx.style)
+        }
+        _ => None,
+    };
+    writeln!(out, "{}", highlighted.unwrap_or(body))
+}
+
+/// 超过这个大小的body不做语法高亮
+pub(crate) const MAX_HIGHLIGHT_SIZE: usize = 2 * 1024 * 1024;
+
+/// 可以通过 --style 选择的主题, 都是syntect自带的
+pub(crate) const DEFAULT_STYLE: &str = "base16-ocean.dark";
+
+pub(crate) const STYLES: &[&str] = &[
+    "base16-ocean.dark",
+    "base16-eighties.dark",
+    "base16-mocha.dark",
+    "base16-ocean.light",
+    "InspiredGitHub",
+    "Solarized (dark)",
+    "Solarized (light)",
+];
+
+/// syntect自带的语法和主题, 反序列化要几十毫秒, 第一次高亮时加载, 之后 --watch, sse, ws 等每次都复用
+static SYNTAXES: OnceLock<SyntaxSet> = OnceLock::new();
+static THEMES: OnceLock<ThemeSet> = OnceLock::new();
+
+/// 使用syntect对body做语法高亮, 找不到语法或者主题时返回None
+pub(crate) fn highlight(body: &str, extension: &str, style: &str) -> Option<String> {
+    let syntaxes = SYNTAXES.get_or_init(SyntaxSet::load_defaults_newlines);
+    let themes = THEMES.get_or_init(ThemeSet::load_defaults);
+    let syntax = syntaxes.find_syntax_by_extension(extension)?;
+    let mut lines = HighlightLines::new(syntax, themes.themes.get(style)?);
+
+    let mut output = String::new();
+    for line in LinesWithEndings::from(body) {
+        let ranges = lines.highlight_line(line, syntaxes).ok()?;
+        output.push_str(&as_24_bit_terminal_escaped(&ranges, false));
+    }
+    // 恢复终端的默认颜色
+    output.push_str("\x1b[0m");
+    Some(output)
+}
+
+/// 打印要发送的body, 打印的是实际发送的字节, 而不是重新格式化后的内容
+/// --compress 压缩过的body打印解压后的内容, 并给出压缩前后的大小
+pub(crate) fn print_request_body(out: &mut impl Output, req: &reqwest::Request) -> io::Result<()> {
+    let encoding = req.headers().get(header::CONTENT_ENCODING).and_then(|v| v.to_str().ok());
+    match req.body().map(|body| body.as_bytes()) {
+        Some(Some(bytes)) if !bytes.is_empty() => match encoding.map(|e| (e, decode_body(e, bytes))) {
+            Some((encoding, Ok(decoded))) => {
+                let note = format!(
+                    "[{} compressed body, {} bytes, {} bytes after compression]",
+                    encoding,
+                    decoded.len(),
+                    bytes.len()
+                );
+                writeln!(out.meta(), "{}", note.dimmed())?;
+                writeln!(out, "{}\n", String::from_utf8_lossy(&decoded))
+            }
+            _ => writeln!(out, "{}\n", String::from_utf8_lossy(bytes)),
+        },
+        Some(None) => writeln!(out, "{}\n", "[streamed body, not shown]".dimmed()),
+        _ => Ok(()),
+    }
+}
+
+/// 格式化JSON, 可以有多个JSON值, 例如每行一个JSON的NDJSON, 空的body得到空字符串
+/// indent为空时每个值输出为一行, sort时对象的key按字典序排列, 数组的顺序不变
+/// serde_json开启了arbitrary_precision和preserve_order, 大整数, 高精度的小数和key的顺序都保持不变
+pub(crate) fn pretty_print_json(body: &str, indent: &str, sort: bool) -> Result<String> {
+    let mut values = Vec::new();
+    for value in serde_json::Deserializer::from_str(body).into_iter::<Value>() {
+        let mut value = value?;
+        if sort {
+            sort_keys(&mut value);
+        }
+        if indent.is_empty() {
+            values.push(serde_json::to_string(&value)?);
+            continue;
+        }
+        let mut buf = Vec::new();
+        let formatter = serde_json::ser::PrettyFormatter::with_indent(indent.as_bytes());
+        value.serialize(&mut serde_json::Serializer::with_formatter(&mut buf, formatter))?;
+        values.push(String::from_utf8(buf)?);
+    }
+    Ok(values.join("\n"))
+}
+
+/// 按照 --indent, --tab 和 --sort-keys 格式化JSON
+pub(crate) fn format_json(ctx: &Context, body: &str) -> Result<String> {
+    pretty_print_json(body, &ctx.json_indent, ctx.sort_keys)
+}
+
+fn sort_keys(value: &mut Value) {
+    match value {
+        Value::Object(object) => {
+            object.sort_keys();
+            object.values_mut().for_each(sort_keys);
+        }
+        Value::Array(items) => items.iter_mut().for_each(sort_keys),
+        _ => {}
+    }
+}
+
+/// 格式化XML, 每个元素一行并缩进, 声明, CDATA和注释的内容保持不变
+///
+/// 标签不匹配或者没有闭合 (例如被截断的响应) 时返回错误
+pub(crate) fn pretty_print_xml(body: &str) -> Result<String> {
+    use quick_xml::events::Event;
+    let mut reader = quick_xml::Reader::from_str(body);
+    reader.trim_text(true);
+    let mut writer = quick_xml::Writer::new_with_indent(Vec::new(), b' ', 2);
+    let mut depth = 0usize;
+    loop {
+        let event = reader.read_event()?;
+        match event {
+            Event::Eof if depth > 0 => return Err(anyhow!("Unclosed XML element")),
+            Event::Eof => break,
+            Event::Start(_) => depth += 1,
+            Event::End(_) => depth -= 1,
+            _ => {}
+        }
+        writer.write_event(event)?;
+    }
+    Ok(String::from_utf8(writer.into_inner())?)
+}
+
+/// --dump-headers: 按照HTTP报文的格式写入状态行和响应头, 每个响应之后有一个空行
+/// 中间的重定向响应只有状态行和Location, 版本和最终的响应相同
+pub(crate) fn dump_headers(
+    path: &Path,
+    append: bool,
+    redirects: &[(StatusCode, Url)],
+    resp: &Response,
+) -> Result<()> {
+    let mut content = Vec::new();
+    for (status, location) in redirects {
+        write!(content, "{:?} {}\r\nlocation: {}\r\n\r\n", resp.version(), status, location)?;
+    }
+    write!(content, "{:?} {}\r\n", resp.version(), resp.status())?;
+    for (name, value) in resp.headers() {
+        content.extend_from_slice(name.as_str().as_bytes());
+        content.extend_from_slice(b": ");
+        content.extend_from_slice(value.as_bytes());
+        content.extend_from_slice(b"\r\n");
+    }
+    content.extend_from_slice(b"\r\n");
+    OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(append)
+        .truncate(!append)
+        .open(path)
+        .and_then(|mut file| file.write_all(&content))
+        .map_err(|e| anyhow!("Failed to write {}: {}", path.display(), e))
+}
+
+// 打印状态码和header
+pub(crate) fn print_head(out: &mut impl Output, resp: &Response) -> io::Result<()> {
+    print_status(out, resp)?;
+    print_headers(out, resp)
+}
+
+// 在header之后单独汇总 Allow 和 CORS 相关的header, 方便调试预检请求
+pub(crate) fn print_cors(out: &mut impl Output, resp: &Response) -> io::Result<()> {
+    let out = out.meta();
+    let names = [
+        header::ALLOW,
+        header::ACCESS_CONTROL_ALLOW_ORIGIN,
+        header::ACCESS_CONTROL_ALLOW_METHODS,
+        header::ACCESS_CONTROL_ALLOW_HEADERS,
+        header::ACCESS_CONTROL_ALLOW_CREDENTIALS,
+        header::ACCESS_CONTROL_MAX_AGE,
+    ];
+
+    writeln!(out, "{}", "CORS:".yellow().bold())?;
+    for name in names.iter() {
+        let value = resp
+            .headers()
+            .get_all(name)
+            .iter()
+            .map(|v| v.to_str().unwrap_or("<non-ascii>"))
+            .collect::<Vec<_>>();
+        let value = if value.is_empty() {
+            "-".dimmed().to_string()
+        } else {
+            value.join(", ").yellow().to_string()
+        };
+        writeln!(out, "  {}: {}", name.to_string().yellow(), value)?;
+    }
+
+    writeln!(out)
+}
+
+pub(crate) async fn print_resp(ctx: &Context, out: &mut impl Output, resp: Response) -> Result<()> {
+    let meta = Meta::new(ctx, &resp);
+    let status = resp.status();
+    if ctx.print.response_headers {
+        print_head(out, &resp)?;
+    }
+    // 使用 -o 时总是保存body
+    let mut size = 0;
+    if ctx.print.response_body || ctx.output.is_some() {
+        size = print_resp_body(ctx, out, resp).await?;
+    } else if ctx.records_body() {
+        record_unprinted_body(ctx, resp).await?;
+    }
+    meta.print(size);
+    ctx.check_status(status)
+}
+
+/// 读取整个body并按照Content-Encoding解压, --har 时同时记录下来
+pub(crate) async fn read_body(ctx: &Context, resp: Response) -> Result<Vec<u8>> {
+    let encoding = content_encoding(&resp).filter(|_| ctx.compression != Compression::None);
+    let body = read_raw_body(ctx, resp).await?;
+    match encoding {
+        Some(encoding) => decode_body(&encoding, &body),
+        None => Ok(body.to_vec()),
+    }
+}
+
+/// 读取整个body但不解压, 受 --max-body-size 限制
+async fn read_raw_body(ctx: &Context, mut resp: Response) -> Result<Vec<u8>> {
+    let mut limit = BodyLimit::new(ctx, &resp)?;
+    let mut body = Vec::new();
+    while let Some(chunk) = resp.chunk().await? {
+        ctx.record_body(&chunk);
+        limit.add(chunk.len())?;
+        body.extend_from_slice(&chunk);
+    }
+    Ok(body)
+}
+
+/// --max-body-size: 有Content-Length时在读取之前检查, 否则一边接收一边统计
+/// 统计的是收到的字节数, 压缩的body按照压缩后的大小计算
+pub(crate) struct BodyLimit {
+    max: Option<u64>,
+    received: u64,
+}
+
+impl BodyLimit {
+    pub(crate) fn new(ctx: &Context, resp: &Response) -> Result<Self> {
+        let max = ctx.max_body_size;
+        // --show-partial 时要先收到一部分, 不能直接拒绝
+        if let (Some(max), Some(len), false) = (max, resp.content_length(), ctx.show_partial) {
+            if len > max {
+                return Err(anyhow!(
+                    "Response body is {} bytes, larger than --max-body-size {} bytes\n\
+                     Save it with -o/--download, or use --max-body-size 0 to print it anyway",
+                    len,
+                    max
+                ));
+            }
+        }
+        Ok(Self { max, received: 0 })
+    }
+
+    /// 接下来的len个字节中还有多少在上限之内
+    pub(crate) fn within(&self, len: usize) -> usize {
+        match self.max {
+            Some(max) => len.min(max.saturating_sub(self.received) as usize),
+            None => len,
+        }
+    }
+
+    pub(crate) fn add(&mut self, len: usize) -> Result<()> {
+        self.received += len as u64;
+        match self.max {
+            Some(max) if self.received > max => Err(anyhow!(
+                "Aborted after receiving {} bytes, the body is larger than --max-body-size {} bytes\n\
+                 Save it with -o/--download, or use --max-body-size 0 to print it anyway \
+                 (--show-partial prints what was received)",
+                self.received,
+                max
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// 不打印body时 --har 仍然要记录它
+pub(crate) async fn record_unprinted_body(ctx: &Context, mut resp: Response) -> Result<()> {
+    while let Some(chunk) = resp.chunk().await? {
+        ctx.record_body(&chunk);
+    }
+    Ok(())
+}
+
+/// send 中记录的请求开始的时间和收到响应头时的耗时
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Timing {
+    pub(crate) start: Instant,
+    pub(crate) first_byte: Duration,
+}
+
+/// --meta 打印的信息, 要在读取body之前从resp中取出来
+pub(crate) struct Meta {
+    pub(crate) enabled: bool,
+    pub(crate) timing: Option<Timing>,
+    /// --timings 记录的各个阶段, 以及是否输出为JSON
+    pub(crate) phases: Option<(PhaseTimings, bool)>,
+    pub(crate) version: Version,
+    pub(crate) remote: String,
+    /// --verbose 时只打印实际连接的地址, 确认 -4 / -6 生效了
+    pub(crate) show_remote: bool,
+}
+
+impl Meta {
+    pub(crate) fn new(ctx: &Context, resp: &Response) -> Self {
+        let addr = resp.remote_addr().or_else(|| Some(resp.extensions().get::<PhaseTimings>()?.remote));
+        let remote = match (&ctx.unix_socket, addr) {
+            (Some(path), _) => path.display().to_string(),
+            (None, Some(addr)) => addr.to_string(),
+            (None, None) => "-".to_string(),
+        };
+        Self {
+            enabled: ctx.meta && ctx.quiet == 0,
+            timing: resp.extensions().get::<Timing>().copied(),
+            phases: ctx
+                .timings
+                .as_ref()
+                .and_then(|timer| Some((*resp.extensions().get::<PhaseTimings>()?, timer.json))),
+            version: resp.version(),
+            remote,
+            show_remote: ctx.verbose && ctx.quiet == 0,
+        }
+    }
+
+    /// 读取完body后打印, size是收到的body的字节数
+    pub(crate) fn print(&self, size: u64) {
+        if let Some((phases, json)) = &self.phases {
+            eprintln!("{}", format_timings(phases, phases.start.elapsed(), *json));
+        }
+        if !self.enabled {
+            if self.show_remote {
+                eprintln!("\n{}", format!("Remote address: {}", self.remote).dimmed());
+            }
+            return;
+        }
+        let mut lines = Vec::new();
+        if let Some(timing) = self.timing {
+            let total = timing.start.elapsed();
+            lines.push(format!(
+                "Elapsed time: {:.3}s (first byte {:.3}s, download {:.3}s)",
+                total.as_secs_f64(),
+                timing.first_byte.as_secs_f64(),
+                total.saturating_sub(timing.first_byte).as_secs_f64()
+            ));
+        }
+        lines.push(format!("Body size: {} bytes", size));
+        lines.push(format!("HTTP version: {:?}", self.version));
+        lines.push(format!("Remote address: {}", self.remote));
+        eprintln!();
+        for line in lines {
+            eprintln!("{}", line.dimmed());
+        }
+    }
+}
+
+// 读取并打印body, 返回body的字节数
+pub(crate) async fn print_resp_body(
+    ctx: &Context,
+    out: &mut impl Write,
+    resp: Response,
+) -> Result<u64> {
+    // 出错时服务器返回的多半是错误信息, 直接打印出来而不是保存到文件中
+    // 继续下载时416表示文件可能已经下载完了
+    let resuming = ctx.resume.as_ref().is_some_and(|r| r.offset > 0)
+        && resp.status() == StatusCode::RANGE_NOT_SATISFIABLE;
+    if ctx.download && (resp.status().is_success() || resuming) {
+        return download(ctx, resp).await;
+    }
+    if let Some(path) = &ctx.output {
+        return save_body(ctx, path, resp).await;
+    }
+    if let Some(filter) = &ctx.filter {
+        return print_filtered(ctx, out, filter, resp).await;
+    }
+    if ctx.stream || is_event_stream(&resp) {
+        return print_events(ctx, out, resp).await;
+    }
+
+    let mime = get_content_type(&resp).unwrap_or_else(|e| {
+        if ctx.warnings() {
+            eprintln!("{}", format!("warning: {}", e).yellow());
+        }
+        None
+    });
+    // 需要格式化或者语法高亮时先把body读到内存中, 其他情况收到第一块数据就开始输出
+    let limit = if needs_formatting(ctx, mime.as_ref()) { ctx.pretty_limit } else { 0 };
+    let mut printer = BodyPrinter::new(ctx, out, mime, limit);
+    let encoding = match content_encoding(&resp) {
+        Some(encoding) if ctx.compression == Compression::None => {
+            printer.skip(format!("{} compressed body", encoding));
+            None
+        }
+        encoding => encoding,
+    };
+
+    // 有多个编码时很少见, 这时还是先读取整个body再解压
+    if let Some(encoding) = encoding.as_deref().filter(|v| v.contains(',')) {
+        let body = read_raw_body(ctx, resp).await?;
+        printer.write_all(&decode_body(encoding, &body)?)?;
+        return printer.finish();
+    }
+    let mut limit = BodyLimit::new(ctx, &resp)?;
+    let mut decoder = StreamDecoder::new(encoding.as_deref(), printer)?;
+    let mut stream = resp.bytes_stream();
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk?;
+        ctx.record_body(&chunk);
+        let within = limit.within(chunk.len());
+        if let Err(e) = limit.add(chunk.len()) {
+            if ctx.show_partial {
+                // 输出上限之内的部分, 压缩数据不完整导致的错误不用管
+                let _ = decoder.write_all(&chunk[..within]).and_then(|_| decoder.flush());
+                decoder.get_mut().finish_partial()?;
+            }
+            return Err(e);
+        }
+        decoder.write_all(&chunk)?;
+    }
+    decoder.finish()?.finish()
+}
+
+/// 是否需要格式化或者语法高亮, 和 print_body 中的判断保持一致
+pub(crate) fn needs_formatting(ctx: &Context, mime: Option<&Mime>) -> bool {
+    let colors = ctx.pretty.colors() && colored::control::SHOULD_COLORIZE.should_colorize();
+    match mime {
+        Some(v) if is_json(v) || is_xml(v) => ctx.pretty.format() || colors,
+        Some(v) if v.subtype() == mime::HTML
+            || v.subtype() == mime::JAVASCRIPT
+            || v.subtype() == mime::CSS =>
+        {
+            colors
+        }
+        _ => false,
+    }
+}
+
+/// text/xml, application/xml 以及 application/rss+xml 这种带 +xml 后缀的类型
+pub(crate) fn is_xml(mime: &Mime) -> bool {
+    mime.subtype() == mime::XML || mime.suffix() == Some(mime::XML)
+}
+
+/// 按照Content-Encoding边下载边解压, 只支持一种编码
+pub(crate) enum StreamDecoder<W: Write> {
+    Identity(W),
+    Gzip(flate2::write::GzDecoder<W>),
+    Deflate(flate2::write::ZlibDecoder<W>),
+    Br(Box<brotli::DecompressorWriter<W>>),
+    Zstd(zstd::stream::write::Decoder<'static, W>),
+}
+
+impl<W: Write> StreamDecoder<W> {
+    pub(crate) fn new(encoding: Option<&str>, inner: W) -> Result<Self> {
+        let encoding = encoding.map(|v| v.trim().to_ascii_lowercase()).unwrap_or_default();
+        Ok(match encoding.as_str() {
+            "" | "identity" => Self::Identity(inner),
+            "gzip" | "x-gzip" => Self::Gzip(flate2::write::GzDecoder::new(inner)),
+            "deflate" => Self::Deflate(flate2::write::ZlibDecoder::new(inner)),
+            "br" => Self::Br(Box::new(brotli::DecompressorWriter::new(inner, 4096))),
+            "zstd" => Self::Zstd(zstd::stream::write::Decoder::new(inner)?),
+            _ => return Err(anyhow!("Unsupported Content-Encoding {}", encoding)),
+        })
+    }
+
+    pub(crate) fn get_mut(&mut self) -> &mut W {
+        match self {
+            Self::Identity(inner) => inner,
+            Self::Gzip(decoder) => decoder.get_mut(),
+            Self::Deflate(decoder) => decoder.get_mut(),
+            Self::Br(decoder) => decoder.get_mut(),
+            Self::Zstd(decoder) => decoder.get_mut(),
+        }
+    }
+
+    /// 解压剩下的数据并返回里面的writer
+    pub(crate) fn finish(self) -> Result<W> {
+        let truncated = |encoding| anyhow!("Failed to decode {} body: unexpected end of data", encoding);
+        match self {
+            Self::Identity(inner) => Ok(inner),
+            Self::Gzip(decoder) => Ok(decoder.finish()?),
+            Self::Deflate(decoder) => Ok(decoder.finish()?),
+            Self::Br(mut decoder) => {
+                decoder.flush()?;
+                decoder.into_inner().map_err(|_| truncated("br"))
+            }
+            Self::Zstd(mut decoder) => {
+                decoder.flush()?;
+                Ok(decoder.into_inner())
+            }
+        }
+    }
+}
+
+impl<W: Write> Write for StreamDecoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Self::Identity(inner) => inner.write(buf),
+            Self::Gzip(decoder) => decoder.write(buf),
+            Self::Deflate(decoder) => decoder.write(buf),
+            Self::Br(decoder) => decoder.write(buf),
+            Self::Zstd(decoder) => decoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Self::Identity(inner) => inner.flush(),
+            Self::Gzip(decoder) => decoder.flush(),
+            Self::Deflate(decoder) => decoder.flush(),
+            Self::Br(decoder) => decoder.flush(),
+            Self::Zstd(decoder) => decoder.flush(),
+        }
+    }
+}
+
+/// 逐块接收解压后的body并输出到out
+///
+/// 开始时先缓存body, 缓存的大小超过limit后就根据已有的内容判断是文本还是二进制,
+/// 然后改为一边接收一边输出, 所以内存的使用是有上限的
+/// 整个body都在limit之内时才会格式化和语法高亮
+pub(crate) struct BodyPrinter<'a, W: Write> {
+    pub(crate) ctx: &'a Context,
+    pub(crate) out: &'a mut W,
+    pub(crate) mime: Option<Mime>,
+    pub(crate) limit: usize,
+    pub(crate) buffer: Vec<u8>,
+    pub(crate) state: PrintState,
+    /// 收到的body的总字节数
+    pub(crate) size: u64,
+}
+
+pub(crate) enum PrintState {
+    Buffering,
+    /// 按照编码解码后输出
+    Text {
+        decoder: encoding_rs::Decoder,
+        had_errors: bool,
+        ends_with_newline: bool,
+    },
+    /// 二进制数据, 输出到终端时只统计大小, 否则原样输出
+    Binary { size: u64, raw: bool, what: String },
+}
+
+impl<'a, W: Write> BodyPrinter<'a, W> {
+    pub(crate) fn new(ctx: &'a Context, out: &'a mut W, mime: Option<Mime>, limit: usize) -> Self {
+        Self {
+            ctx,
+            out,
+            mime,
+            limit,
+            buffer: Vec::new(),
+            state: PrintState::Buffering,
+            size: 0,
+        }
+    }
+
+    /// 不输出body, 只在最后给出大小
+    pub(crate) fn skip(&mut self, what: String) {
+        self.state = PrintState::Binary { size: 0, raw: false, what };
+    }
+
+    /// 根据缓存的内容决定怎样输出, 然后输出缓存的内容
+    pub(crate) fn start_streaming(&mut self) -> io::Result<()> {
+        let buffer = std::mem::take(&mut self.buffer);
+        let mime = self.mime.as_ref();
+        let sniffed = match self.ctx.compression {
+            // 不解压时, 服务器没有返回Content-Encoding也根据body开头的几个字节猜一下
+            Compression::None => sniff_encoding(&buffer),
+            _ => None,
+        };
+        self.state = if let Some(encoding) = sniffed {
+            let what = format!("{} compressed body", encoding);
+            PrintState::Binary { size: 0, raw: false, what }
+        } else if is_binary(mime, &buffer) {
+            let raw = !io::stdout().is_terminal();
+            PrintState::Binary { size: 0, raw, what: "binary data".to_string() }
+        } else {
+            if self.limit > 0 {
+                let note = format!(
+                    "body is larger than {}, printing it without formatting (--pretty-limit)",
+                    HumanBytes(self.limit as u64)
+                );
+                eprintln!("{}", note.dimmed());
+            }
+            let encoding = text_encoding(&buffer, mime, self.ctx.charset);
+            PrintState::Text {
+                decoder: encoding.new_decoder(),
+                had_errors: false,
+                ends_with_newline: true,
+            }
+        };
+        self.output(&buffer, false)
+    }
+
+    pub(crate) fn output(&mut self, data: &[u8], last: bool) -> io::Result<()> {
+        match &mut self.state {
+            PrintState::Buffering => self.buffer.extend_from_slice(data),
+            PrintState::Text { decoder, had_errors, ends_with_newline } => {
+                let mut text = String::with_capacity(data.len() + 16);
+                let mut rest = data;
+                loop {
+                    let (result, read, errors) = decoder.decode_to_string(rest, &mut text, last);
+                    *had_errors |= errors;
+                    rest = &rest[read..];
+                    if result == encoding_rs::CoderResult::InputEmpty {
+                        break;
+                    }
+                    // 输出缓冲区满了, 先输出一部分
+                    self.out.write_all(text.as_bytes())?;
+                    text.clear();
+                    text.reserve(rest.len() + 16);
+                }
+                if !text.is_empty() {
+                    *ends_with_newline = text.ends_with('\n');
+                    self.out.write_all(text.as_bytes())?;
+                }
+            }
+            PrintState::Binary { size, raw, .. } => {
+                *size += data.len() as u64;
+                if *raw {
+                    self.out.write_all(data)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn finish(mut self) -> Result<u64> {
+        self.complete()
+    }
+
+    /// --max-body-size 中止时输出已经收到的部分, 不完整的body不格式化
+    pub(crate) fn finish_partial(&mut self) -> Result<u64> {
+        if matches!(self.state, PrintState::Buffering) {
+            self.limit = 0;
+            self.start_streaming()?;
+        }
+        self.complete()
+    }
+
+    fn complete(&mut self) -> Result<u64> {
+        match &self.state {
+            // 整个body都在缓存中, 可以格式化
+            PrintState::Buffering => {
+                let mime = self.mime.as_ref();
+                let compressed = self.ctx.compression == Compression::None
+                    && sniff_encoding(&self.buffer).is_some();
+                if compressed || is_binary(mime, &self.buffer) {
+                    self.start_streaming()?;
+                    return self.complete();
+                }
+                let text = decode_text(&self.buffer, mime, self.ctx.charset);
+                print_body(self.ctx, self.out, self.mime.clone(), &text)?;
+                return Ok(self.size);
+            }
+            PrintState::Text { .. } => self.output(&[], true)?,
+            PrintState::Binary { .. } => {}
+        }
+
+        match &self.state {
+            PrintState::Text { had_errors, ends_with_newline, decoder } => {
+                if !ends_with_newline {
+                    writeln!(self.out)?;
+                }
+                if *had_errors {
+                    let warning = format!(
+                        "warning: the body is not valid {}, invalid bytes are replaced with U+FFFD",
+                        decoder.encoding().name()
+                    );
+                    eprintln!("{}", warning.yellow());
+                }
+            }
+            PrintState::Binary { size, raw: false, what } => {
+                let note = if what == "binary data" {
+                    format!("+ binary data, {} bytes, use -o to save +", size)
+                } else {
+                    format!("[{}, {} bytes, not decompressed (--compress-response none)]", what, size)
+                };
+                writeln!(self.out, "{}", note.dimmed())?;
+            }
+            _ => {}
+        }
+        self.out.flush()?;
+        Ok(self.size)
+    }
+}
+
+impl<W: Write> Write for BodyPrinter<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.size += buf.len() as u64;
+        self.output(buf, false)?;
+        if matches!(self.state, PrintState::Buffering) && self.buffer.len() > self.limit {
+            // 不需要格式化时limit为0, 用第一块数据判断是不是二进制
+            self.start_streaming()?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+/// 根据Content-Type和body的内容判断是不是二进制数据
+pub(crate) fn is_binary(mime: Option<&Mime>, body: &[u8]) -> bool {
+    if let Some(mime) = mime {
+        match (mime.type_(), mime.subtype().as_str()) {
+            // svg 是XML格式的文本
+            (mime::IMAGE, "svg") => return false,
+            (mime::IMAGE, _) | (mime::AUDIO, _) | (mime::VIDEO, _) | (mime::FONT, _) => {
+                return true
+            }
+            (mime::APPLICATION, "octet-stream" | "pdf" | "zip" | "gzip" | "x-tar" | "wasm") => {
+                return true
+            }
+            _ => {}
+        }
+        // UTF-16的文本中会有很多NUL
+        let charset = mime.get_param(mime::CHARSET).map(|v| v.as_str().to_ascii_lowercase());
+        if charset.is_some_and(|v| v.starts_with("utf-16")) {
+            return false;
+        }
+    }
+
+    // 只检查开头的一部分, 有NUL或者控制字符太多时认为是二进制数据
+    let head = &body[..body.len().min(1024)];
+    let controls = head
+        .iter()
+        .filter(|&&b| b < 0x20 && !matches!(b, b'\t' | b'\n' | b'\r' | 0x0c | 0x1b))
+        .count();
+    head.contains(&0) || controls * 10 > head.len()
+}
+
+/// 把body转换成字符串, 使用的编码依次为: --response-charset, Content-Type中的charset,
+/// HTML中 <meta> 标签指定的charset, 默认为UTF-8
+pub(crate) fn decode_text(body: &[u8], mime: Option<&Mime>, forced: Option<&'static Encoding>) -> String {
+    let encoding = text_encoding(body, mime, forced);
+
+    // 开头有BOM时以BOM为准
+    let (text, encoding, had_errors) = encoding.decode(body);
+    if had_errors {
+        let warning = format!(
+            "warning: the body is not valid {}, invalid bytes are replaced with U+FFFD",
+            encoding.name()
+        );
+        eprintln!("{}", warning.yellow());
+    }
+    text.into_owned()
+}
+
+/// 决定body使用的编码, 只会用到body开头的部分
+pub(crate) fn text_encoding(
+    body: &[u8],
+    mime: Option<&Mime>,
+    forced: Option<&'static Encoding>,
+) -> &'static Encoding {
+    let from_header = || {
+        mime.and_then(|mime| mime.get_param(mime::CHARSET))
+            .and_then(|charset| Encoding::for_label(charset.as_str().as_bytes()))
+    };
+    let from_meta = || match mime {
+        Some(mime) if mime.subtype() == mime::HTML => meta_charset(body),
+        _ => None,
+    };
+    forced.or_else(from_header).or_else(from_meta).unwrap_or(encoding_rs::UTF_8)
+}
+
+/// 从HTML开头的 <meta charset="..."> 或者
+/// <meta http-equiv="Content-Type" content="text/html; charset=..."> 中找到charset
+pub(crate) fn meta_charset(body: &[u8]) -> Option<&'static Encoding> {
+    let head = String::from_utf8_lossy(&body[..body.len().min(1024)]).to_ascii_lowercase();
+    head.split("<meta").skip(1).find_map(|tag| {
+        let tag = tag.split('>').next()?;
+        let (_, charset) = tag.split_once("charset=")?;
+        let charset = charset.trim_start_matches(['"', '\'']);
+        let end = charset
+            .find(|c: char| !(c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | ':' | '.')))
+            .unwrap_or(charset.len());
+        Encoding::for_label(&charset.as_bytes()[..end])
+    })
+}
+
+pub(crate) fn content_encoding(resp: &Response) -> Option<String> {
+    resp.headers()
+        .get(header::CONTENT_ENCODING)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.to_string())
+}
+
+/// 根据Content-Encoding解压body, 有多个编码时按照相反的顺序解压
+pub(crate) fn decode_body(encoding: &str, body: &[u8]) -> Result<Vec<u8>> {
+    let mut body = body.to_vec();
+    for encoding in encoding.rsplit(',').map(|v| v.trim().to_ascii_lowercase()) {
+        let mut decoded = Vec::new();
+        let result = match encoding.as_str() {
+            "gzip" | "x-gzip" => flate2::read::GzDecoder::new(&body[..]).read_to_end(&mut decoded),
+            "deflate" => flate2::read::ZlibDecoder::new(&body[..]).read_to_end(&mut decoded),
+            "br" => brotli::Decompressor::new(&body[..], 4096).read_to_end(&mut decoded),
+            "zstd" => zstd::stream::read::Decoder::new(&body[..])?.read_to_end(&mut decoded),
+            "identity" | "" => continue,
+            _ => return Err(anyhow!("Unsupported Content-Encoding {}", encoding)),
+        };
+        result.map_err(|e| anyhow!("Failed to decode {} body: {}", encoding, e))?;
+        body = decoded;
+    }
+    Ok(body)
+}
+
+/// 通过magic number判断body是否被压缩过, brotli没有magic number所以无法判断
+pub(crate) fn sniff_encoding(body: &[u8]) -> Option<&'static str> {
+    if body.starts_with(&[0x1f, 0x8b]) {
+        Some("gzip")
+    } else if body.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+        Some("zstd")
+    } else {
+        None
+    }
+}
+
+/// 得到Content-Type, 没有这个header时为None
+/// 有非ASCII字符或者不是 type/subtype 的格式时返回Err, 调用方把它当作未知的类型
+pub(crate) fn get_content_type(resp: &Response) -> Result<Option<Mime>> {
+    let value = match resp.headers().get(header::CONTENT_TYPE) {
+        Some(value) => value,
+        None => return Ok(None),
+    };
+    match value.to_str().ok().and_then(|v| v.parse().ok()) {
+        Some(mime) => Ok(Some(mime)),
+        None => Err(anyhow!("Invalid Content-Type {:?}, treating the body as plain text", value)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use structopt::StructOpt;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use super::*;
+
+    /// 只响应一次的HTTP服务器, 返回它的URL
+    async fn serve_once(response: &'static [u8]) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buf = [0; 4096];
+            let _ = stream.read(&mut buf).await;
+            stream.write_all(response).await.unwrap();
+        });
+        format!("http://{}/", addr)
+    }
+
+    /// 关闭颜色, 这样输出中没有转义序列
+    fn context(args: &[&str]) -> Context {
+        colored::control::set_override(false);
+        let mut argv = vec!["httpie"];
+        argv.extend_from_slice(args);
+        Context::from_args(&App::from_iter_safe(argv).unwrap()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn print_head_writes_status_and_headers() {
+        colored::control::set_override(false);
+        let response = b"HTTP/1.1 404 Not Found\r\nX-Test: yes\r\nContent-Length: 0\r\n\r\n";
+        let resp = reqwest::get(serve_once(response).await).await.unwrap();
+        let mut out = Vec::new();
+        print_head(&mut out, &resp).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.starts_with("HTTP/1.1 404 Not Found\n\n"));
+        assert!(out.contains("x-test: \"yes\"\n"));
+    }
+
+    #[tokio::test]
+    async fn print_resp_pretty_prints_json_body() {
+        let ctx = context(&["--pretty", "format", "--print", "hb", "get", "localhost"]);
+        let response = b"HTTP/1.1 200 OK\r\nContent-Type: application/json\r\n\
+                         Content-Length: 13\r\n\r\n{\"a\":[1,\"b\"]}";
+        let resp = reqwest::get(serve_once(response).await).await.unwrap();
+        let mut out = Vec::new();
+        print_resp(&ctx, &mut out, resp).await.unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("content-type: \"application/json\"\n"));
+        assert!(out.ends_with("{\n  \"a\": [\n    1,\n    \"b\"\n  ]\n}\n"));
+    }
+
+    #[test]
+    fn pretty_none_and_colors_keep_the_body_as_is() {
+        let body = "<a><b>1</b></a>";
+        for pretty in ["none", "colors"].iter() {
+            let ctx = context(&["--pretty", pretty, "get", "localhost"]);
+            let mut out = Vec::new();
+            print_body(&ctx, &mut out, Some(mime::TEXT_XML), body).unwrap();
+            assert_eq!(out, b"<a><b>1</b></a>\n");
+        }
+        let ctx = context(&["--pretty", "none", "get", "localhost"]);
+        assert!(!needs_formatting(&ctx, Some(&mime::APPLICATION_JSON)));
+        let ctx = context(&["--pretty", "format", "get", "localhost"]);
+        assert!(needs_formatting(&ctx, Some(&mime::TEXT_XML)));
+    }
+
+    #[test]
+    fn status_colors_follow_the_status_class() {
+        use colored::{Color, Styles};
+        let style = |code: u16| {
+            let styled = styled_status(StatusCode::from_u16(code).unwrap());
+            (styled.fgcolor, styled.style.contains(Styles::Bold))
+        };
+        assert_eq!(style(200), (Some(Color::Green), false));
+        assert_eq!(style(101), (Some(Color::Cyan), false));
+        assert_eq!(style(304), (Some(Color::Cyan), false));
+        assert_eq!(style(404), (Some(Color::Yellow), false));
+        assert_eq!(style(503), (Some(Color::Red), true));
+        assert_eq!(styled_status(StatusCode::NOT_FOUND).input, "404 Not Found");
+    }
+
+    #[test]
+    fn json_with_parameters_or_suffix_is_formatted() {
+        let ctx = context(&["--pretty", "format", "--sort-keys", "get", "localhost"]);
+        for mime in ["application/json; charset=utf-8", "application/graphql-response+json"].iter() {
+            let mime = mime.parse::<Mime>().unwrap();
+            assert!(needs_formatting(&ctx, Some(&mime)));
+            let mut out = Vec::new();
+            print_body(&ctx, &mut out, Some(mime), r#"{"b":1,"a":2}"#).unwrap();
+            assert_eq!(out, b"{\n  \"a\": 2,\n  \"b\": 1\n}\n");
+        }
+    }
+
+    #[test]
+    fn print_body_writes_invalid_json_as_is() {
+        let ctx = context(&["-qq", "get", "localhost"]);
+        let mut out = Vec::new();
+        print_body(&ctx, &mut out, Some(mime::APPLICATION_JSON), "not json").unwrap();
+        assert_eq!(out, b"not json\n");
+    }
+
+    #[test]
+    fn body_printer_streams_plain_text() {
+        let ctx = context(&["get", "localhost"]);
+        let mut out = Vec::new();
+        let mut printer = BodyPrinter::new(&ctx, &mut out, Some(mime::TEXT_PLAIN), 0);
+        printer.write_all(b"hello ").unwrap();
+        printer.write_all(b"world").unwrap();
+        assert_eq!(printer.finish().unwrap(), 11);
+        // 没有以换行结尾时补上一个
+        assert_eq!(out, b"hello world\n");
+    }
+
+    async fn content_type_of(response: &'static [u8]) -> Result<Option<Mime>> {
+        let resp = reqwest::get(serve_once(response).await).await.unwrap();
+        get_content_type(&resp)
+    }
+
+    #[tokio::test]
+    async fn content_type_is_parsed() {
+        let response = b"HTTP/1.1 200 OK\r\nContent-Type: application/json; charset=utf-8\r\n\
+                         Content-Length: 2\r\n\r\n{}";
+        let mime = content_type_of(response).await.unwrap().unwrap();
+        assert_eq!(mime.essence_str(), "application/json");
+    }
+
+    #[tokio::test]
+    async fn missing_content_type_is_none() {
+        let response = b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\n{}";
+        assert!(content_type_of(response).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn junk_content_type_is_an_error() {
+        let response = b"HTTP/1.1 200 OK\r\nContent-Type: json\r\nContent-Length: 2\r\n\r\n{}";
+        assert!(content_type_of(response).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn non_ascii_content_type_is_an_error() {
+        let response = b"HTTP/1.1 200 OK\r\nContent-Type: text/\xe4\xbd\xa0\r\n\
+                         Content-Length: 2\r\n\r\nhi";
+        assert!(content_type_of(response).await.is_err());
+    }
+
+    #[test]
+    fn pretty_print_json_rejects_invalid_json() {
+        assert!(pretty_print_json(r#"{"a": 1"#, "  ", false).is_err());
+        assert!(pretty_print_json("<html>502 Bad Gateway</html>", "  ", false).is_err());
+    }
+
+    #[test]
+    fn pretty_print_json_of_empty_body_is_empty() {
+        assert_eq!(pretty_print_json("", "  ", false).unwrap(), "");
+        assert_eq!(pretty_print_json(" \n", "  ", false).unwrap(), "");
+    }
+
+    #[test]
+    fn pretty_print_json_formats_huge_single_line() {
+        let items = (0..100_000).map(|i| i.to_string()).collect::<Vec<_>>();
+        let body = format!("[{}]", items.join(","));
+        let pretty = pretty_print_json(&body, "  ", false).unwrap();
+        assert_eq!(pretty.lines().count(), 100_002);
+        assert_eq!(pretty.lines().nth(1).map(str::trim), Some("0,"));
+    }
+
+    #[test]
+    fn sorted_json_keeps_array_order_and_number_precision() {
+        let body = r#"{"b": [3, 1], "a": {"d": 18446744073709551616123, "c": 0.10000000000000000555}}"#;
+        let expected = r#"{
+  "a": {
+    "c": 0.10000000000000000555,
+    "d": 18446744073709551616123
+  },
+  "b": [
+    3,
+    1
+  ]
+}"#;
+        assert_eq!(pretty_print_json(body, "  ", true).unwrap(), expected);
+        assert_eq!(pretty_print_json("{\"b\":1,\"a\":2}\n[]", "  ", true).unwrap(), "{\n  \"a\": 2,\n  \"b\": 1\n}\n[]");
+        assert!(pretty_print_json(r#"{"a": 1"#, "  ", true).is_err());
+    }
+
+    #[test]
+    fn pretty_print_xml_keeps_declaration_cdata_and_comments() {
+        let body = "<?xml version=\"1.0\"?><!-- feed --><rss><item><![CDATA[ a <b> & c ]]></item><name>x</name></rss>";
+        let expected = "<?xml version=\"1.0\"?>\n<!-- feed -->\n<rss>\n  <item><![CDATA[ a <b> & c ]]></item>\n  <name>x</name>\n</rss>";
+        assert_eq!(pretty_print_xml(body).unwrap(), expected);
+    }
+
+    #[test]
+    fn malformed_xml_falls_back_to_the_raw_body() {
+        assert!(pretty_print_xml("<rss><item></rss>").is_err());
+        assert!(pretty_print_xml("<rss><item>").is_err());
+        let ctx = context(&["--pretty", "format", "get", "localhost"]);
+        let mut out = Vec::new();
+        print_body(&ctx, &mut out, Some(mime::TEXT_XML), "<rss><item></rss>").unwrap();
+        assert_eq!(out, b"<rss><item></rss>\n");
+    }
+
+    #[test]
+    fn pretty_print_json_accepts_ndjson() {
+        assert!(pretty_print_json("{\"a\":1}\n{\"a\":2}\n", "  ", false).is_ok());
+    }
+
+    #[test]
+    fn indent_can_be_spaces_tabs_or_nothing() {
+        let body = r#"{"b": [1], "a": 12345678901234567890123}"#;
+        assert_eq!(
+            pretty_print_json(body, "    ", false).unwrap(),
+            "{\n    \"b\": [\n        1\n    ],\n    \"a\": 12345678901234567890123\n}"
+        );
+        assert_eq!(pretty_print_json(body, "\t", false).unwrap().lines().nth(2), Some("\t\t1"));
+        let ndjson = pretty_print_json("{\"a\": 1}\n[1, 2]\n", "", false).unwrap();
+        assert_eq!(ndjson, "{\"a\":1}\n[1,2]");
+    }
+}
@@ -0,0 +1,136 @@
+use std::collections::HashSet;
+
+use anyhow::{anyhow, Result};
+use colored::Colorize;
+use reqwest::{header, Response, Url};
+use serde_json::Value;
+
+use crate::{cli::*, output::*, request::*};
+
+/// 从Link响应头中找到 rel="next" 的地址, 相对地址按照响应的URL解析
+pub(crate) fn next_link(resp: &Response) -> Option<Url> {
+    resp.headers()
+        .get_all(header::LINK)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .find_map(parse_next)
+        .and_then(|link| resp.url().join(&link).ok())
+}
+
+/// 解析RFC 5988 (8288) 格式的Link, 例如 `<https://x/?page=2>; rel="next", <...>; rel="last"`
+///
+/// URL中可以有逗号, 所以按照 <> 而不是逗号切分, rel中可以有多个用空格分开的值
+pub(crate) fn parse_next(value: &str) -> Option<String> {
+    let mut rest = value;
+    loop {
+        let start = rest.find('<')?;
+        let end = start + rest[start..].find('>')?;
+        let target = &rest[start + 1..end];
+        rest = &rest[end + 1..];
+        let params = &rest[..rest.find('<').unwrap_or(rest.len())];
+        let is_next = |rels: &str| rels.split_whitespace().any(|r| r.eq_ignore_ascii_case("next"));
+        let next = params
+            .split(';')
+            .filter_map(|param| param.split_once('='))
+            .any(|(k, v)| k.trim().eq_ignore_ascii_case("rel") && is_next(v.trim().trim_matches('"')));
+        if next {
+            return Some(target.to_string());
+        }
+    }
+}
+
+/// --follow-pagination: 打印第一页的响应, 然后按照Link一直请求下一页
+///
+/// 遇到错误的状态码, 已经请求过的地址 (服务器返回的next没有变化) 或者超过 --max-pages 时停止
+pub(crate) async fn paginate(
+    ctx: &Context,
+    args: &Get,
+    first: Response,
+    out: &mut impl Output,
+) -> Result<()> {
+    // 下一页的URL中已经包含了查询参数, 只需要再加上请求头
+    let items = args
+        .items
+        .iter()
+        .filter(|item| !matches!(item, RequestItem::Query(..)))
+        .cloned()
+        .collect::<Vec<_>>();
+    let mut visited = HashSet::from([first.url().clone()]);
+    let mut merged = Vec::new();
+    let mut resp = first;
+    let mut page = 1;
+    loop {
+        let next = next_link(&resp);
+        let status = resp.status();
+        if args.merge_json {
+            if page == 1 && ctx.print.response_headers {
+                print_head(out, &resp)?;
+            }
+            ctx.check_status(status)?;
+            if !status.is_success() {
+                return Err(anyhow!("Page {} returned {}, not merging the pages", page, status));
+            }
+            merged.extend(json_array(ctx, resp, page).await?);
+        } else {
+            // 每一页之间用空行分开
+            if page > 1 && (ctx.print.response_headers || ctx.print.response_body) {
+                writeln!(out)?;
+            }
+            print_resp(ctx, out, resp).await?;
+        }
+        ctx.save_records()?;
+        if !status.is_success() {
+            break;
+        }
+
+        let Some(next) = next else { break };
+        if page >= args.max_pages {
+            if ctx.notes() {
+                let note = format!("Stopped after {} pages (--max-pages), next: {}", page, next);
+                eprintln!("{}", note.dimmed());
+            }
+            break;
+        }
+        if !visited.insert(next.clone()) {
+            if ctx.warnings() {
+                let warning = format!("warning: page {} links to {} again, stopping", page, next);
+                eprintln!("{}", warning.yellow());
+            }
+            break;
+        }
+        resp = match ctx.send(ctx.client.get(next), &items, out).await? {
+            Some(resp) => resp,
+            None => break,
+        };
+        page += 1;
+    }
+
+    if args.merge_json && ctx.print.response_body {
+        let body = serde_json::to_string(&Value::Array(merged))?;
+        print_body(ctx, out, Some(mime::APPLICATION_JSON), &body)?;
+    }
+    Ok(())
+}
+
+/// 读取一页的body, 它必须是JSON数组
+async fn json_array(ctx: &Context, resp: Response, page: u64) -> Result<Vec<Value>> {
+    match serde_json::from_slice(&read_body(ctx, resp).await?) {
+        Ok(Value::Array(items)) => Ok(items),
+        _ => Err(anyhow!("Page {} is not a JSON array, can't use --merge-json", page)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn next_link_is_found_among_other_relations() {
+        let value =
+            r#"<https://api.x/items?page=1,2>; rel="prev", <https://api.x/items?page=3>; rel="next""#;
+        assert_eq!(parse_next(value).as_deref(), Some("https://api.x/items?page=3"));
+        assert_eq!(parse_next("</p/2>; title=x; REL=\"last next\"").as_deref(), Some("/p/2"));
+        assert_eq!(parse_next(r#"<https://api.x/items?page=9>; rel="last""#), None);
+        assert_eq!(parse_next("garbage"), None);
+    }
+}
@@ -0,0 +1,229 @@
+use std::{
+    fs,
+    io::{self, IsTerminal},
+    path::PathBuf,
+    sync::Arc,
+};
+
+use anyhow::{anyhow, Result};
+use colored::Colorize;
+use rustyline::{error::ReadlineError, DefaultEditor};
+use structopt::StructOpt;
+
+use crate::{
+    cli::*,
+    config::config_dir,
+    output::Output,
+    request::{self, Context},
+};
+
+/// 这些方法有对应的子命令, 其他大写的方法使用 request 子命令
+const METHODS: &[&str] = &["get", "post", "put", "delete", "patch", "head", "options"];
+
+const HELP: &str = "\
+<method> [/path] [items...]   send a request to the base URL, e.g. get /users limit==5
+:headers                      list the sticky headers sent with every request
+:headers Name:value Name:     add or replace / remove sticky headers
+:help                         show this help
+:quit                         exit, Ctrl-D also works";
+
+/// repl 的输入历史, 在配置目录中
+fn repl_history_path() -> Result<PathBuf> {
+    Ok(config_dir()?.join("repl_history"))
+}
+
+/// repl 子命令: 读取一行命令就发送一个请求, 认证信息, cookie和client在命令之间共享
+///
+/// 没有在命令中给出的请求头从sticky中补上, sticky由命令行中的请求项和 :headers 修改
+pub async fn run(ctx: &Arc<Context>, args: &Repl, out: &mut impl Output) -> Result<()> {
+    if !io::stdin().is_terminal() {
+        return Err(anyhow!("repl needs an interactive terminal, stdin is not a TTY"));
+    }
+    if ctx.chunked {
+        return Err(anyhow!("--chunked can't be used with repl"));
+    }
+    let mut sticky = args.items.clone();
+    let mut editor = DefaultEditor::new()?;
+    let history = repl_history_path()?;
+    // 第一次使用时还没有历史文件
+    let _ = editor.load_history(&history);
+    if ctx.notes() {
+        eprintln!("{}", format!("{}, :help for the commands, Ctrl-D to exit", args.url).dimmed());
+    }
+    let prompt = format!("{}> ", args.url);
+    loop {
+        let line = match tokio::task::block_in_place(|| editor.readline(&prompt)) {
+            Ok(line) => line,
+            // Ctrl-C 只清空当前行
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(e) => return Err(e.into()),
+        };
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let _ = editor.add_history_entry(line);
+        match eval(ctx, &args.url, &mut sticky, line, out).await {
+            Ok(true) => {}
+            Ok(false) => break,
+            Err(e) => eprintln!("{}", format!("Error: {:#}", e).red()),
+        }
+    }
+    fs::create_dir_all(config_dir()?)?;
+    editor
+        .save_history(&history)
+        .map_err(|e| anyhow!("Failed to save {}: {}", history.display(), e))
+}
+
+/// 执行一行命令, :quit 时返回false
+async fn eval(
+    ctx: &Arc<Context>,
+    base: &str,
+    sticky: &mut Vec<RequestItem>,
+    line: &str,
+    out: &mut impl Output,
+) -> Result<bool> {
+    let words = split_line(line)?;
+    match words[0].as_str() {
+        ":quit" | ":q" | ":exit" => return Ok(false),
+        ":help" => println!("{}", HELP),
+        ":headers" => update_headers(sticky, &words[1..])?,
+        cmd if cmd.starts_with(':') => return Err(anyhow!("Unknown command {}, see :help", cmd)),
+        _ => {
+            let mut cmd = parse_command(base, &words)?;
+            if let Some(items) = cmd.items_mut() {
+                with_sticky(items, sticky);
+            }
+            request::run(ctx, &cmd, out).await?;
+        }
+    }
+    Ok(true)
+}
+
+/// :headers 没有参数时列出sticky的请求头, Name:value 添加或替换, Name: 删除
+fn update_headers(sticky: &mut Vec<RequestItem>, words: &[String]) -> Result<()> {
+    if words.is_empty() {
+        let headers = sticky.iter().filter_map(|item| match item {
+            RequestItem::Header(name, value) => Some((name, value)),
+            _ => None,
+        });
+        let mut empty = true;
+        for (name, value) in headers {
+            println!("{}: {}", name.to_string().green(), String::from_utf8_lossy(value.as_bytes()));
+            empty = false;
+        }
+        if empty {
+            println!("{}", "no sticky headers".dimmed());
+        }
+        return Ok(());
+    }
+    for word in words {
+        let item = parse_request_item(word)?;
+        let name = match &item {
+            RequestItem::Header(name, _) | RequestItem::RemoveHeader(name) => name.clone(),
+            _ => return Err(anyhow!("Invalid header {}, use Name:value to add and Name: to remove", word)),
+        };
+        sticky.retain(|item| !matches!(item, RequestItem::Header(n, _) if *n == name));
+        if let RequestItem::Header(..) = item {
+            sticky.push(item);
+        }
+    }
+    Ok(())
+}
+
+/// 把一行命令转换成子命令, 路径接在基础URL后面, 没有路径时请求基础URL
+fn parse_command(base: &str, words: &[String]) -> Result<SubCommand> {
+    let method = &words[0];
+    let mut argv = vec!["httpie".to_string()];
+    if METHODS.contains(&method.to_ascii_lowercase().as_str()) {
+        argv.push(method.to_ascii_lowercase());
+    } else if method.chars().all(|c| c.is_ascii_uppercase()) {
+        argv.extend(["request".to_string(), method.clone()].iter().cloned());
+    } else {
+        return Err(anyhow!("Unknown method {}, expected get, post, ... or :help", method));
+    }
+    let (url, rest) = match words.get(1) {
+        Some(path) if path.contains("://") => (path.clone(), &words[2..]),
+        Some(path) if path.starts_with(['/', '?']) => (join_url(base, path), &words[2..]),
+        _ => (base.to_string(), &words[1..]),
+    };
+    argv.push(url);
+    argv.extend(rest.iter().cloned());
+    Ok(SubCommand::from_iter_safe(argv)?)
+}
+
+fn join_url(base: &str, path: &str) -> String {
+    match path.strip_prefix('/') {
+        Some(path) => format!("{}/{}", base.trim_end_matches('/'), path),
+        None => format!("{}{}", base.trim_end_matches('/'), path),
+    }
+}
+
+/// 命令中没有的sticky请求项补在前面, 命令中的同名请求头和查询参数优先
+fn with_sticky(items: &mut Vec<RequestItem>, sticky: &[RequestItem]) {
+    let overridden = |item: &RequestItem| {
+        items.iter().any(|cmd| match (item, cmd) {
+            (RequestItem::Header(name, _), RequestItem::Header(cmd, _))
+            | (RequestItem::Header(name, _), RequestItem::RemoveHeader(cmd)) => name == cmd,
+            (RequestItem::Query(key, _), RequestItem::Query(cmd, _)) => key == cmd,
+            _ => false,
+        })
+    };
+    let mut merged = sticky.iter().filter(|item| !overridden(item)).cloned().collect::<Vec<_>>();
+    merged.append(items);
+    *items = merged;
+}
+
+/// 按照空白分割, 和shell一样支持单引号, 双引号和反斜杠转义
+fn split_line(line: &str) -> Result<Vec<String>> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut chars = line.chars();
+    let mut quote = None;
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (None, c) if c.is_whitespace() => words.extend(word.take()),
+            (None, '\'' | '"') => {
+                quote = Some(c);
+                word.get_or_insert_with(String::new);
+            }
+            (Some(q), c) if c == q => quote = None,
+            (None | Some('"'), '\\') => match chars.next() {
+                Some(next) => word.get_or_insert_with(String::new).push(next),
+                None => return Err(anyhow!("Unfinished escape at the end of the line")),
+            },
+            (_, c) => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    if quote.is_some() {
+        return Err(anyhow!("Unclosed quote in {}", line));
+    }
+    words.extend(word);
+    Ok(words)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lines_are_split_like_a_shell() {
+        let words = split_line(r#"post /users name='alice smith' 'X-Note:"hi"' a\ b """#).unwrap();
+        assert_eq!(words, ["post", "/users", "name=alice smith", "X-Note:\"hi\"", "a b", ""]);
+        assert!(split_line("get 'oops").is_err());
+    }
+
+    #[test]
+    fn commands_are_relative_to_the_base_url() {
+        let url = |line: &str| {
+            let words = split_line(line).unwrap();
+            parse_command("https://api.example.com/v1/", &words).unwrap().url().unwrap().to_string()
+        };
+        assert_eq!(url("get /users?limit=5"), "https://api.example.com/v1/users?limit=5");
+        assert_eq!(url("GET ?page=2"), "https://api.example.com/v1?page=2");
+        assert_eq!(url("post name=alice"), "https://api.example.com/v1/");
+        assert_eq!(url("PROPFIND http://other.example.com/x"), "http://other.example.com/x");
+        assert!(parse_command("http://localhost", &["fetch".to_string()]).is_err());
+    }
+}
@@ -0,0 +1,1959 @@
+use std::{
+    collections::{hash_map::RandomState, BTreeMap, HashMap},
+    ffi::OsString,
+    fs,
+    hash::{BuildHasher, Hasher},
+    io::{self, IsTerminal},
+    net::{IpAddr, SocketAddr},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime},
+};
+
+use anyhow::{anyhow, Result};
+use colored::Colorize;
+use encoding_rs::Encoding;
+use mime::Mime;
+use openssl::{pkey::PKey, x509::X509};
+use reqwest::cookie::CookieStore as _;
+use reqwest::{
+    header::{self, HeaderMap, HeaderValue},
+    multipart, redirect, Body, Certificate, Client, ClientBuilder, Identity, Method, NoProxy, Proxy,
+    RequestBuilder, Response, StatusCode, Url, Version,
+};
+use reqwest_cookie_store::{CookieStore, CookieStoreMutex};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use structopt::StructOpt;
+use futures_util::StreamExt;
+use tokio::io::AsyncReadExt;
+use tokio_util::io::ReaderStream;
+
+use crate::{
+    aws::*, batch, bench, cache::*, collection, expect::*, cli::*, compress::*, diff, curl::*, digest::*, dns::*, download::*, error::*, filter::*, graphql::*, har::*, history::*, netrc::*, oauth2::*, output::*,
+    paginate::*, session::*, timings::*, tls::*, transcript::*, upload::*, watch::{self, Watch}, ws,
+};
+
+/// 发送请求时使用的认证信息
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub(crate) enum Auth {
+    Basic { user: String, password: String },
+    Bearer { token: String },
+    /// 收到服务器的质询后才能计算出Authorization
+    Digest { user: String, password: String },
+}
+
+impl Auth {
+    /// 根据命令行参数得到认证信息
+    pub(crate) fn from_args(opt: &App) -> Result<Option<Self>> {
+        match (&opt.auth, &opt.bearer) {
+            (Some(_), Some(_)) => Err(anyhow!("--auth and --bearer can't be used together")),
+            // 签名由 AwsSigner 完成
+            _ if opt.auth_type == AuthType::Aws4 => Ok(None),
+            (None, Some(Secret(token))) => Ok(Some(Self::Bearer {
+                token: token.clone(),
+            })),
+            (Some(Secret(token)), None) if opt.auth_type == AuthType::Bearer => {
+                Ok(Some(Self::Bearer {
+                    token: token.clone(),
+                }))
+            }
+            (Some(Secret(s)), None) => {
+                // 密码中可以带 `:`, 所以只在第一个 `:` 处切分
+                let (user, password) = match s.split_once(':') {
+                    Some((user, password)) => (user.to_string(), password.to_string()),
+                    // 只给出用户名时提示输入密码, 这样密码不会留在shell的历史记录里
+                    None => {
+                        let prompt = format!("http: password for {}: ", s);
+                        (s.clone(), rpassword::prompt_password(prompt)?)
+                    }
+                };
+                match opt.auth_type {
+                    AuthType::Digest => Ok(Some(Self::Digest { user, password })),
+                    _ => Ok(Some(Self::Basic { user, password })),
+                }
+            }
+            (None, None) => Ok(None),
+        }
+    }
+
+    /// 环境变量 HTTPIE_TOKEN 中的 Bearer token
+    pub(crate) fn from_env() -> Option<Self> {
+        std::env::var("HTTPIE_TOKEN")
+            .ok()
+            .filter(|token| !token.is_empty())
+            .map(|token| Self::Bearer { token })
+    }
+}
+
+/// 一次运行中所有请求共享的HTTP客户端和全局选项
+pub struct Context {
+    pub(crate) client: Client,
+    /// client使用的cookie, 包括从 --cookie-jar 中读取的和服务器返回的
+    pub(crate) cookies: Arc<CookieStoreMutex>,
+    pub(crate) cookie_jar: Option<PathBuf>,
+    /// --cookie 中给出的cookie
+    pub(crate) extra_cookies: Vec<String>,
+    /// --session 对应的会话, 为None时表示没有使用会话
+    pub(crate) session: Option<SessionFile>,
+    pub(crate) ignore_stdin: bool,
+    /// --chunked, 以流的方式发送标准输入
+    pub(crate) chunked: bool,
+    pub(crate) auth: Option<Auth>,
+    pub(crate) follow: bool,
+    /// --all 时自己跟随重定向, 打印每个中间的响应
+    pub(crate) all: bool,
+    pub(crate) max_redirects: usize,
+    pub(crate) retry: RetryPolicy,
+    pub(crate) http2: bool,
+    /// 不验证服务器的TLS证书
+    pub(crate) insecure: bool,
+    /// 设置了 --unix-socket 时不使用client, 直接通过这个socket发送请求
+    pub(crate) unix_socket: Option<PathBuf>,
+    pub(crate) compression: Compression,
+    /// --user-agent 指定的User-Agent, 会替换掉默认的
+    pub(crate) user_agent: Option<HeaderValue>,
+    /// --accept 或者 --json 时的Accept请求头
+    pub(crate) accept: Option<HeaderValue>,
+    /// --content-type 指定的body类型
+    pub(crate) content_type: Option<Mime>,
+    /// 要打印请求和响应中的哪些部分
+    pub(crate) print: PrintParts,
+    /// -o 指定的文件, 设置时body写入这个文件而不是打印出来
+    pub(crate) output: Option<PathBuf>,
+    pub(crate) download: bool,
+    /// --continue 时从哪里继续下载
+    pub(crate) resume: Option<Resume>,
+    /// 语法高亮使用的主题
+    pub(crate) style: String,
+    /// --response-charset 指定的编码
+    pub(crate) charset: Option<&'static Encoding>,
+    /// 超过这个大小的body不格式化, 直接流式输出
+    pub(crate) pretty_limit: usize,
+    /// --compress 时请求body的压缩格式
+    pub(crate) compress: Option<BodyCompression>,
+    /// --pretty, 决定body是否格式化和高亮
+    pub(crate) pretty: Pretty,
+    /// --max-body-size, None表示不限制
+    pub(crate) max_body_size: Option<u64>,
+    pub(crate) show_partial: bool,
+    pub(crate) meta: bool,
+    /// --verbose 时在最后打印实际连接的地址
+    pub(crate) verbose: bool,
+    pub(crate) sort_keys: bool,
+    /// 格式化JSON时的缩进, 为空时输出为一行
+    pub(crate) json_indent: String,
+    pub(crate) filter: Option<JsonFilter>,
+    /// 按照Server-Sent Events打印body, 以及什么时候停止接收
+    pub(crate) stream: bool,
+    pub(crate) max_events: Option<u64>,
+    pub(crate) duration: Option<Duration>,
+    /// --dump-headers 的文件, 以及是否追加到文件末尾
+    pub(crate) dump_headers: Option<PathBuf>,
+    pub(crate) dump_headers_append: bool,
+    /// 跟随重定向时经过的中间响应, 由重定向策略记录
+    pub(crate) redirects: RedirectLog,
+    /// --har 时记录请求和响应
+    pub(crate) har: Option<HarRecorder>,
+    /// --log-file 时记录请求和响应
+    pub(crate) transcript: Option<Transcript>,
+    /// --timings 时自己建立连接, 分别计时
+    pub(crate) timings: Option<PhaseTimer>,
+    /// --auth-type digest 时记住服务器的质询
+    pub(crate) digest: Option<DigestAuth>,
+    /// --auth-type aws4 时签名请求
+    pub(crate) aws: Option<AwsSigner>,
+    /// --oauth2-token-url 时取得access token
+    pub(crate) oauth2: Option<OAuth2Client>,
+    /// --tls-info 时检查TLS连接
+    pub(crate) tls_info: Option<TlsInspector>,
+    /// --interface 绑定的本地地址
+    pub(crate) interface: Option<IpAddr>,
+    /// -4 / -6 限定的地址族
+    pub(crate) family: Option<IpFamily>,
+    /// 上传较大的body时显示的进度条
+    pub(crate) upload: UploadProgress,
+    /// --etag-cache 时使用的缓存
+    pub(crate) etag_cache: Option<EtagCache>,
+    /// --expect-status 等对响应的检查
+    pub(crate) expect: Option<Expectations>,
+    /// --cache 时使用的响应缓存
+    pub(crate) response_cache: Option<ResponseCache>,
+    /// 历史文件的路径, --no-history 时为None
+    pub(crate) history: Option<PathBuf>,
+    pub(crate) check_status: bool,
+    /// -q 的次数, 1 时只显示警告和错误, 2 时什么都不显示
+    pub(crate) quiet: u8,
+    pub(crate) offline: bool,
+    pub(crate) curl: bool,
+    pub(crate) curl_redact: bool,
+    /// --repeat 的次数, 为None时正常发送一次请求
+    pub(crate) repeat: Option<u64>,
+    pub(crate) repeat_delay: Duration,
+    pub(crate) watch: Option<Watch>,
+    pub(crate) concurrency: usize,
+    /// 配置文件中的默认请求头
+    pub(crate) config_headers: HeaderMap,
+}
+
+/// 重定向策略中只能拿到状态码和下一个地址, 拿不到中间响应的其他响应头
+pub(crate) type RedirectLog = Arc<Mutex<Vec<(StatusCode, Url)>>>;
+
+/// 请求失败时的重试策略
+pub(crate) struct RetryPolicy {
+    /// 最多重试的次数, 为0时不重试
+    pub(crate) retries: u32,
+    /// 第一次重试前等待的秒数
+    pub(crate) delay: f64,
+    /// 需要重试的状态码
+    pub(crate) on_status: Vec<StatusCode>,
+    pub(crate) non_idempotent: bool,
+    /// 没有 --retry 时也按照Retry-After重试429和503
+    pub(crate) respect_retry_after: bool,
+    pub(crate) retry_after_max: Duration,
+}
+
+/// 只给出 --respect-retry-after 时按照Retry-After重试的次数
+pub(crate) const RETRY_AFTER_RETRIES: u32 = 3;
+
+impl RetryPolicy {
+    /// 第attempt次重试前等待的时间: delay * 2^(attempt-1), 再乘上 [0.5, 1.5) 之间的随机数
+    pub(crate) fn backoff(&self, attempt: u32) -> Duration {
+        // RandomState每次都会生成不同的随机种子, 用来做抖动已经足够
+        let random = RandomState::new().build_hasher().finish();
+        let jitter = 0.5 + (random % 1000) as f64 / 1000.0;
+        Duration::from_secs_f64(self.delay * 2f64.powi(attempt as i32 - 1) * jitter)
+    }
+
+    /// 这个请求是否可以重试
+    pub(crate) fn allows(&self, method: &Method) -> bool {
+        let idempotent = matches!(
+            *method,
+            Method::GET | Method::HEAD | Method::OPTIONS | Method::PUT | Method::DELETE | Method::TRACE
+        );
+        (self.retries > 0 || self.respect_retry_after) && (idempotent || self.non_idempotent)
+    }
+
+    /// 429和503响应中Retry-After要求等待的时间, 不需要遵守时为None
+    pub(crate) fn retry_after(&self, resp: &Response) -> Option<Duration> {
+        let limited = matches!(
+            resp.status(),
+            StatusCode::TOO_MANY_REQUESTS | StatusCode::SERVICE_UNAVAILABLE
+        );
+        if !limited || !(self.retries > 0 || self.respect_retry_after) {
+            return None;
+        }
+        let value = resp.headers().get(header::RETRY_AFTER)?.to_str().ok()?;
+        parse_retry_after(value, SystemTime::now())
+    }
+
+    /// 最多重试的次数, 只给出 --respect-retry-after 时只有Retry-After的响应会重试
+    pub(crate) fn max_retries(&self, retry_after: bool) -> u32 {
+        match self.retries {
+            0 if retry_after && self.respect_retry_after => RETRY_AFTER_RETRIES,
+            retries => retries,
+        }
+    }
+}
+
+/// Retry-After可以是秒数, 也可以是HTTP日期, 已经过去的日期表示马上重试
+pub(crate) fn parse_retry_after(value: &str, now: SystemTime) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+    let at = httpdate::parse_http_date(value).ok()?;
+    Some(at.duration_since(now).unwrap_or_default())
+}
+
+impl Context {
+    /// 设置认证信息, 请求头和查询参数, 得到最终要发送的请求
+    pub(crate) fn build(&self, mut req: RequestBuilder, items: &[RequestItem]) -> Result<reqwest::Request> {
+        // 先设置认证信息, 这样命令行中的 Authorization:xxx 可以覆盖它
+        match &self.auth {
+            Some(Auth::Basic { user, password }) => req = req.basic_auth(user, Some(password)),
+            Some(Auth::Bearer { token }) => req = req.bearer_auth(token),
+            // 发送时由 DigestAuth 添加
+            Some(Auth::Digest { .. }) | None => {}
+        }
+        // 会话中的请求头优先于配置文件中的
+        let mut extra = self.config_headers.clone();
+        if let Some(session) = &self.session {
+            extra.extend(session.session.header_map());
+        }
+        if let Some(user_agent) = &self.user_agent {
+            extra.insert(header::USER_AGENT, user_agent.clone());
+        }
+        if let Some(accept) = &self.accept {
+            let explicit = items.iter().find_map(|item| match item {
+                RequestItem::Header(name, value) if name == header::ACCEPT => Some(value),
+                _ => None,
+            });
+            if let (Some(value), true) = (explicit, self.warnings()) {
+                let warning = format!(
+                    "warning: Accept:{} from the command line overrides --accept/--json",
+                    String::from_utf8_lossy(value.as_bytes())
+                );
+                eprintln!("{}", warning.yellow());
+            }
+            extra.insert(header::ACCEPT, accept.clone());
+        }
+        let mut req = with_items(req, items, extra)?.build()?;
+        // reqwest没有开启gzip等feature, 不会自己设置Accept-Encoding和解压body
+        if !req.headers().contains_key(header::ACCEPT_ENCODING) {
+            let encoding = HeaderValue::from_static(self.compression.accept_encoding());
+            req.headers_mut().insert(header::ACCEPT_ENCODING, encoding);
+        }
+        // 继续下载时只请求还没有下载的部分
+        if let Some(resume) = &self.resume {
+            if resume.offset > 0 && !req.headers().contains_key(header::RANGE) {
+                let range = format!("bytes={}-", resume.offset);
+                req.headers_mut().insert(header::RANGE, HeaderValue::from_str(&range)?);
+            }
+        }
+        // body已经序列化好了, 这里只替换类型
+        if let (Some(mime), true) = (&self.content_type, req.body().is_some()) {
+            if items.iter().any(|item| matches!(item, RequestItem::Upload(..))) {
+                return Err(anyhow!(
+                    "--content-type can't be used with key@path uploads, \
+                     the multipart body needs its own Content-Type with the boundary"
+                ));
+            }
+            req.headers_mut().insert(header::CONTENT_TYPE, HeaderValue::from_str(mime.as_ref())?);
+        }
+        // 最后再去掉 Header: 中的请求头, 这样认证信息和body的Content-Type也可以去掉
+        for item in items {
+            if let RequestItem::RemoveHeader(name) = item {
+                req.headers_mut().remove(name);
+            }
+        }
+        if let Some(compression) = self.compress {
+            compress_body(&mut req, items, compression)?;
+        }
+        // 签名必须在请求头和body都确定之后
+        if let Some(aws) = &self.aws {
+            aws.sign(&mut req)?;
+        }
+        for cookie in self.extra_cookies.iter() {
+            // 对整个站点生效, 而不只是当前请求的路径
+            self.cookies
+                .lock()
+                .unwrap()
+                .parse(&format!("{}; Path=/", cookie), req.url())
+                .map_err(|e| anyhow!("Invalid cookie {}: {}", cookie, e))?;
+        }
+        Ok(req)
+    }
+
+    /// 构造并发送请求, --offline 时只打印请求, 不发送, 返回None
+    pub(crate) async fn send(
+        &self,
+        req: RequestBuilder,
+        items: &[RequestItem],
+        out: &mut impl Output,
+    ) -> Result<Option<Response>> {
+        let mut req = self.build(req, items)?;
+        if let Some(cache) = &self.etag_cache {
+            cache.request(&mut req);
+        }
+        if self.curl {
+            writeln!(out, "{}", curl_command(self, &req, items))?;
+            // 只打印curl命令时最后不需要空行
+            if !self.offline || self.print.request_headers || self.print.request_body {
+                writeln!(out)?;
+            }
+        }
+        if self.print.request_headers {
+            self.print_request_head(out, &req)?;
+        }
+        if self.print.request_body {
+            print_request_body(out, &req)?;
+        }
+        if self.offline {
+            return Ok(None);
+        }
+        if let (Some(tls), "https") = (&self.tls_info, req.url().scheme()) {
+            // 握手失败时仍然发送请求, 由请求给出错误
+            match tls.inspect(req.url()).await {
+                Ok(info) => print_tls_info(out, &info)?,
+                Err(e) if self.warnings() => {
+                    eprintln!("{}", format!("warning: --tls-info: {:#}", e).yellow())
+                }
+                Err(_) => {}
+            }
+        }
+        if let Some(har) = &self.har {
+            har.request(&req, &self.request_headers(&req));
+        }
+        if let Some(transcript) = &self.transcript {
+            transcript.request(&req, &self.request_headers(&req));
+        }
+        let history = self.history.as_ref().map(|_| HistoryEntry::new(&req));
+
+        let url = req.url().clone();
+        // body是stream时不能重新发送, 遇到重定向时就停下来
+        let replay = if self.follows_manually() { req.try_clone() } else { None };
+        self.redirects.lock().unwrap().clear();
+        let start = Instant::now();
+        let result = match &self.response_cache {
+            Some(cache) => cache.execute(self, req).await,
+            None => self.execute(req).await,
+        };
+        let result = result.map_err(|e| {
+            // 服务器不支持 HTTP/2 时h2的错误信息很难看懂, 这里加上提示
+            if self.http2 && !is_timeout(&e) {
+                e.context(
+                    "HTTP/2 request failed, the server may not support HTTP/2 \
+                     without negotiation (--http2)",
+                )
+            } else if let (Some(ip), true) = (self.interface, is_addr_not_available(&e)) {
+                e.context(format!("Failed to bind to --interface {}, the address isn't assigned to this host", ip))
+            } else if is_proxy_auth_required(&e) {
+                e.context(format!(
+                    "The proxy rejected the credentials while opening a tunnel to {} \
+                     (407 Proxy Authentication Required)",
+                    url.host_str().unwrap_or_default()
+                ))
+            } else {
+                e
+            }
+        });
+        let mut resp = match (result, &self.transcript) {
+            (Ok(resp), _) => resp,
+            (Err(e), Some(transcript)) => {
+                transcript.error(&e)?;
+                return Err(e);
+            }
+            (Err(e), None) => return Err(e),
+        };
+        if self.follows_manually() {
+            resp = self.follow_redirects(replay, resp, out).await?;
+        }
+        if resp.status() == StatusCode::UNSUPPORTED_MEDIA_TYPE && self.compress.is_some() && self.warnings() {
+            let warning = "warning: 415 Unsupported Media Type, the server may not accept \
+                           a compressed body, try again without --compress";
+            eprintln!("{}", warning.yellow());
+        }
+        // 407来自代理而不是服务器, 和401区分开
+        if resp.status() == StatusCode::PROXY_AUTHENTICATION_REQUIRED && self.warnings() {
+            let warning = format!(
+                "warning: 407 Proxy Authentication Required came from the proxy, not from {}, \
+                 check the credentials in --proxy or the proxy environment variables",
+                url.host_str().unwrap_or_default()
+            );
+            eprintln!("{}", warning.yellow());
+        }
+        // 读取body之前的耗时, 包括重定向和重试
+        resp.extensions_mut().insert(Timing { start, first_byte: start.elapsed() });
+        // 在读取body之前写入, 读取body失败时也能看到服务器返回了什么
+        if let Some(path) = &self.dump_headers {
+            let redirects = self.redirects.lock().unwrap().clone();
+            dump_headers(path, self.dump_headers_append, &redirects, &resp)?;
+        }
+        if let Some(har) = &self.har {
+            har.response(&resp);
+        }
+        if let Some(transcript) = &self.transcript {
+            transcript.response(&resp);
+        }
+        if let Some(cache) = &self.etag_cache {
+            cache.response(&resp);
+        }
+        if let Some(expect) = &self.expect {
+            expect.response(&resp);
+        }
+        // 由 run 写入历史文件, 作为库使用时不记录
+        if let Some(history) = history {
+            resp.extensions_mut().insert(history);
+        }
+        // 通过Unix socket发送的请求不会跟随重定向, resp中也没有URL
+        if self.follow && self.unix_socket.is_none() && resp.url() != &url && self.notes() {
+            print_final_url(out, &resp)?;
+        }
+        // 服务器返回的Set-Cookie已经保存到了cookies中, 这里把它们写回文件
+        if let Some(path) = &self.cookie_jar {
+            save_cookie_jar(&self.cookies.lock().unwrap(), path)?;
+        }
+        if let Some(session) = &self.session {
+            session.update(items, self.auth.clone(), &self.cookies.lock().unwrap())?;
+        }
+        Ok(Some(resp))
+    }
+
+    fn follows_manually(&self) -> bool {
+        self.all && self.follow && self.unix_socket.is_none()
+    }
+
+    /// --all: 跟随重定向之前打印中间的响应和下一个请求
+    /// 中间响应的Set-Cookie由client保存到cookies中, 之后的请求会带上
+    async fn follow_redirects(
+        &self,
+        mut req: Option<reqwest::Request>,
+        mut resp: Response,
+        out: &mut impl Output,
+    ) -> Result<Response> {
+        let mut chain = vec![resp.url().clone()];
+        while let Some(location) = redirect_location(&resp) {
+            let Some(prev) = req.take() else {
+                if self.warnings() {
+                    let warning = format!("warning: the body can't be sent again, not following {}", location);
+                    eprintln!("{}", warning.yellow());
+                }
+                break;
+            };
+            let url = resp.url().join(&location).map_err(|e| anyhow!("Invalid Location {}: {}", location, e))?;
+            if chain.len() > self.max_redirects {
+                chain.push(url);
+                return Err(TooManyRedirects { max: self.max_redirects, chain }.into());
+            }
+            self.redirects.lock().unwrap().push((resp.status(), url.clone()));
+            if self.print.response_headers {
+                print_head(out, &resp)?;
+            }
+            let next = redirect_request(prev, resp.status(), url.clone());
+            if self.print.request_headers {
+                self.print_request_head(out, &next)?;
+            }
+            req = next.try_clone();
+            chain.push(url);
+            resp = self.execute(next).await?;
+        }
+        Ok(resp)
+    }
+
+    /// with_body 中怎样使用标准输入
+    pub(crate) fn stdin_body(&self) -> StdinBody {
+        match (self.chunked, self.ignore_stdin) {
+            (true, _) => StdinBody::Chunked,
+            (false, true) => StdinBody::Ignore,
+            (false, false) => StdinBody::Json,
+        }
+    }
+
+    /// 是否显示提示信息, 例如重试和保存文件
+    pub(crate) fn notes(&self) -> bool {
+        self.quiet == 0
+    }
+
+    /// 是否显示进度条, 只画到stderr上, stderr不是终端时不显示
+    pub(crate) fn progress(&self) -> bool {
+        self.notes() && io::stderr().is_terminal()
+    }
+
+    /// 是否显示警告, 只有 -qq 时不显示
+    pub(crate) fn warnings(&self) -> bool {
+        self.quiet < 2
+    }
+
+    /// --check-status 时把错误的状态码变成 ErrorStatus, 跟随重定向时3xx不算错误
+    pub(crate) fn check_status(&self, status: StatusCode) -> Result<()> {
+        let redirect = status.is_redirection() && !self.follow;
+        if self.check_status && (redirect || status.is_client_error() || status.is_server_error()) {
+            return Err(ErrorStatus(status).into());
+        }
+        Ok(())
+    }
+
+    /// 打印要发送的请求行和请求头, 包括发送时reqwest和hyper才会加上的请求头
+    pub(crate) fn print_request_head(
+        &self,
+        out: &mut impl Output,
+        req: &reqwest::Request,
+    ) -> io::Result<()> {
+        let out = out.meta();
+        let url = req.url();
+        let mut target = url.path().to_string();
+        if let Some(query) = url.query() {
+            target.push('?');
+            target.push_str(query);
+        }
+        // --http2 是在client上设置的, req中的版本仍然是默认的 HTTP/1.1
+        let version = if self.http2 { Version::HTTP_2 } else { req.version() };
+        let line = format!("{} {} {:?}", req.method(), target, version).blue();
+        writeln!(out, "{}", line)?;
+
+        for (name, value) in self.request_headers(req).iter() {
+            // --offline 的输出要能直接当作HTTP请求使用, 所以不给值加引号
+            match value.to_str() {
+                Ok(value) if self.offline => {
+                    writeln!(out, "{}: {}", name.to_string().green(), value)?
+                }
+                _ => writeln!(out, "{}: {:?}", name.to_string().green(), value)?,
+            }
+        }
+        writeln!(out)
+    }
+
+    /// 实际发送的请求头, 包括发送时reqwest和hyper才会加上的
+    pub(crate) fn request_headers(&self, req: &reqwest::Request) -> HeaderMap {
+        let url = req.url();
+        let mut headers = HeaderMap::new();
+        if !req.headers().contains_key(header::HOST) {
+            if let Ok(host) = HeaderValue::from_str(&host_header(url)) {
+                headers.insert(header::HOST, host);
+            }
+        }
+        headers.extend(req.headers().clone());
+        headers
+            .entry(header::ACCEPT)
+            .or_insert_with(|| HeaderValue::from_static("*/*"));
+        if !headers.contains_key(header::COOKIE) {
+            if let Some(cookies) = self.cookies.cookies(url) {
+                headers.insert(header::COOKIE, cookies);
+            }
+        }
+        if let Some(bytes) = req.body().and_then(|body| body.as_bytes()) {
+            if !bytes.is_empty() {
+                headers.insert(header::CONTENT_LENGTH, bytes.len().into());
+            }
+        }
+        headers
+    }
+
+    /// 不打印body时是否也要读取它, --har, --log-file 和 --etag-cache 都需要完整的body
+    pub(crate) fn records_body(&self) -> bool {
+        self.har.is_some()
+            || self.transcript.is_some()
+            || self.etag_cache.is_some()
+            || self.expect.as_ref().is_some_and(|expect| expect.needs_body())
+    }
+
+    /// --har, --log-file 和 --etag-cache 时记录读取到的body
+    pub(crate) fn record_body(&self, chunk: &[u8]) {
+        if let Some(har) = &self.har {
+            har.body(chunk);
+        }
+        if let Some(transcript) = &self.transcript {
+            transcript.body(chunk);
+        }
+        if let Some(expect) = &self.expect {
+            expect.body(chunk);
+        }
+        if let Some(cache) = &self.etag_cache {
+            cache.body(chunk);
+        }
+    }
+
+    /// 把这次的请求和响应写入 --har, --log-file 指定的文件和 --etag-cache 的缓存
+    pub(crate) fn save_records(&self) -> Result<()> {
+        if let Some(har) = &self.har {
+            har.save()?;
+        }
+        if let Some(transcript) = &self.transcript {
+            transcript.save()?;
+        }
+        match &self.etag_cache {
+            Some(cache) => cache.save(),
+            None => Ok(()),
+        }
+    }
+
+    /// 发送请求, Digest认证时收到质询后会再发送一次, OAuth2时先取得access token
+    pub(crate) async fn execute(&self, mut req: reqwest::Request) -> Result<Response> {
+        if let Some(family) = self.family {
+            family.check_url(req.url())?;
+        }
+        if let Some(oauth2) = &self.oauth2 {
+            oauth2.authorize(self, &mut req).await?;
+        }
+        match &self.digest {
+            Some(digest) => digest.execute(self, req).await,
+            None => self.execute_with_retries(req).await,
+        }
+    }
+
+    /// 发送请求, 失败时按照重试策略重试
+    pub(crate) async fn execute_with_retries(&self, req: reqwest::Request) -> Result<Response> {
+        if let Some(path) = &self.unix_socket {
+            return self.execute_unix(path, req).await;
+        }
+        if let Some(timer) = &self.timings {
+            return timer.execute(self, req).await;
+        }
+        // body是stream (例如上传文件) 时请求不能复制, 也就不能重试
+        if !self.retry.allows(req.method()) || req.try_clone().is_none() {
+            return self.execute_once(req).await;
+        }
+
+        // 服务器不接受压缩过的body时重试也没有用
+        let compressed = req.headers().contains_key(header::CONTENT_ENCODING);
+        let mut attempt = 0;
+        loop {
+            let result = self.execute_once(req.try_clone().unwrap()).await;
+            let retry_after = result.as_ref().ok().and_then(|resp| self.retry.retry_after(resp));
+            let reason = match &result {
+                Ok(resp) if compressed && resp.status() == StatusCode::UNSUPPORTED_MEDIA_TYPE => return result,
+                Err(e) if is_retryable(e) && self.retry.retries > 0 => e.to_string(),
+                Ok(resp) if retry_after.is_some() => format!("{} (Retry-After)", resp.status()),
+                Ok(resp) if self.retry.on_status.contains(&resp.status()) => {
+                    resp.status().to_string()
+                }
+                // 检查没有通过的响应也可能是暂时的, 重试之后再下结论
+                Ok(resp) if self.retry.retries > 0 && self.expect.as_ref().is_some_and(|e| !e.head_matches(resp)) => {
+                    format!("{} (expectations not met)", resp.status())
+                }
+                _ => return result,
+            };
+            let retries = self.retry.max_retries(retry_after.is_some());
+            if attempt >= retries {
+                return result;
+            }
+
+            attempt += 1;
+            let delay = match retry_after {
+                // 超过上限时返回这个响应, 这样还能看到限流相关的响应头
+                Some(wait) if wait > self.retry.retry_after_max => {
+                    if self.warnings() {
+                        let warning = format!(
+                            "warning: Retry-After is {}s, more than --retry-after-max {}s, not retrying",
+                            wait.as_secs(),
+                            self.retry.retry_after_max.as_secs_f64()
+                        );
+                        eprintln!("{}", warning.yellow());
+                    }
+                    return result;
+                }
+                Some(wait) => wait,
+                None => self.retry.backoff(attempt),
+            };
+            let notice = format!(
+                "retry {}/{} in {:.2}s: {}",
+                attempt,
+                retries,
+                delay.as_secs_f64(),
+                reason
+            );
+            if self.notes() {
+                eprintln!("{}", notice.dimmed());
+            }
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    /// 发送一次请求, 上传时显示进度条, 收到响应头后清除
+    async fn execute_once(&self, req: reqwest::Request) -> Result<Response> {
+        let result = self.client.execute(self.upload.track(req)).await;
+        self.upload.finish();
+        Ok(result?)
+    }
+
+    /// 通过Unix domain socket发送请求, reqwest不支持, 所以直接使用hyper
+    pub(crate) async fn execute_unix(&self, path: &Path, req: reqwest::Request) -> Result<Response> {
+        let url = req.url().clone();
+        let uri = hyperlocal::Uri::new(path, &request_target(&url)).into();
+        let request = self.hyper_request(&req, uri, "--unix-socket")?;
+        let client = hyper::Client::builder().build::<_, hyper::Body>(hyperlocal::UnixConnector);
+        let resp = client
+            .request(request)
+            .await
+            .map_err(|e| unix_socket_error(path, e))?;
+        self.store_cookies(&url, &resp);
+        Ok(Response::from(resp))
+    }
+
+    /// 不经过reqwest发送时把请求转换成hyper的请求, flag用于不支持上传文件时的提示
+    pub(crate) fn hyper_request(
+        &self,
+        req: &reqwest::Request,
+        uri: hyper::Uri,
+        flag: &str,
+    ) -> Result<hyper::Request<hyper::Body>> {
+        let url = req.url();
+        let body = match req.body() {
+            None => hyper::Body::empty(),
+            Some(body) => match body.as_bytes() {
+                Some(bytes) => hyper::Body::from(bytes.to_vec()),
+                None => return Err(anyhow!("Uploading files is not supported with {}", flag)),
+            },
+        };
+
+        let mut headers = req.headers().clone();
+        // 不设置Host时hyper会使用编码后的socket路径
+        if !headers.contains_key(header::HOST) {
+            headers.insert(header::HOST, HeaderValue::from_str(&host_header(url))?);
+        }
+        // cookie也需要自己处理
+        if !headers.contains_key(header::COOKIE) {
+            if let Some(cookies) = self.cookies.cookies(url) {
+                headers.insert(header::COOKIE, cookies);
+            }
+        }
+        let mut request = hyper::Request::builder()
+            .method(req.method().clone())
+            .uri(uri)
+            .body(body)?;
+        *request.headers_mut() = headers;
+        Ok(request)
+    }
+
+    pub(crate) fn store_cookies(&self, url: &Url, resp: &hyper::Response<hyper::Body>) {
+        self.cookies
+            .set_cookies(&mut resp.headers().get_all(header::SET_COOKIE).iter(), url);
+    }
+}
+
+/// 请求行中的路径和查询字符串
+pub(crate) fn request_target(url: &Url) -> String {
+    let mut target = url.path().to_string();
+    if let Some(query) = url.query() {
+        target.push('?');
+        target.push_str(query);
+    }
+    target
+}
+
+/// URL对应的Host请求头, 只有非默认端口时才带上端口
+pub(crate) fn host_header(url: &Url) -> String {
+    match (url.host_str(), url.port()) {
+        (Some(host), Some(port)) => format!("{}:{}", host, port),
+        (Some(host), None) => host.to_string(),
+        (None, _) => "localhost".to_string(),
+    }
+}
+
+impl Context {
+    /// 根据命令行参数创建client, 读取会话和cookie
+    pub fn from_args(opt: &App) -> Result<Self> {
+        if let (SubCommand::Post(args), true) = (&opt.subcommand, opt.json) {
+            if args.form {
+                return Err(anyhow!("--json can't be used together with --form"));
+            }
+        }
+        let url = opt.subcommand.url().unwrap_or_default();
+        let session = match (&opt.session, &opt.session_read_only) {
+            (Some(name), _) => Some(SessionFile::open(name, url, false)?),
+            (_, Some(name)) => Some(SessionFile::open(name, url, true)?),
+            _ => None,
+        };
+
+        let aws = match opt.auth_type {
+            AuthType::Aws4 => Some(AwsSigner::from_args(opt)?),
+            _ => None,
+        };
+        // 认证信息的优先级: 命令行 > 会话 > 环境变量 > netrc, --auth-type netrc 时只使用netrc
+        let auth = match Auth::from_args(opt)? {
+            Some(auth) => Some(auth),
+            None if opt.auth_type == AuthType::Netrc => netrc_auth(opt, url)?,
+            None => match session.as_ref().and_then(|s| s.session.auth.clone()).or_else(Auth::from_env) {
+                Some(auth) => Some(auth),
+                None if aws.is_none() && opt.oauth2_token_url.is_none() => netrc_auth(opt, url)?,
+                None => None,
+            },
+        };
+        let auth = auth.filter(|_| aws.is_none());
+        let digest = match &auth {
+            Some(Auth::Digest { user, password }) => Some(DigestAuth::new(user.clone(), password.clone())),
+            _ => None,
+        };
+        let cookies = match (&opt.cookie_jar, &session) {
+            (Some(path), _) => load_cookie_jar(path)?,
+            (_, Some(session)) => session.session.cookie_store()?,
+            _ => CookieStore::default(),
+        };
+        let cookies = Arc::new(CookieStoreMutex::new(cookies));
+        let resume = match (opt.continue_download, &opt.output) {
+            (Some(mode), Some(output)) => {
+                Some(Resume::prepare(output, mode.unwrap_or(ContinueMode::Restart))?)
+            }
+            _ => None,
+        };
+        let mut builder = Client::builder().cookie_provider(cookies.clone());
+        if let Some(secs) = opt.timeout {
+            builder = builder.timeout(Duration::from_secs_f64(secs));
+        }
+        if opt.insecure || opt.verify == Verify::No {
+            // 输出到stderr, 不影响stdout中的内容
+            if opt.quiet < 2 {
+                eprintln!(
+                    "{}",
+                    "warning: TLS certificate verification is disabled".yellow()
+                );
+            }
+            builder = builder.danger_accept_invalid_certs(true);
+        }
+        if let Verify::CaBundle(path) = &opt.verify {
+            for cert in load_ca_bundle(path)? {
+                builder = builder.add_root_certificate(cert);
+            }
+        }
+        if let Some(identity) = load_identity(opt)? {
+            builder = builder.identity(identity);
+        }
+        // 同一个主机名可以给出多个地址, reqwest 会依次尝试
+        let mut overrides: BTreeMap<&str, Vec<SocketAddr>> = BTreeMap::new();
+        for r in opt.resolve.iter() {
+            overrides.entry(&r.host).or_default().push(r.addr);
+        }
+        // -4 / -6 对 --resolve 中的地址同样生效
+        let family = IpFamily::from_args(opt)?;
+        let mut resolve = Vec::new();
+        for (host, addrs) in overrides {
+            let addrs = match family {
+                Some(family) => family.filter(host, addrs.into_iter())?,
+                None => addrs,
+            };
+            builder = builder.resolve_to_addrs(host, &addrs);
+            resolve.extend(addrs.into_iter().map(|addr| (host.to_string(), addr)));
+        }
+        if let Some(family) = family {
+            builder = builder.dns_resolver(Arc::new(FamilyResolver(family)));
+        }
+        if let Some(ip) = opt.interface {
+            builder = builder.local_address(ip);
+        }
+        if opt.http1 {
+            builder = builder.http1_only();
+        }
+        if opt.http2 {
+            builder = builder.http2_prior_knowledge();
+        }
+        // --follow 和 --no-follow 中后出现的会覆盖前面的
+        let follow = opt.follow && !opt.no_follow;
+        let redirects = RedirectLog::default();
+        // --all 时由 follow_redirects 跟随, reqwest不跟随
+        let policy = redirect_policy(follow && !opt.all, opt.max_redirects, redirects.clone());
+        builder = builder.redirect(policy);
+        let client = with_proxies(builder, opt)?.build()?;
+
+        Ok(Context {
+            client,
+            cookies,
+            cookie_jar: opt.cookie_jar.clone(),
+            extra_cookies: opt.cookie.clone(),
+            session,
+            ignore_stdin: opt.ignore_stdin,
+            chunked: opt.chunked,
+            auth,
+            follow,
+            all: opt.all,
+            max_redirects: opt.max_redirects,
+            http2: opt.http2,
+            insecure: opt.insecure || opt.verify == Verify::No,
+            unix_socket: opt.unix_socket.clone(),
+            // 下载时默认不压缩, 保存下来的就是服务器上的原始文件
+            compression: match opt.compress_response {
+                Compression::Auto if opt.download => Compression::None,
+                compression => compression,
+            },
+            user_agent: opt.user_agent.clone(),
+            accept: match (&opt.accept, opt.json) {
+                (Some(mime), _) => Some(HeaderValue::from_str(mime.as_ref())?),
+                (None, true) => Some(HeaderValue::from_static("application/json, */*;q=0.5")),
+                (None, false) => None,
+            },
+            content_type: opt.content_type.clone(),
+            print: PrintParts::from_args(opt),
+            output: opt.output.clone(),
+            download: opt.download,
+            resume,
+            style: opt.style.clone().unwrap_or_else(|| DEFAULT_STYLE.to_string()),
+            charset: opt.response_charset.map(|c| c.0),
+            pretty_limit: opt.pretty_limit,
+            compress: opt.compress.map(|compression| compression.unwrap_or(BodyCompression::Gzip)),
+            pretty: Pretty::from_args(opt),
+            max_body_size: Some(opt.max_body_size as u64).filter(|size| *size > 0),
+            show_partial: opt.show_partial,
+            meta: opt.meta,
+            verbose: opt.verbose,
+            sort_keys: opt.sort_keys,
+            json_indent: if opt.tab { "\t".to_string() } else { " ".repeat(opt.indent) },
+            filter: opt.filter.clone(),
+            stream: opt.stream,
+            max_events: opt.max_events,
+            duration: opt.duration.map(Duration::from_secs_f64),
+            dump_headers: opt.dump_headers.clone(),
+            dump_headers_append: opt.dump_headers_append,
+            redirects,
+            har: opt.har.clone().map(HarRecorder::new),
+            transcript: opt.log_file.clone().map(|path| Transcript::new(path, opt.log_redact.clone())),
+            timings: match opt.timings || opt.timings_json {
+                true => Some(PhaseTimer {
+                    tls: timings_tls_connector(opt)?,
+                    resolve: resolve.clone(),
+                    timeout: opt.timeout.map(Duration::from_secs_f64),
+                    json: opt.timings_json,
+                    family,
+                    local_address: opt.interface,
+                    connection: Default::default(),
+                }),
+                false => None,
+            },
+            digest,
+            aws,
+            oauth2: match (&opt.oauth2_token_url, &opt.oauth2_client_id, &opt.oauth2_client_secret) {
+                (Some(token_url), Some(client_id), Some(Secret(client_secret))) => Some(OAuth2Client {
+                    token_url: token_url.clone(),
+                    client_id: client_id.clone(),
+                    client_secret: client_secret.clone(),
+                    scope: opt.oauth2_scope.clone(),
+                    force_refresh: opt.oauth2_force_refresh,
+                    dir: oauth2_token_dir()?,
+                    token: Default::default(),
+                }),
+                _ => None,
+            },
+            tls_info: Some(TlsInspector {
+                ca_bundle: match &opt.verify {
+                    Verify::CaBundle(path) => Some(path.clone()),
+                    _ => None,
+                },
+                resolve,
+                timeout: opt.timeout.map(Duration::from_secs_f64),
+                family,
+            })
+            .filter(|_| opt.tls_info),
+            interface: opt.interface,
+            family,
+            upload: UploadProgress::new(opt.quiet == 0 && io::stderr().is_terminal()),
+            etag_cache: match opt.etag_cache {
+                true => Some(EtagCache::new(etag_cache_dir()?, opt.show_cached)),
+                false => None,
+            },
+            expect: Expectations::from_args(opt),
+            response_cache: match opt.cache {
+                true => Some(ResponseCache {
+                    dir: response_cache_dir()?,
+                    bypass: opt.cache_bypass,
+                    max_size: opt.cache_max_size as u64,
+                }),
+                false => None,
+            },
+            history: if opt.no_history { None } else { history_path().ok() },
+            check_status: opt.check_status,
+            quiet: opt.quiet,
+            offline: opt.offline,
+            curl: opt.curl,
+            curl_redact: opt.curl_redact,
+            repeat: opt.repeat,
+            watch: opt.watch.map(|secs| Watch {
+                every: Duration::from_secs_f64(secs),
+                append: opt.watch_append,
+                until: opt.watch_until_status.clone(),
+            }),
+            repeat_delay: opt.delay.map(Duration::from_millis).unwrap_or_default(),
+            concurrency: opt.concurrency.unwrap_or(1) as usize,
+            config_headers: opt.config_headers.clone(),
+            retry: RetryPolicy {
+                retries: opt.retry,
+                delay: opt.retry_delay,
+                on_status: opt.retry_on.clone(),
+                non_idempotent: opt.retry_non_idempotent,
+                respect_retry_after: opt.respect_retry_after,
+                retry_after_max: Duration::from_secs_f64(opt.retry_after_max),
+            },
+        })
+    }
+}
+
+/// 不跟随时直接返回3xx响应, 跟随时最多重定向max次
+pub(crate) fn redirect_policy(follow: bool, max: usize, log: RedirectLog) -> redirect::Policy {
+    if !follow {
+        return redirect::Policy::none();
+    }
+
+    redirect::Policy::custom(move |attempt| {
+        log.lock().unwrap().push((attempt.status(), attempt.url().clone()));
+        if attempt.previous().len() > max {
+            // 把访问过的地址都列出来, 方便找到重定向的循环
+            let mut chain = attempt.previous().to_vec();
+            chain.push(attempt.url().clone());
+            attempt.error(TooManyRedirects { max, chain })
+        } else {
+            attempt.follow()
+        }
+    })
+}
+
+/// 需要跟随的重定向响应中的Location
+fn redirect_location(resp: &Response) -> Option<String> {
+    let redirect = [
+        StatusCode::MOVED_PERMANENTLY,
+        StatusCode::FOUND,
+        StatusCode::SEE_OTHER,
+        StatusCode::TEMPORARY_REDIRECT,
+        StatusCode::PERMANENT_REDIRECT,
+    ];
+    if !redirect.contains(&resp.status()) {
+        return None;
+    }
+    resp.headers().get(header::LOCATION)?.to_str().ok().map(str::to_string)
+}
+
+/// 按照RFC 7231得到重定向之后的请求: 303改为GET (HEAD除外), 301和302时POST改为GET,
+/// 307和308保持方法和body. 改为GET时去掉body, 换了主机时去掉认证信息和Cookie
+pub(crate) fn redirect_request(mut req: reqwest::Request, status: StatusCode, url: Url) -> reqwest::Request {
+    let method = match status {
+        StatusCode::SEE_OTHER if req.method() != Method::HEAD => Method::GET,
+        StatusCode::MOVED_PERMANENTLY | StatusCode::FOUND if req.method() == Method::POST => Method::GET,
+        _ => req.method().clone(),
+    };
+    if method != req.method() {
+        *req.body_mut() = None;
+        for name in [header::CONTENT_TYPE, header::CONTENT_LENGTH, header::CONTENT_ENCODING].iter() {
+            req.headers_mut().remove(name);
+        }
+    }
+    if url.origin() != req.url().origin() {
+        for name in [header::AUTHORIZATION, header::COOKIE, header::PROXY_AUTHORIZATION].iter() {
+            req.headers_mut().remove(name);
+        }
+    }
+    *req.method_mut() = method;
+    *req.url_mut() = url;
+    req
+}
+
+/// 读取PEM格式的CA证书文件
+pub(crate) fn load_ca_bundle(path: &Path) -> Result<Vec<Certificate>> {
+    let pem = fs::read(path)
+        .map_err(|e| anyhow!("Failed to read CA bundle {}: {}", path.display(), e))?;
+    let certs = Certificate::from_pem_bundle(&pem)
+        .map_err(|e| anyhow!("Failed to parse CA bundle {}: {}", path.display(), e))?;
+    if certs.is_empty() {
+        return Err(anyhow!("No certificate found in CA bundle {}", path.display()));
+    }
+    Ok(certs)
+}
+
+/// --timings 时使用的TLS设置, 和client的一样处理 --verify, 还不支持客户端证书
+pub(crate) fn timings_tls_connector(opt: &App) -> Result<tokio_native_tls::TlsConnector> {
+    if opt.cert.is_some() {
+        return Err(anyhow!("--timings can't be used with --cert yet"));
+    }
+    let mut builder = native_tls::TlsConnector::builder();
+    builder.danger_accept_invalid_certs(opt.insecure || opt.verify == Verify::No);
+    if let Verify::CaBundle(path) = &opt.verify {
+        let pem = fs::read(path)
+            .map_err(|e| anyhow!("Failed to read CA bundle {}: {}", path.display(), e))?;
+        let certs = X509::stack_from_pem(&pem)
+            .map_err(|e| anyhow!("Failed to parse CA bundle {}: {}", path.display(), e))?;
+        for cert in certs {
+            builder.add_root_certificate(native_tls::Certificate::from_der(&cert.to_der()?)?);
+        }
+    }
+    Ok(builder.build()?.into())
+}
+
+/// 根据 --cert 等参数读取客户端证书
+pub(crate) fn load_identity(opt: &App) -> Result<Option<Identity>> {
+    let cert_path = match &opt.cert {
+        Some(path) => path,
+        None => return Ok(None),
+    };
+    let cert = fs::read(cert_path).map_err(|e| {
+        anyhow!("Failed to read client certificate {}: {}", cert_path.display(), e)
+    })?;
+    let identity = match opt.cert_format {
+        CertFormat::P12 => {
+            if opt.cert_key.is_some() {
+                return Err(anyhow!("--cert-key can't be used with --cert-format p12"));
+            }
+            // 没有给出密码时先试一下空密码, 失败了再提示输入
+            let result = match &opt.cert_pass {
+                Some(Secret(pass)) => Identity::from_pkcs12_der(&cert, pass),
+                None => match Identity::from_pkcs12_der(&cert, "") {
+                    Ok(identity) => Ok(identity),
+                    Err(_) => Identity::from_pkcs12_der(&cert, &prompt_cert_pass(cert_path)?),
+                },
+            };
+            result.map_err(|e| {
+                anyhow!("Failed to load PKCS#12 file {}: {}", cert_path.display(), e)
+            })?
+        }
+        CertFormat::Pem => {
+            let key_path = opt.cert_key.as_deref().unwrap_or(cert_path);
+            let key = if key_path == cert_path {
+                cert.clone()
+            } else {
+                fs::read(key_path).map_err(|e| {
+                    anyhow!("Failed to read private key {}: {}", key_path.display(), e)
+                })?
+            };
+            let x509 = X509::from_pem(&cert).map_err(|e| {
+                anyhow!("Invalid client certificate {}: {}", cert_path.display(), e)
+            })?;
+            let pkey = if String::from_utf8_lossy(&key).contains("ENCRYPTED") {
+                let pass = match &opt.cert_pass {
+                    Some(Secret(pass)) => pass.clone(),
+                    None => prompt_cert_pass(key_path)?,
+                };
+                PKey::private_key_from_pem_passphrase(&key, pass.as_bytes())
+            } else {
+                PKey::private_key_from_pem(&key)
+            }
+            .map_err(|e| anyhow!("Invalid private key {}: {}", key_path.display(), e))?;
+            if !x509.public_key()?.public_eq(&pkey) {
+                return Err(anyhow!(
+                    "Private key {} doesn't match certificate {}",
+                    key_path.display(),
+                    cert_path.display()
+                ));
+            }
+            // native-tls 只接受PKCS#8格式的私钥, 所以统一转换一下
+            Identity::from_pkcs8_pem(&cert, &pkey.private_key_to_pem_pkcs8()?).map_err(|e| {
+                anyhow!("Invalid client certificate {}: {}", cert_path.display(), e)
+            })?
+        }
+    };
+    Ok(Some(identity))
+}
+
+pub(crate) fn prompt_cert_pass(path: &Path) -> Result<String> {
+    let prompt = format!("http: passphrase for {}: ", path.display());
+    Ok(rpassword::prompt_password(prompt)?)
+}
+
+/// 根据命令行设置代理
+/// 手动设置了代理后reqwest不再读取代理的环境变量, 这里仍然遵守 NO_PROXY
+pub(crate) fn with_proxies(mut builder: ClientBuilder, opt: &App) -> Result<ClientBuilder> {
+    if opt.no_proxy {
+        return Ok(builder.no_proxy());
+    }
+
+    // 先匹配的代理优先, 所以按照协议区分的代理要在所有请求的代理之前添加
+    let scheme = |scheme: &'static str| {
+        opt.proxy.iter().filter(move |p| p.scheme.as_deref() == Some(scheme)).map(|p| &p.url)
+    };
+    let all = opt.proxy.iter().filter(|p| p.scheme.is_none()).map(|p| &p.url);
+    let proxies = opt
+        .proxy_http
+        .iter()
+        .chain(scheme("http"))
+        .map(|url| (url, Proxy::http as fn(Url) -> reqwest::Result<Proxy>))
+        .chain(opt.proxy_https.iter().chain(scheme("https")).map(|url| (url, Proxy::https as _)))
+        .chain(all.map(|url| (url, Proxy::all as _)));
+    for (url, proxy) in proxies {
+        // url中的 user:pass 会作为代理的 Basic 认证, 通过 Proxy-Authorization 发送
+        // 和reqwest读取环境变量中的代理时一样遵守 NO_PROXY
+        let proxy = proxy(url.clone())?.no_proxy(NoProxy::from_env());
+        builder = builder.proxy(proxy);
+    }
+    Ok(builder)
+}
+
+/// 根据子命令构造请求, 返回请求和命令行中的请求项
+pub(crate) async fn build_request<'c>(
+    ctx: &Context,
+    cmd: &'c SubCommand,
+) -> Result<(RequestBuilder, &'c [RequestItem])> {
+    Ok(match cmd {
+        SubCommand::Get(args) => {
+            let url = args.url.as_deref().ok_or_else(|| anyhow!("get needs a URL or --url-file"))?;
+            (ctx.client.get(url), &args.items)
+        }
+        SubCommand::Post(args) => {
+            if args.form && ctx.content_type.is_some() {
+                return Err(anyhow!(
+                    "--content-type can't be used together with --form, \
+                     form bodies are always application/x-www-form-urlencoded"
+                ));
+            }
+            let req = ctx.client.post(&args.url);
+            let empty = EmptyBody::Object;
+            let req = with_body(req, &args.items, ctx.stdin_body(), empty, args.form, &ctx.upload).await?;
+            (req, &args.items)
+        }
+        SubCommand::Put(args) => {
+            let req = ctx.client.put(&args.url);
+            let empty = EmptyBody::Object;
+            let req = with_body(req, &args.items, ctx.stdin_body(), empty, false, &ctx.upload).await?;
+            (req, &args.items)
+        }
+        SubCommand::Delete(args) => {
+            let req = ctx.client.delete(&args.url);
+            // 有些严格的服务器会拒绝带body的DELETE，所以没有key=value时不设置body和Content-Type
+            let empty = EmptyBody::Omit;
+            let req = with_body(req, &args.items, ctx.stdin_body(), empty, false, &ctx.upload).await?;
+            (req, &args.items)
+        }
+        SubCommand::Patch(args) => {
+            let req = ctx.client.patch(&args.url);
+            let empty = EmptyBody::Object;
+            let mut req = with_body(req, &args.items, ctx.stdin_body(), empty, false, &ctx.upload).await?;
+            // json() 会设置 application/json, 这里用 headers() 替换掉它，而不是再追加一个
+            // 有 key@path 时body是multipart表单, 要保留带boundary的Content-Type
+            if !args.items.iter().any(|item| matches!(item, RequestItem::Upload(..))) {
+                let mut content_type = HeaderMap::new();
+                content_type.insert(header::CONTENT_TYPE, args.merge_type.content_type().parse()?);
+                req = req.headers(content_type);
+            }
+            (req, &args.items)
+        }
+        SubCommand::Head(args) => (ctx.client.head(&args.url), &args.items),
+        SubCommand::Options(args) => {
+            let mut preflight = HeaderMap::new();
+            if let Some(origin) = &args.origin {
+                preflight.insert(header::ORIGIN, origin.clone());
+            }
+            if let Some(method) = &args.request_method {
+                preflight.insert(header::ACCESS_CONTROL_REQUEST_METHOD, method.as_str().parse()?);
+            }
+            if let Some(headers) = &args.request_headers {
+                preflight.insert(header::ACCESS_CONTROL_REQUEST_HEADERS, headers.clone());
+            }
+            // 命令行中的 Header:value 优先于 --origin 等选项
+            let req = ctx.client.request(Method::OPTIONS, &args.url).headers(preflight);
+            (req, &args.items)
+        }
+        SubCommand::Request(args) => {
+            let req = ctx.client.request(args.method.clone(), &args.url);
+            let empty = EmptyBody::Omit;
+            let req = with_body(req, &args.items, ctx.stdin_body(), empty, false, &ctx.upload).await?;
+            (req, &args.items)
+        }
+        SubCommand::Graphql(args) => (graphql_request(ctx, args).await?, &args.items),
+        SubCommand::Replay(args) => (replay_request(ctx, args).await?, &args.items),
+        SubCommand::Ws(_)
+        | SubCommand::Diff(_)
+        | SubCommand::Run(_)
+        | SubCommand::Repl(_)
+        | SubCommand::Serve(_)
+        | SubCommand::History(_)
+        | SubCommand::Config(_)
+        | SubCommand::Completions(_) => {
+            return Err(anyhow!("This subcommand does not send a request"))
+        }
+    })
+}
+
+/// 发送子命令对应的请求, 把请求和响应按照 --print 输出到out
+///
+/// --repeat 时ctx要在多个task之间共享, 所以这里使用Arc
+pub async fn run(ctx: &Arc<Context>, cmd: &SubCommand, out: &mut impl Output) -> Result<()> {
+    if let SubCommand::Ws(args) = cmd {
+        return ws::run(ctx, args, out).await;
+    }
+    if let SubCommand::Diff(args) = cmd {
+        return diff::run(ctx, args, out).await;
+    }
+    if let SubCommand::Run(args) = cmd {
+        return collection::run(ctx, args, out).await;
+    }
+    if let Some(count) = ctx.repeat {
+        return bench::run(ctx, cmd, count, out).await;
+    }
+    if let SubCommand::Get(args @ Get { url_file: Some(_), .. }) = cmd {
+        return batch::run(ctx, args, out).await;
+    }
+    if ctx.watch.is_some() {
+        return watch::run(ctx, cmd, out).await;
+    }
+    send_and_print(ctx, cmd, out).await.map(|_| ())
+}
+
+/// 发送一次请求并打印响应, 返回响应的状态码, --offline 时没有响应
+pub(crate) async fn send_and_print(
+    ctx: &Context,
+    cmd: &SubCommand,
+    out: &mut impl Output,
+) -> Result<Option<StatusCode>> {
+    let (req, items) = build_request(ctx, cmd).await?;
+    let Some(mut resp) = ctx.send(req, items, out).await? else {
+        return Ok(None);
+    };
+    let status = resp.status();
+    if let (Some(path), Some(entry)) = (&ctx.history, resp.extensions_mut().remove()) {
+        record(path, entry, &resp)?;
+    }
+    // 有缓存时304不算错误
+    if let Some(entry) = ctx.etag_cache.as_ref().and_then(|cache| cache.not_modified(&resp)) {
+        print_not_modified(ctx, out, resp, entry)?;
+        ctx.save_records()?;
+        return Ok(Some(status));
+    }
+    let result = match cmd {
+        SubCommand::Head(_) => {
+            let meta = Meta::new(ctx, &resp);
+            // HEAD 响应没有body, 只打印状态码和header (其中的Content-Length就是资源的大小)
+            if ctx.print.response_headers {
+                print_head(out, &resp)?;
+            }
+            meta.print(0);
+            ctx.check_status(resp.status())
+        }
+        SubCommand::Options(_) => {
+            let meta = Meta::new(ctx, &resp);
+            let status = resp.status();
+            if ctx.print.response_headers {
+                print_head(out, &resp)?;
+                print_cors(out, &resp)?;
+            }
+            // 使用 -o 时总是保存body
+            let mut size = 0;
+            if ctx.print.response_body || ctx.output.is_some() {
+                size = print_resp_body(ctx, out, resp).await?;
+            } else if ctx.records_body() {
+                record_unprinted_body(ctx, resp).await?;
+            }
+            meta.print(size);
+            ctx.check_status(status)
+        }
+        SubCommand::Get(args) if args.follow_pagination => paginate(ctx, args, resp, out).await,
+        SubCommand::Graphql(_) => print_graphql(ctx, out, resp).await,
+        _ => print_resp(ctx, out, resp).await,
+    };
+    // --check-status 的错误状态码也要记录下来
+    ctx.save_records()?;
+    result?;
+    if let Some(expect) = &ctx.expect {
+        expect.verify()?;
+    }
+    Ok(Some(status))
+}
+
+/// 读取完body的响应, 供把httpie当作库使用的程序处理
+#[derive(Debug, Clone)]
+pub struct HttpResponse {
+    pub status: StatusCode,
+    pub version: Version,
+    pub headers: HeaderMap,
+    /// 跟随重定向之后最终请求的地址
+    pub url: Url,
+    /// 按照Content-Encoding解压之后的body
+    pub body: Vec<u8>,
+}
+
+impl HttpResponse {
+    /// 按照Content-Type中的charset把body解码成字符串
+    pub fn text(&self) -> String {
+        let mime = self
+            .headers
+            .get(header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse::<Mime>().ok());
+        let encoding = text_encoding(&self.body, mime.as_ref(), None);
+        encoding.decode(&self.body).0.into_owned()
+    }
+
+    /// 把body解析成JSON
+    pub fn json(&self) -> Result<Value> {
+        serde_json::from_slice(&self.body).map_err(|e| anyhow!("Failed to parse JSON body: {}", e))
+    }
+}
+
+impl Context {
+    /// 发送子命令对应的请求并读取整个body, 什么都不打印
+    /// 使用 --check-status 时错误的状态码返回 ErrorStatus
+    pub async fn fetch(&self, cmd: &SubCommand) -> Result<HttpResponse> {
+        let (req, items) = build_request(self, cmd).await?;
+        let resp = match self.send(req, items, &mut io::sink()).await? {
+            Some(resp) => resp,
+            None => return Err(anyhow!("--offline does not send the request")),
+        };
+        let status = resp.status();
+        let version = resp.version();
+        let headers = resp.headers().clone();
+        let url = resp.url().clone();
+        let body = read_body(self, resp).await?;
+        self.save_records()?;
+        self.check_status(status)?;
+        Ok(HttpResponse { status, version, headers, url, body })
+    }
+}
+
+/// 像命令行一样解析参数并发送请求, 第一个参数是程序名, 例如
+/// `fetch(["httpie", "post", "localhost:3000/users", "name=tom"])`
+pub async fn fetch<I, T>(args: I) -> Result<HttpResponse>
+where
+    I: IntoIterator<Item = T>,
+    T: Into<OsString> + Clone,
+{
+    let mut opt = App::from_iter_safe(args)?;
+    opt.load_items_files()?;
+    opt.substitute_vars()?;
+    opt.expand_url()?;
+    let ctx = Context::from_args(&opt)?;
+    ctx.fetch(&opt.subcommand).await
+}
+
+/// 没有任何body来源时的处理方式
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum EmptyBody {
+    /// 发送空的json对象 {}, 这样依然会带上 Content-Type: application/json
+    Object,
+    /// 不发送body, 也不设置 Content-Type
+    Omit,
+}
+
+/// 怎样使用标准输入作为请求的body
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum StdinBody {
+    /// 标准输入不是终端时读取全部内容, 作为json发送
+    Json,
+    /// --ignore-stdin, 不读取标准输入
+    Ignore,
+    /// --chunked, 边读边发送, 不设置 Content-Length
+    Chunked,
+}
+
+/// 设置请求的body, 依次尝试以下来源:
+/// 0. --chunked 时以流的方式发送标准输入, 不能和其他来源一起使用
+/// 1. 命令行中有 key@path 时, 作为 multipart 表单上传
+/// 2. 标准输入不是终端时从中读到的json
+/// 3. 命令行中的 @path, 整个文件作为body
+/// 4. 命令行中的 key=value 和 key=@path, form为true时作为表单发送, 否则作为json
+///
+/// 不同的来源不能同时使用
+pub(crate) async fn with_body(
+    req: RequestBuilder,
+    items: &[RequestItem],
+    stdin: StdinBody,
+    empty: EmptyBody,
+    form: bool,
+    upload: &UploadProgress,
+) -> Result<RequestBuilder> {
+    let raw_files = items
+        .iter()
+        .filter_map(|item| match item {
+            RequestItem::RawBodyFile(path) => Some(path),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    let has_fields = items.iter().any(|item| {
+        matches!(
+            item,
+            RequestItem::Body(_) | RequestItem::BodyFile(..) | RequestItem::RawJson(..)
+        )
+    });
+
+    if stdin == StdinBody::Chunked {
+        let uploads = items.iter().any(|item| matches!(item, RequestItem::Upload(..)));
+        if has_fields || uploads || !raw_files.is_empty() {
+            return Err(anyhow!(
+                "--chunked sends stdin as the body, it can't be used together with \
+                 key=value, key@path or @path items"
+            ));
+        }
+        if form {
+            return Err(anyhow!("--chunked can't be used together with --form"));
+        }
+        return Ok(req.body(stdin_stream()));
+    }
+
+    if items.iter().any(|item| matches!(item, RequestItem::Upload(..))) {
+        if !raw_files.is_empty() {
+            return Err(anyhow!("@path body can't be used together with key@path uploads"));
+        }
+        return Ok(req.multipart(multipart_form(items, upload).await?));
+    }
+
+    // 表单模式下不读取标准输入
+    let stdin = if stdin == StdinBody::Ignore || form {
+        None
+    } else {
+        read_stdin_json().await?
+    };
+
+    if let Some(raw) = stdin {
+        if has_fields || !raw_files.is_empty() {
+            return Err(anyhow!(
+                "Request body from stdin and body items can't be used together, \
+                 use --ignore-stdin to ignore stdin"
+            ));
+        }
+        // 原样发送读到的内容
+        return Ok(req
+            .header(header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+            .body(raw));
+    }
+
+    match raw_files[..] {
+        [] => {}
+        [_] if form => return Err(anyhow!("@path body can't be used together with --form")),
+        [path] if !has_fields => {
+            // 文件只读一次, 读到的内容直接交给body, 不再复制
+            let raw = read_file(path).await?;
+            let mime = mime_guess::from_path(path).first_or_octet_stream();
+            return Ok(req.header(header::CONTENT_TYPE, mime.as_ref()).body(raw));
+        }
+        [_] => return Err(anyhow!("@path body can't be used together with key=value items")),
+        _ => return Err(anyhow!("Only one @path body is allowed")),
+    }
+
+    if form {
+        // form() 会设置 Content-Type 并对值做百分号编码 (包括unicode以及值中的 & 和 =)
+        return Ok(req.form(&form_fields(items).await?));
+    }
+
+    let body = body_fields(items).await?;
+    match empty {
+        EmptyBody::Omit if body.is_empty() => Ok(req),
+        _ => Ok(req.json(&body)),
+    }
+}
+
+/// 构造multipart表单: key@path 作为文件, key=value 作为文本
+pub(crate) async fn multipart_form(
+    items: &[RequestItem],
+    upload: &UploadProgress,
+) -> Result<multipart::Form> {
+    let mut form = multipart::Form::new();
+    for (k, v) in form_fields(items).await? {
+        form = form.text(k, v);
+    }
+
+    for item in items {
+        if let RequestItem::Upload(k, path, mime) = item {
+            // 在发送请求之前就打开文件, 这样文件不存在时不会发出请求
+            let file = tokio::fs::File::open(path)
+                .await
+                .map_err(|e| anyhow!("Failed to open {}: {}", path.display(), e))?;
+            let len = file.metadata().await?.len();
+            let mime = mime
+                .clone()
+                .unwrap_or_else(|| mime_guess::from_path(path).first_or_octet_stream());
+            // 以流的方式上传, 不会把整个文件读到内存中
+            let body = Body::wrap_stream(upload.wrap_stream(len, ReaderStream::new(file)));
+            let mut part = multipart::Part::stream_with_length(body, len).mime_str(mime.as_ref())?;
+            if let Some(name) = path.file_name() {
+                part = part.file_name(name.to_string_lossy().into_owned());
+            }
+            form = form.part(k.clone(), part);
+        }
+    }
+
+    Ok(form)
+}
+
+/// 读取文件内容, 出错时带上文件路径
+pub(crate) async fn read_file(path: &Path) -> Result<Vec<u8>> {
+    tokio::fs::read(path)
+        .await
+        .map_err(|e| anyhow!("Failed to read {}: {}", path.display(), e))
+}
+
+/// --chunked 时的body: 读到多少就发送多少, reqwest 会使用 Transfer-Encoding: chunked
+///
+/// 读取过程中按下 Ctrl-C 时body以错误结束, 请求被中止, 服务器不会把收到的部分当作完整的body
+fn stdin_stream() -> Body {
+    let reader = ReaderStream::new(tokio::io::stdin());
+    let interrupted = Box::pin(tokio::signal::ctrl_c());
+    let chunks = futures_util::stream::unfold(Some((reader, interrupted)), |state| async move {
+        let (mut reader, mut interrupted) = state?;
+        tokio::select! {
+            chunk = reader.next() => chunk.map(|chunk| (chunk, Some((reader, interrupted)))),
+            _ = &mut interrupted => {
+                let e = io::Error::new(io::ErrorKind::Interrupted, "interrupted while reading stdin");
+                Some((Err(e), None))
+            }
+        }
+    });
+    Body::wrap_stream(chunks)
+}
+
+/// 标准输入不是终端时读取全部内容，并检查是否是合法的json
+/// 读到的内容为空 (例如 < /dev/null) 时当作没有输入
+pub(crate) async fn read_stdin_json() -> Result<Option<Vec<u8>>> {
+    if io::stdin().is_terminal() {
+        return Ok(None);
+    }
+
+    let mut raw = Vec::new();
+    tokio::io::stdin().read_to_end(&mut raw).await?;
+    if raw.iter().all(|b| b.is_ascii_whitespace()) {
+        return Ok(None);
+    }
+
+    // serde_json的错误信息里带有出错的行号和列号
+    serde_json::from_slice::<serde_json::Value>(&raw)
+        .map_err(|e| anyhow!("Request body from stdin is not valid JSON: {}", e))?;
+    Ok(Some(raw))
+}
+
+/// 把命令行中的key=value, key:=json和key=@path收集成json body
+/// key可以是 user[name] / user[roles][0] / tags[] 这样的路径, 用来构造嵌套的json
+pub(crate) async fn body_fields(items: &[RequestItem]) -> Result<Map<String, Value>> {
+    let mut body = Value::Object(Map::new());
+    let mut assigned = HashMap::new();
+    for item in items {
+        let (k, v) = match item {
+            RequestItem::Body(pair) => (&pair.k, Value::String(pair.v.clone())),
+            RequestItem::RawJson(k, v) => (k, v.clone()),
+            RequestItem::BodyFile(k, path) => (k, Value::String(read_text_file(path).await?)),
+            _ => continue,
+        };
+        set_path(&mut body, &parse_path(k)?, v, k, &mut assigned)?;
+    }
+
+    match body {
+        Value::Object(body) => Ok(body),
+        _ => unreachable!("the root of the body is always an object"),
+    }
+}
+
+/// body字段路径中的一段
+#[derive(Debug)]
+pub(crate) enum PathSegment {
+    /// 对象的字段, user 或者 [name]
+    Key(String),
+    /// 数组的下标, [0]
+    Index(usize),
+    /// 追加到数组末尾, []
+    Append,
+}
+
+/// 把 user[roles][0] 解析成 [Key("user"), Key("roles"), Index(0)]
+pub(crate) fn parse_path(key: &str) -> Result<Vec<PathSegment>> {
+    let err = || anyhow!("Invalid body field path {}", key);
+    let (first, mut rest) = match key.find('[') {
+        Some(i) => key.split_at(i),
+        None => (key, ""),
+    };
+
+    let mut path = vec![PathSegment::Key(first.to_string())];
+    while !rest.is_empty() {
+        let inner = rest.strip_prefix('[').ok_or_else(err)?;
+        let end = inner.find(']').ok_or_else(err)?;
+        let seg = &inner[..end];
+        path.push(if seg.is_empty() {
+            PathSegment::Append
+        } else if let Ok(i) = seg.parse() {
+            PathSegment::Index(i)
+        } else {
+            PathSegment::Key(seg.to_string())
+        });
+        rest = &inner[end + 1..];
+    }
+
+    Ok(path)
+}
+
+/// 按照路径把value设置到body中, 中间缺少的对象或数组会自动创建
+///
+/// assigned 记录已经设置过的位置 (JSON pointer) 以及是否已经重复过, 这样 tags:=[1] tags=2
+/// 得到 [[1], "2"] 而不是把2追加到用户给出的数组中, key:=null 之后的同名key也不会覆盖它
+pub(crate) fn set_path(
+    root: &mut Value,
+    path: &[PathSegment],
+    value: Value,
+    key: &str,
+    assigned: &mut HashMap<String, bool>,
+) -> Result<()> {
+    let mut cur = root;
+    let mut pointer = String::new();
+    for (i, seg) in path.iter().enumerate() {
+        // 已经存在的值类型不对时给出提示, 例如 user=x 之后又出现 user[name]=y
+        let conflict = |expected: &str, found: &Value| {
+            anyhow!(
+                "Can't set {}: {} is {}, expected {}",
+                key,
+                path_prefix(key, i),
+                json_type(found),
+                expected
+            )
+        };
+        let slot = match seg {
+            PathSegment::Key(k) => {
+                if cur.is_null() {
+                    *cur = Value::Object(Map::new());
+                }
+                pointer.push_str(&format!("/{}", k.replace('~', "~0").replace('/', "~1")));
+                match cur {
+                    Value::Object(map) => map.entry(k.clone()).or_insert(Value::Null),
+                    other => return Err(conflict("an object", other)),
+                }
+            }
+            PathSegment::Index(_) | PathSegment::Append => {
+                if cur.is_null() {
+                    *cur = Value::Array(Vec::new());
+                }
+                match cur {
+                    Value::Array(arr) => {
+                        let idx = match seg {
+                            PathSegment::Index(idx) => *idx,
+                            _ => arr.len(),
+                        };
+                        // 跳过的下标用null填充
+                        if arr.len() <= idx {
+                            arr.resize(idx + 1, Value::Null);
+                        }
+                        pointer.push_str(&format!("/{}", idx));
+                        &mut arr[idx]
+                    }
+                    other => return Err(conflict("an array", other)),
+                }
+            }
+        };
+        cur = slot;
+    }
+
+    // 重复的key组成数组, 例如 tag=a tag=b 得到 {"tag": ["a", "b"]}
+    match (assigned.get(&pointer).copied(), cur) {
+        // 之前只是作为中间的路径创建出来, 或者是跳过的下标
+        (None, slot @ Value::Null) => *slot = value,
+        // tags[]=a 创建的数组, 和 tags=b 一起组成 ["a", "b"]
+        (None, Value::Array(arr)) => arr.push(value),
+        (None, cur) => {
+            return Err(anyhow!(
+                "Can't set {}: it already holds {}",
+                key,
+                json_type(cur)
+            ))
+        }
+        (Some(true), Value::Array(arr)) => arr.push(value),
+        (Some(_), other) => {
+            *other = Value::Array(vec![other.take(), value]);
+            assigned.insert(pointer, true);
+            return Ok(());
+        }
+    }
+    assigned.entry(pointer).or_insert(false);
+    Ok(())
+}
+
+/// 路径中前n段 (不含第n段) 对应的原始key, 用于错误提示
+pub(crate) fn path_prefix(key: &str, n: usize) -> &str {
+    let mut end = key.find('[').unwrap_or(key.len());
+    for _ in 1..n {
+        match key[end..].find(']') {
+            Some(i) => end += i + 1,
+            None => break,
+        }
+    }
+    &key[..end]
+}
+
+pub(crate) fn json_type(v: &Value) -> &'static str {
+    match v {
+        Value::Null => "null",
+        Value::Bool(_) => "a boolean",
+        Value::Number(_) => "a number",
+        Value::String(_) => "a string",
+        Value::Array(_) => "an array",
+        Value::Object(_) => "an object",
+    }
+}
+
+/// 把命令行中的key=value和key=@path收集成表单的字段
+/// 表单的值只能是字符串, 所以不支持 key:=json
+pub(crate) async fn form_fields(items: &[RequestItem]) -> Result<Vec<(String, String)>> {
+    let mut fields = Vec::new();
+    for item in items {
+        match item {
+            RequestItem::Body(pair) => fields.push((pair.k.clone(), pair.v.clone())),
+            RequestItem::BodyFile(k, path) => fields.push((k.clone(), read_text_file(path).await?)),
+            RequestItem::RawJson(k, _) => {
+                return Err(anyhow!(
+                    "{}:= is a JSON value and can't be sent as a form field",
+                    k
+                ))
+            }
+            _ => {}
+        }
+    }
+    Ok(fields)
+}
+
+pub(crate) async fn read_text_file(path: &Path) -> Result<String> {
+    String::from_utf8(read_file(path).await?)
+        .map_err(|_| anyhow!("{} is not valid UTF-8 text", path.display()))
+}
+
+/// 内置的默认请求头, 只有 User-Agent: httpie-rs/<版本>, 其他的请求头在配置文件的 [default_headers] 中设置
+pub(crate) fn default_headers() -> Result<HeaderMap> {
+    let mut headers = HeaderMap::new();
+    headers.insert(header::USER_AGENT, concat!("httpie-rs/", env!("CARGO_PKG_VERSION")).parse()?);
+    Ok(headers)
+}
+
+/// 把命令行中的请求头和查询参数设置到请求上
+/// 请求头的优先级: 命令行中的 Header:value > extra (会话中保存的 > 配置文件中的) > 内置的默认请求头
+/// 命令行中的 Header: 在 Context::build 中最后处理, 所以三者中的请求头都可以去掉
+pub(crate) fn with_items(
+    req: RequestBuilder,
+    items: &[RequestItem],
+    extra: HeaderMap,
+) -> Result<RequestBuilder> {
+    let mut custom = HeaderMap::new();
+    let mut query = Vec::new();
+    for item in items {
+        match item {
+            // 同名header可以出现多次
+            RequestItem::Header(name, value) => {
+                custom.append(name, value.clone());
+            }
+            // 同名参数会重复出现在查询字符串中，而不是互相覆盖
+            RequestItem::Query(k, v) => query.push((k, v)),
+            _ => {}
+        }
+    }
+
+    let mut headers = default_headers()?;
+    // extend 会用custom中的值替换掉同名的默认值
+    headers.extend(extra);
+    headers.extend(custom);
+    // query() 会对参数做百分号编码
+    Ok(req.headers(headers).query(&query))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retry_after_accepts_seconds_and_http_dates() {
+        let now = httpdate::parse_http_date("Wed, 21 Oct 2015 07:28:00 GMT").unwrap();
+        let secs = |value| parse_retry_after(value, now).map(|d| d.as_secs());
+        assert_eq!(secs("120"), Some(120));
+        assert_eq!(secs("Wed, 21 Oct 2015 07:28:30 GMT"), Some(30));
+        // 已经过去的时间表示马上重试
+        assert_eq!(secs("Wed, 21 Oct 2015 07:00:00 GMT"), Some(0));
+        assert_eq!(secs("soon"), None);
+    }
+
+    #[test]
+    fn redirects_switch_the_method_per_rfc_7231() {
+        let post = || {
+            let mut req = reqwest::Request::new(Method::POST, "http://a.test/form".parse().unwrap());
+            *req.body_mut() = Some("x=1".into());
+            req.headers_mut().insert(header::CONTENT_TYPE, HeaderValue::from_static("text/plain"));
+            req.headers_mut().insert(header::AUTHORIZATION, HeaderValue::from_static("Bearer t"));
+            req
+        };
+        let same = "http://a.test/next".parse::<Url>().unwrap();
+        for status in [StatusCode::FOUND, StatusCode::SEE_OTHER].iter() {
+            let next = redirect_request(post(), *status, same.clone());
+            assert_eq!(next.method(), Method::GET);
+            assert!(next.body().is_none());
+            assert!(!next.headers().contains_key(header::CONTENT_TYPE));
+            assert!(next.headers().contains_key(header::AUTHORIZATION));
+        }
+        let next = redirect_request(post(), StatusCode::TEMPORARY_REDIRECT, same);
+        assert_eq!(next.method(), Method::POST);
+        assert_eq!(next.body().and_then(|b| b.as_bytes()), Some(&b"x=1"[..]));
+        let other = redirect_request(post(), StatusCode::PERMANENT_REDIRECT, "http://b.test/".parse().unwrap());
+        assert_eq!(other.method(), Method::POST);
+        assert!(!other.headers().contains_key(header::AUTHORIZATION));
+    }
+
+    #[tokio::test]
+    async fn repeated_keys_become_arrays_and_repeated_form_fields() {
+        let items = ["tag=a", "tag=b", "tags:=[1]", "tags=2", "user[name]=x", "tag=c", "none:=null", "none=x"]
+            .iter()
+            .map(|s| s.parse().unwrap())
+            .collect::<Vec<RequestItem>>();
+        let body = body_fields(&items).await.unwrap();
+        // 用户给出的数组作为一个值, 显式的null也不会被后面的值覆盖
+        assert_eq!(
+            Value::Object(body),
+            serde_json::json!({
+                "tag": ["a", "b", "c"],
+                "tags": [[1], "2"],
+                "user": {"name": "x"},
+                "none": [null, "x"]
+            })
+        );
+        let nested = ["list:=[]", "list=a", "list=b", "ids[2]=c", "ids[0]=d", "ids[]=e", "ids=f"]
+            .iter()
+            .map(|s| s.parse().unwrap())
+            .collect::<Vec<RequestItem>>();
+        let body = body_fields(&nested).await.unwrap();
+        assert_eq!(Value::Object(body), serde_json::json!({"list": [[], "a", "b"], "ids": ["d", null, "c", "e", "f"]}));
+        let conflict = ["user[name]=x".parse().unwrap(), "user=y".parse().unwrap()];
+        assert!(body_fields(&conflict).await.is_err());
+
+        let form = form_fields(&items[..2]).await.unwrap();
+        assert_eq!(form, [("tag".to_string(), "a".to_string()), ("tag".to_string(), "b".to_string())]);
+    }
+}
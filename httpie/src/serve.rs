@@ -0,0 +1,240 @@
+use std::{
+    convert::Infallible,
+    io::Write,
+    net::{Ipv4Addr, SocketAddr},
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use colored::Colorize;
+use hyper::{
+    header,
+    http::request::Parts,
+    server::conn::AddrStream,
+    service::{make_service_fn, service_fn},
+    Body, Request, Response, Server, StatusCode,
+};
+use reqwest::Url;
+use serde_json::{json, Map, Value};
+
+use crate::{cli::Serve, output::styled_status};
+
+/// /delay/<seconds> 最多等待的秒数, 和 httpbin 一样
+const MAX_DELAY: f64 = 10.0;
+
+/// 请求的路径对应的处理方式
+#[derive(Debug, PartialEq)]
+enum Route {
+    /// 其他所有的路径: 把请求原样以JSON返回
+    Echo,
+    /// /status/<code>: 返回这个状态码, 没有body
+    Status(StatusCode),
+    /// /delay/<seconds>: 等待之后再回显
+    Delay(Duration),
+    /// /gzip: 回显, body使用gzip压缩
+    Gzip,
+}
+
+fn route(path: &str) -> Result<Route> {
+    let mut segments = path.trim_start_matches('/').splitn(2, '/');
+    match (segments.next(), segments.next()) {
+        (Some("status"), Some(code)) => {
+            let status = code
+                .parse::<u16>()
+                .ok()
+                .and_then(|code| StatusCode::from_u16(code).ok())
+                .filter(|status| (200..=599).contains(&status.as_u16()))
+                .ok_or_else(|| anyhow!("Invalid status {}, expected a number between 200 and 599", code))?;
+            Ok(Route::Status(status))
+        }
+        (Some("delay"), Some(secs)) => {
+            let secs = secs
+                .parse::<f64>()
+                .ok()
+                .filter(|secs| secs.is_finite() && *secs >= 0.0)
+                .ok_or_else(|| anyhow!("Invalid delay {}, expected a number of seconds", secs))?;
+            Ok(Route::Delay(Duration::from_secs_f64(secs.min(MAX_DELAY))))
+        }
+        (Some("gzip"), None) => Ok(Route::Gzip),
+        _ => Ok(Route::Echo),
+    }
+}
+
+/// serve 子命令: 在本地启动一个HTTP服务器, 用来试验和调试客户端, 类似 httpbin 的 /anything
+///
+/// Ctrl-C 时不再接受新的连接, 等正在处理的请求完成之后退出
+pub async fn run(args: &Serve, quiet: u8) -> Result<()> {
+    let ip = if args.public { Ipv4Addr::UNSPECIFIED } else { Ipv4Addr::LOCALHOST };
+    let addr = SocketAddr::from((ip, args.port));
+    let builder = Server::try_bind(&addr).map_err(|e| anyhow!("Failed to listen on {}: {}", addr, e))?;
+    let log = quiet == 0;
+    let make_service = make_service_fn(move |conn: &AddrStream| {
+        let remote = conn.remote_addr();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req: Request<Body>| async move {
+                let started = Instant::now();
+                let (method, uri) = (req.method().clone(), req.uri().clone());
+                let resp = handle(req, remote).await;
+                if log {
+                    let elapsed = format!("{}ms", started.elapsed().as_millis()).dimmed();
+                    eprintln!("{} {} {} {}", method, uri, styled_status(resp.status()), elapsed);
+                }
+                Ok::<_, Infallible>(resp)
+            }))
+        }
+    });
+    let server = builder.serve(make_service);
+    if log {
+        eprintln!("Listening on http://{}, Ctrl-C to stop", server.local_addr());
+    }
+    server
+        .with_graceful_shutdown(async {
+            let _ = tokio::signal::ctrl_c().await;
+        })
+        .await?;
+    if log {
+        eprintln!("{}", "Stopped".dimmed());
+    }
+    Ok(())
+}
+
+async fn handle(req: Request<Body>, remote: SocketAddr) -> Response<Body> {
+    let route = match route(req.uri().path()) {
+        Ok(route) => route,
+        Err(e) => return json_response(StatusCode::BAD_REQUEST, &json!({ "error": e.to_string() })),
+    };
+    if let Route::Status(status) = route {
+        return Response::builder().status(status).body(Body::empty()).unwrap();
+    }
+    if let Route::Delay(delay) = route {
+        tokio::time::sleep(delay).await;
+    }
+    let (parts, body) = req.into_parts();
+    let body = match hyper::body::to_bytes(body).await {
+        Ok(body) => body,
+        Err(e) => {
+            let error = json!({ "error": format!("Failed to read the body: {}", e) });
+            return json_response(StatusCode::BAD_REQUEST, &error);
+        }
+    };
+    let mut echo = echo(&parts, &body, remote);
+    if route == Route::Gzip {
+        echo["gzipped"] = Value::Bool(true);
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        let compressed = encoder
+            .write_all(&serde_json::to_vec_pretty(&echo).unwrap_or_default())
+            .and_then(|_| encoder.finish());
+        if let Ok(compressed) = compressed {
+            return Response::builder()
+                .header(header::CONTENT_TYPE, "application/json")
+                .header(header::CONTENT_ENCODING, "gzip")
+                .body(Body::from(compressed))
+                .unwrap();
+        }
+    }
+    json_response(StatusCode::OK, &echo)
+}
+
+fn json_response(status: StatusCode, value: &Value) -> Response<Body> {
+    let mut body = serde_json::to_vec_pretty(value).unwrap_or_default();
+    body.push(b'\n');
+    Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body))
+        .unwrap()
+}
+
+/// 收到的请求: 方法, 路径, 查询参数, 请求头和body
+///
+/// body是JSON时原样放进去, 否则使用base64编码, body_encoding 表示是哪一种;
+/// 重复的查询参数是数组, 重复的请求头用 ", " 连接
+fn echo(parts: &Parts, body: &[u8], remote: SocketAddr) -> Value {
+    let host = parts.headers.get(header::HOST).and_then(|v| v.to_str().ok()).unwrap_or("localhost");
+    let url = format!("http://{}{}", host, parts.uri);
+    let mut query = Map::new();
+    if let Ok(url) = Url::parse(&url) {
+        for (k, v) in url.query_pairs() {
+            let v = Value::String(v.into_owned());
+            match query.get_mut(k.as_ref()) {
+                Some(Value::Array(values)) => values.push(v),
+                Some(first) => *first = Value::Array(vec![first.take(), v]),
+                None => {
+                    query.insert(k.into_owned(), v);
+                }
+            }
+        }
+    }
+    let mut headers = Map::new();
+    for name in parts.headers.keys() {
+        let values = parts
+            .headers
+            .get_all(name)
+            .iter()
+            .map(|v| String::from_utf8_lossy(v.as_bytes()).into_owned())
+            .collect::<Vec<_>>();
+        headers.insert(name.to_string(), Value::String(values.join(", ")));
+    }
+
+    let mut echo = json!({
+        "method": parts.method.as_str(),
+        "url": url,
+        "path": parts.uri.path(),
+        "query": query,
+        "headers": headers,
+        "origin": remote.ip().to_string(),
+    });
+    let (body, encoding) = match serde_json::from_slice::<Value>(body) {
+        _ if body.is_empty() => (Value::Null, None),
+        Ok(value) => (value, Some("json")),
+        Err(_) => (Value::String(STANDARD.encode(body)), Some("base64")),
+    };
+    echo["body"] = body;
+    if let Some(encoding) = encoding {
+        echo["body_encoding"] = Value::from(encoding);
+    }
+    echo
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paths_are_routed_and_checked() {
+        assert_eq!(route("/anything/else").unwrap(), Route::Echo);
+        assert_eq!(route("/status/418").unwrap(), Route::Status(StatusCode::IM_A_TEAPOT));
+        assert_eq!(route("/delay/0.5").unwrap(), Route::Delay(Duration::from_millis(500)));
+        assert_eq!(route("/delay/3600").unwrap(), Route::Delay(Duration::from_secs(10)));
+        assert_eq!(route("/gzip").unwrap(), Route::Gzip);
+        assert!(route("/status/abc").is_err());
+        assert!(route("/status/101").is_err());
+        assert!(route("/delay/-1").is_err());
+    }
+
+    #[test]
+    fn requests_are_echoed_as_json() {
+        let req = Request::post("/anything?tag=a&tag=b&page=2")
+            .header("host", "localhost:8080")
+            .header("x-trace", "1")
+            .header("x-trace", "2")
+            .body(())
+            .unwrap();
+        let (parts, _) = req.into_parts();
+        let remote = SocketAddr::from((Ipv4Addr::LOCALHOST, 50000));
+
+        let echoed = echo(&parts, br#"{"name":"alice"}"#, remote);
+        assert_eq!(echoed["method"], "POST");
+        assert_eq!(echoed["url"], "http://localhost:8080/anything?tag=a&tag=b&page=2");
+        assert_eq!(echoed["query"], json!({ "tag": ["a", "b"], "page": "2" }));
+        assert_eq!(echoed["headers"]["x-trace"], "1, 2");
+        assert_eq!(echoed["body"], json!({ "name": "alice" }));
+        assert_eq!(echoed["body_encoding"], "json");
+
+        let echoed = echo(&parts, b"\xff\x00", remote);
+        assert_eq!(echoed["body"], "/wA=");
+        assert_eq!(echoed["body_encoding"], "base64");
+        assert_eq!(echo(&parts, b"", remote)["body"], Value::Null);
+    }
+}
@@ -0,0 +1,244 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    time::{Duration, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, Result};
+use cookie_store::{CookieDomain, CookieExpiration};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::Url;
+use reqwest_cookie_store::CookieStore;
+use serde::{Deserialize, Serialize};
+
+use crate::{cli::*, config::*, request::*};
+
+/// 读取 Netscape (curl) 格式的cookie文件, 文件不存在时返回空的CookieStore
+/// 每行的格式为: domain include_subdomains path secure expires name value, 以tab分隔
+pub(crate) fn load_cookie_jar(path: &Path) -> Result<CookieStore> {
+    let mut store = CookieStore::default();
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(store),
+        Err(e) => return Err(anyhow!("Failed to read {}: {}", path.display(), e)),
+    };
+
+    for (no, line) in content.lines().enumerate() {
+        // curl 用 #HttpOnly_ 前缀表示 HttpOnly 的cookie, 其他以 # 开头的是注释
+        let (line, http_only) = match line.strip_prefix("#HttpOnly_") {
+            Some(line) => (line, true),
+            None => (line, false),
+        };
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let fields = line.split('\t').collect::<Vec<_>>();
+        let (domain, subdomains, cookie_path, secure, expires, name, value) = match fields[..] {
+            [domain, subdomains, cookie_path, secure, expires, name, value] => {
+                (domain, subdomains, cookie_path, secure, expires, name, value)
+            }
+            _ => {
+                return Err(anyhow!(
+                    "{}:{}: invalid cookie line, expected 7 tab separated fields",
+                    path.display(),
+                    no + 1
+                ))
+            }
+        };
+        let expires: u64 = expires.parse().map_err(|_| {
+            anyhow!("{}:{}: invalid expires {}", path.display(), no + 1, expires)
+        })?;
+
+        // 转换成Set-Cookie的格式, 交给CookieStore解析
+        let host = domain.trim_start_matches('.');
+        let secure = secure.eq_ignore_ascii_case("TRUE");
+        let mut cookie = format!("{}={}; Path={}", name, value, cookie_path);
+        if subdomains.eq_ignore_ascii_case("TRUE") {
+            cookie.push_str(&format!("; Domain={}", host));
+        }
+        if secure {
+            cookie.push_str("; Secure");
+        }
+        if http_only {
+            cookie.push_str("; HttpOnly");
+        }
+        // expires为0表示会话cookie
+        if expires > 0 {
+            let at = UNIX_EPOCH + Duration::from_secs(expires);
+            cookie.push_str(&format!("; Expires={}", httpdate::fmt_http_date(at)));
+        }
+
+        let scheme = if secure { "https" } else { "http" };
+        let url = Url::parse(&format!("{}://{}{}", scheme, host, cookie_path))
+            .map_err(|e| anyhow!("{}:{}: {}", path.display(), no + 1, e))?;
+        // 已经过期的cookie会被忽略
+        let _ = store.parse(&cookie, &url);
+    }
+
+    Ok(store)
+}
+
+/// 把没有过期的cookie以 Netscape (curl) 格式写入文件
+/// cookie里可能有登录凭证, 所以文件的权限是 0600
+pub(crate) fn save_cookie_jar(store: &CookieStore, path: &Path) -> Result<()> {
+    let mut content = String::from("# Netscape HTTP Cookie File\n");
+    for cookie in store.iter_unexpired() {
+        let (domain, subdomains) = match &cookie.domain {
+            CookieDomain::HostOnly(domain) => (domain.clone(), "FALSE"),
+            CookieDomain::Suffix(domain) => (format!(".{}", domain), "TRUE"),
+            _ => continue,
+        };
+        let expires = match cookie.expires {
+            CookieExpiration::AtUtc(at) => at.unix_timestamp().max(0),
+            CookieExpiration::SessionEnd => 0,
+        };
+        let bool_str = |b: bool| if b { "TRUE" } else { "FALSE" };
+        if cookie.http_only().unwrap_or(false) {
+            content.push_str("#HttpOnly_");
+        }
+        content.push_str(&format!(
+            "{}\t{}\t{}\t{}\t{}\t{}\t{}\n",
+            domain,
+            subdomains,
+            cookie.path.as_ref(),
+            bool_str(cookie.secure().unwrap_or(false)),
+            expires,
+            cookie.name(),
+            cookie.value()
+        ));
+    }
+
+    write_private_file(path, content.as_bytes())
+        .map_err(|e| anyhow!("Failed to write {}: {}", path.display(), e))
+}
+
+/// 以 0600 的权限写入文件, 用于保存cookie等敏感信息
+pub(crate) fn write_private_file(path: &Path, content: &[u8]) -> io::Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+        options.mode(0o600);
+        // 文件已经存在时mode不会生效, 需要单独设置
+        if path.exists() {
+            fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+        }
+    }
+    options.open(path)?.write_all(content)
+}
+
+/// 会话中保存的内容
+#[derive(Default, Serialize, Deserialize)]
+pub(crate) struct Session {
+    #[serde(default)]
+    pub(crate) headers: BTreeMap<String, String>,
+    #[serde(default)]
+    pub(crate) auth: Option<Auth>,
+    #[serde(default)]
+    pub(crate) cookies: Vec<cookie_store::Cookie<'static>>,
+}
+
+impl Session {
+    pub(crate) fn header_map(&self) -> HeaderMap {
+        self.headers
+            .iter()
+            .filter_map(|(name, value)| {
+                Some((
+                    HeaderName::from_bytes(name.as_bytes()).ok()?,
+                    HeaderValue::from_str(value).ok()?,
+                ))
+            })
+            .collect()
+    }
+
+    pub(crate) fn cookie_store(&self) -> Result<CookieStore> {
+        let cookies = self.cookies.iter().cloned().map(Ok::<_, anyhow::Error>);
+        CookieStore::from_cookies(cookies, false)
+    }
+}
+
+/// 会话文件, 路径为 <配置目录>/httpie-rs/sessions/<host>_<port>/<name>.json
+/// 不同主机的会话分开保存, 这样cookie不会发送给其他主机
+pub(crate) struct SessionFile {
+    pub(crate) path: PathBuf,
+    pub(crate) session: Session,
+    pub(crate) read_only: bool,
+}
+
+impl SessionFile {
+    /// 读取会话文件, 不存在时得到空的会话
+    /// name中含有路径分隔符或者以 .json 结尾时直接作为文件路径
+    pub(crate) fn open(name: &str, url: &str, read_only: bool) -> Result<Self> {
+        let path = if name.contains(std::path::MAIN_SEPARATOR) || name.ends_with(".json") {
+            PathBuf::from(name)
+        } else {
+            let url: Url = url.parse()?;
+            let host = format!(
+                "{}_{}",
+                url.host_str().unwrap_or("localhost"),
+                url.port_or_known_default().unwrap_or(80)
+            );
+            config_dir()?
+                .join("sessions")
+                .join(host.replace(':', "_"))
+                .join(format!("{}.json", name))
+        };
+
+        let session = match fs::read(&path) {
+            Ok(content) => serde_json::from_slice(&content)
+                .map_err(|e| anyhow!("Invalid session file {}: {}", path.display(), e))?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Session::default(),
+            Err(e) => return Err(anyhow!("Failed to read {}: {}", path.display(), e)),
+        };
+
+        Ok(Self {
+            path,
+            session,
+            read_only,
+        })
+    }
+
+    /// 用这次请求的请求头, 认证信息和cookie更新会话文件
+    pub(crate) fn update(&self, items: &[RequestItem], auth: Option<Auth>, cookies: &CookieStore) -> Result<()> {
+        if self.read_only {
+            return Ok(());
+        }
+
+        let mut headers = self.session.headers.clone();
+        for item in items {
+            match item {
+                RequestItem::Header(name, value) => {
+                    // 和body或者条件请求相关的header只对这一次请求有意义, 不保存
+                    let name = name.as_str();
+                    if name.starts_with("content-") || name.starts_with("if-") {
+                        continue;
+                    }
+                    if let Ok(value) = value.to_str() {
+                        headers.insert(name.to_string(), value.to_string());
+                    }
+                }
+                // Header: 同时会把它从会话中去掉
+                RequestItem::RemoveHeader(name) => {
+                    headers.remove(name.as_str());
+                }
+                _ => {}
+            }
+        }
+
+        let session = Session {
+            headers,
+            auth,
+            cookies: cookies.iter_unexpired().cloned().collect(),
+        };
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let content = serde_json::to_vec_pretty(&session)?;
+        write_private_file(&self.path, &content)
+            .map_err(|e| anyhow!("Failed to write {}: {}", self.path.display(), e))
+    }
+}
@@ -0,0 +1,204 @@
+use std::{
+    io::{self, Write},
+    mem,
+};
+
+use anyhow::Result;
+use colored::Colorize;
+use futures_util::{future, StreamExt};
+use reqwest::Response;
+use serde_json::Value;
+use time::OffsetDateTime;
+
+use crate::{cli::*, output::*, request::*};
+
+/// 一个Server-Sent Event, data的多行之间用 \n 连接
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct Event {
+    pub(crate) event: Option<String>,
+    pub(crate) data: String,
+    pub(crate) id: Option<String>,
+}
+
+/// 按照 text/event-stream 的规范逐行解析收到的数据
+///
+/// 实现了Write, 这样可以放在StreamDecoder后面, 压缩过的事件流也能边收边解析
+#[derive(Debug, Default)]
+pub(crate) struct EventParser {
+    line: Vec<u8>,
+    current: Event,
+    events: Vec<Event>,
+    /// 上一块数据以 \r 结尾, 下一块开头的 \n 和它是同一个换行
+    skip_lf: bool,
+    started: bool,
+}
+
+impl EventParser {
+    /// 取出已经完整收到的事件
+    pub(crate) fn take_events(&mut self) -> Vec<Event> {
+        mem::take(&mut self.events)
+    }
+
+    fn feed(&mut self, data: &[u8]) {
+        for &b in data {
+            if mem::take(&mut self.skip_lf) && b == b'\n' {
+                continue;
+            }
+            match b {
+                b'\n' => self.end_line(),
+                b'\r' => {
+                    self.end_line();
+                    self.skip_lf = true;
+                }
+                _ => self.line.push(b),
+            }
+        }
+    }
+
+    fn end_line(&mut self) {
+        let line = String::from_utf8_lossy(&mem::take(&mut self.line)).into_owned();
+        // 事件流开头可以有BOM
+        let line = match mem::replace(&mut self.started, true) {
+            false => line.trim_start_matches('\u{feff}').to_string(),
+            true => line,
+        };
+        // 空行表示一个事件结束, 没有data的事件不派发
+        if line.is_empty() {
+            let mut event = mem::take(&mut self.current);
+            if !event.data.is_empty() {
+                event.data.pop();
+                self.events.push(event);
+            }
+            return;
+        }
+        // 以冒号开头的是注释, 常用来保持连接
+        if line.starts_with(':') {
+            return;
+        }
+        let (field, value) = match line.split_once(':') {
+            Some((field, value)) => (field, value.strip_prefix(' ').unwrap_or(value)),
+            None => (line.as_str(), ""),
+        };
+        match field {
+            "event" => self.current.event = Some(value.to_string()),
+            "data" => {
+                self.current.data.push_str(value);
+                self.current.data.push('\n');
+            }
+            "id" if !value.contains('\0') => self.current.id = Some(value.to_string()),
+            // retry 和未知的字段忽略
+            _ => {}
+        }
+    }
+}
+
+impl Write for EventParser {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.feed(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+pub(crate) fn is_event_stream(resp: &Response) -> bool {
+    matches!(get_content_type(resp), Ok(Some(m)) if m.essence_str() == "text/event-stream")
+}
+
+/// 收到一个事件就打印一个, 前面加上收到的时间 (UTC), JSON格式的data会格式化
+///
+/// 服务器关闭连接, Ctrl-C, --max-events 或者 --duration 时结束, 返回收到的字节数
+pub(crate) async fn print_events(ctx: &Context, out: &mut impl Write, resp: Response) -> Result<u64> {
+    let encoding = content_encoding(&resp).filter(|_| ctx.compression != Compression::None);
+    let mut decoder = StreamDecoder::new(encoding.as_deref(), EventParser::default())?;
+    let mut stream = resp.bytes_stream();
+    let interrupted = tokio::signal::ctrl_c();
+    let deadline = async {
+        match ctx.duration {
+            Some(duration) => tokio::time::sleep(duration).await,
+            None => future::pending().await,
+        }
+    };
+    tokio::pin!(interrupted, deadline);
+
+    let (mut size, mut count) = (0, 0);
+    loop {
+        let chunk = tokio::select! {
+            chunk = stream.next() => chunk,
+            _ = &mut interrupted => None,
+            _ = &mut deadline => None,
+        };
+        let Some(chunk) = chunk else { break };
+        let chunk = chunk?;
+        size += chunk.len() as u64;
+        ctx.record_body(&chunk);
+        decoder.write_all(&chunk)?;
+        decoder.flush()?;
+        for event in decoder.get_mut().take_events() {
+            print_event(ctx, out, &event)?;
+            count += 1;
+            if ctx.max_events.is_some_and(|max| count >= max) {
+                return Ok(size);
+            }
+        }
+    }
+    // 连接关闭时最后一个没有以空行结束的事件按照规范丢弃
+    Ok(size)
+}
+
+pub(crate) fn print_event(ctx: &Context, out: &mut impl Write, event: &Event) -> io::Result<()> {
+    let now = OffsetDateTime::now_utc();
+    let mut head = format!(
+        "[{:02}:{:02}:{:02}.{:03}Z] event: {}",
+        now.hour(),
+        now.minute(),
+        now.second(),
+        now.millisecond(),
+        event.event.as_deref().unwrap_or("message")
+    );
+    if let Some(id) = &event.id {
+        head.push_str(&format!(", id: {}", id));
+    }
+    writeln!(out, "{}", head.dimmed())?;
+    match serde_json::from_str::<Value>(&event.data) {
+        Ok(Value::Object(_) | Value::Array(_)) => {
+            print_body(ctx, out, Some(mime::APPLICATION_JSON), &event.data)?
+        }
+        _ => writeln!(out, "{}", event.data)?,
+    }
+    out.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(chunks: &[&str]) -> Vec<Event> {
+        let mut parser = EventParser::default();
+        let mut events = Vec::new();
+        for chunk in chunks {
+            parser.write_all(chunk.as_bytes()).unwrap();
+            events.extend(parser.take_events());
+        }
+        events
+    }
+
+    #[test]
+    fn events_are_split_on_blank_lines() {
+        let chunks = ["\u{feff}: keep-alive\nevent: up", "date\ndata: a\ndata:b\nid: 7\n\n", "data: c\n"];
+        let events = parse(&chunks);
+        assert_eq!(
+            events,
+            [Event { event: Some("update".into()), data: "a\nb".into(), id: Some("7".into()) }]
+        );
+    }
+
+    #[test]
+    fn crlf_split_across_chunks_is_one_line_break() {
+        let events = parse(&["data: 1\r", "\n\r", "\nevent: empty\r\n\r\ndata\r\rdata: 2\n\n"]);
+        let data = events.iter().map(|e| e.data.as_str()).collect::<Vec<_>>();
+        assert_eq!(data, ["1", "", "2"]);
+    }
+}
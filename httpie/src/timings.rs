@@ -0,0 +1,285 @@
+use std::{
+    io,
+    net::{IpAddr, SocketAddr},
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Result};
+use hyper::client::conn::SendRequest;
+use futures_util::future::poll_fn;
+use reqwest::{Response, ResponseBuilderExt};
+use serde_json::json;
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::{TcpSocket, TcpStream},
+    sync::Mutex,
+};
+
+use crate::{dns::*, request::*};
+
+/// 瀑布图的宽度
+const WATERFALL_WIDTH: usize = 40;
+
+/// 一个阶段的耗时
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Phase {
+    Took(Duration),
+    /// 复用了上一个请求的keep-alive连接
+    Reused,
+    /// 不需要这个阶段: IP地址和 --resolve 时没有DNS查询, http没有TLS握手
+    Skipped,
+}
+
+impl Phase {
+    fn duration(&self) -> Duration {
+        match self {
+            Phase::Took(d) => *d,
+            _ => Duration::ZERO,
+        }
+    }
+}
+
+/// --timings 记录的各个阶段, 放在响应的extensions中, 读取完body后打印
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct PhaseTimings {
+    pub(crate) dns: Phase,
+    pub(crate) connect: Phase,
+    pub(crate) tls: Phase,
+    /// 发送请求到收到响应头 (time to first byte)
+    pub(crate) waiting: Duration,
+    pub(crate) start: Instant,
+    /// 实际连接的地址
+    pub(crate) remote: SocketAddr,
+}
+
+/// --timings: 不使用reqwest, 自己完成DNS查询, TCP连接和TLS握手, 这样可以分别计时
+/// 只支持HTTP/1.1, 直接连接服务器, 不经过代理, 也不重试
+pub(crate) struct PhaseTimer {
+    pub(crate) tls: tokio_native_tls::TlsConnector,
+    pub(crate) resolve: Vec<(String, SocketAddr)>,
+    pub(crate) timeout: Option<Duration>,
+    /// --timings-json 时打印一行JSON而不是表格
+    pub(crate) json: bool,
+    /// --interface 绑定的本地地址
+    pub(crate) local_address: Option<IpAddr>,
+    /// -4 / -6 限定的地址族
+    pub(crate) family: Option<IpFamily>,
+    /// 上一个请求的连接和它的地址, scheme, host和port都一样时复用
+    pub(crate) connection: Mutex<Option<(String, SocketAddr, SendRequest<hyper::Body>)>>,
+}
+
+impl PhaseTimer {
+    pub(crate) async fn execute(&self, ctx: &Context, req: reqwest::Request) -> Result<Response> {
+        match self.timeout {
+            Some(timeout) => tokio::time::timeout(timeout, self.send(ctx, req))
+                .await
+                .map_err(|_| anyhow!("Request timed out after {:.1}s", timeout.as_secs_f64()))?,
+            None => self.send(ctx, req).await,
+        }
+    }
+
+    async fn send(&self, ctx: &Context, req: reqwest::Request) -> Result<Response> {
+        let url = req.url().clone();
+        let https = url.scheme() == "https";
+        let host = url.host_str().ok_or_else(|| anyhow!("{} has no host", url))?;
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        let port = url.port_or_known_default().unwrap_or(80);
+        let key = format!("{}://{}:{}", url.scheme(), host, port);
+        let start = Instant::now();
+
+        let mut connection = self.connection.lock().await;
+        // 服务器已经关闭的连接不能再用
+        let reused = match connection.take() {
+            Some((k, remote, mut sender)) if k == key => {
+                poll_fn(|cx| sender.poll_ready(cx)).await.ok().map(|_| (remote, sender))
+            }
+            _ => None,
+        };
+        let (remote, mut sender, dns, connect, tls) = match reused {
+            Some((remote, sender)) => {
+                let tls = if https { Phase::Reused } else { Phase::Skipped };
+                (remote, sender, Phase::Reused, Phase::Reused, tls)
+            }
+            None => {
+                let (addrs, dns) = self.lookup(host, port).await?;
+                let connected = Instant::now();
+                let stream = connect_any(&addrs, host, self.local_address).await?;
+                let connect = Phase::Took(connected.elapsed());
+                let remote = stream.peer_addr()?;
+                if https {
+                    let handshake_start = Instant::now();
+                    let stream = self
+                        .tls
+                        .connect(host, stream)
+                        .await
+                        .map_err(|e| anyhow!("TLS handshake with {} failed: {}", key, e))?;
+                    let tls = Phase::Took(handshake_start.elapsed());
+                    (remote, handshake(stream).await?, dns, connect, tls)
+                } else {
+                    (remote, handshake(stream).await?, dns, connect, Phase::Skipped)
+                }
+            }
+        };
+
+        let request = ctx.hyper_request(&req, request_target(&url).parse()?, "--timings")?;
+        let sent = Instant::now();
+        let resp = sender.send_request(request).await?;
+        let waiting = sent.elapsed();
+        *connection = Some((key, remote, sender));
+        ctx.store_cookies(&url, &resp);
+
+        let (parts, body) = resp.into_parts();
+        let builder = hyper::Response::builder().status(parts.status).version(parts.version);
+        let mut builder = builder.url(url);
+        if let Some(headers) = builder.headers_mut() {
+            *headers = parts.headers;
+        }
+        let mut resp = Response::from(builder.body(body)?);
+        resp.extensions_mut().insert(PhaseTimings { dns, connect, tls, waiting, start, remote });
+        Ok(resp)
+    }
+
+    async fn lookup(&self, host: &str, port: u16) -> Result<(Vec<SocketAddr>, Phase)> {
+        // --resolve 中的地址已经按照 -4 / -6 过滤过了
+        if let Some((_, addr)) = self.resolve.iter().find(|(h, _)| h == host) {
+            return Ok((vec![*addr], Phase::Skipped));
+        }
+        if let Ok(ip) = host.parse::<IpAddr>() {
+            return Ok((vec![SocketAddr::new(ip, port)], Phase::Skipped));
+        }
+        let start = Instant::now();
+        let addrs = tokio::net::lookup_host((host, port))
+            .await
+            .map_err(|e| anyhow!("Failed to resolve {}: {}", host, e))?;
+        let addrs = match self.family {
+            Some(family) => family.filter(host, addrs)?,
+            None => addrs.collect(),
+        };
+        Ok((addrs, Phase::Took(start.elapsed())))
+    }
+}
+
+/// 依次尝试每个地址, 和reqwest一样只在地址族相同时绑定本地地址
+async fn connect_any(addrs: &[SocketAddr], host: &str, local: Option<IpAddr>) -> Result<TcpStream> {
+    let mut last_error = None;
+    for addr in addrs {
+        match connect(*addr, local).await {
+            Ok(stream) => return Ok(stream),
+            Err(e) if e.kind() == io::ErrorKind::AddrNotAvailable => {
+                let local = local.map(|ip| ip.to_string()).unwrap_or_default();
+                return Err(anyhow!(
+                    "Failed to bind to --interface {}, the address isn't assigned to this host",
+                    local
+                ));
+            }
+            Err(e) => last_error = Some(e),
+        }
+    }
+    match last_error {
+        Some(e) => Err(anyhow!("Failed to connect to {}: {}", host, e)),
+        None => Err(anyhow!("Failed to resolve {}", host)),
+    }
+}
+
+async fn connect(addr: SocketAddr, local: Option<IpAddr>) -> io::Result<TcpStream> {
+    let socket = match addr {
+        SocketAddr::V4(_) => TcpSocket::new_v4()?,
+        SocketAddr::V6(_) => TcpSocket::new_v6()?,
+    };
+    if let Some(ip) = local.filter(|ip| ip.is_ipv4() == addr.is_ipv4()) {
+        socket.bind(SocketAddr::new(ip, 0))?;
+    }
+    socket.connect(addr).await
+}
+
+/// HTTP/1.1握手, 连接在后台任务中运行, SendRequest被丢弃时关闭
+async fn handshake<T>(io: T) -> Result<SendRequest<hyper::Body>>
+where
+    T: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let (sender, connection) = hyper::client::conn::handshake(io).await?;
+    tokio::spawn(connection);
+    Ok(sender)
+}
+
+/// 读取完body后打印到stderr, total是从开始连接到读取完body的耗时
+pub(crate) fn format_timings(timings: &PhaseTimings, total: Duration, json: bool) -> String {
+    let before_body = timings.dns.duration()
+        + timings.connect.duration()
+        + timings.tls.duration()
+        + timings.waiting;
+    let download = total.saturating_sub(before_body);
+    if json {
+        let secs = |phase: Phase| phase.duration().as_secs_f64();
+        let value = json!({
+            "dns": secs(timings.dns),
+            "connect": secs(timings.connect),
+            "tls": secs(timings.tls),
+            "waiting": timings.waiting.as_secs_f64(),
+            "download": download.as_secs_f64(),
+            "total": total.as_secs_f64(),
+            "reused": timings.connect == Phase::Reused,
+        });
+        return value.to_string();
+    }
+
+    let rows = [
+        ("DNS lookup", timings.dns),
+        ("TCP connect", timings.connect),
+        ("TLS handshake", timings.tls),
+        ("Waiting (TTFB)", Phase::Took(timings.waiting)),
+        ("Content download", Phase::Took(download)),
+    ];
+    let scale = |d: Duration| match total.as_secs_f64() {
+        secs if secs > 0.0 => (d.as_secs_f64() / secs * WATERFALL_WIDTH as f64).round() as usize,
+        _ => 0,
+    };
+    let mut lines = Vec::new();
+    let mut offset = Duration::ZERO;
+    for (name, phase) in rows {
+        let value = match phase {
+            Phase::Took(d) => format!("{:.1}ms", d.as_secs_f64() * 1000.0),
+            Phase::Reused => "reused".to_string(),
+            Phase::Skipped => "-".to_string(),
+        };
+        let (start, len) = (scale(offset).min(WATERFALL_WIDTH), scale(phase.duration()));
+        // 耗时不为0的阶段至少画一格
+        let len = if phase.duration() > Duration::ZERO { len.max(1) } else { 0 };
+        let len = len.min(WATERFALL_WIDTH - start);
+        let bar = format!("{}{}", " ".repeat(start), "█".repeat(len));
+        lines.push(format!("{:<17}{:>9}  |{:<width$}|", name, value, bar, width = WATERFALL_WIDTH));
+        offset += phase.duration();
+    }
+    lines.push(format!("{:<17}{:>9}", "Total", format!("{:.1}ms", total.as_secs_f64() * 1000.0)));
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn skipped_and_reused_phases_keep_their_rows() {
+        let ms = Duration::from_millis;
+        let timings = PhaseTimings {
+            dns: Phase::Reused,
+            connect: Phase::Reused,
+            tls: Phase::Skipped,
+            waiting: ms(30),
+            start: Instant::now(),
+            remote: "127.0.0.1:80".parse().unwrap(),
+        };
+        let table = format_timings(&timings, ms(40), false);
+        let lines = table.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 6);
+        assert!(lines[0].starts_with("DNS lookup          reused  |"), "{}", lines[0]);
+        assert!(lines[2].starts_with("TLS handshake            -  |"), "{}", lines[2]);
+        assert!(lines[3].starts_with("Waiting (TTFB)      30.0ms  |██████████████████████████████"));
+        assert_eq!(lines[5], "Total               40.0ms");
+
+        let value: serde_json::Value = serde_json::from_str(&format_timings(&timings, ms(40), true)).unwrap();
+        assert_eq!(value["reused"], true);
+        assert_eq!(value["tls"], 0.0);
+        assert_eq!(value["waiting"], 0.03);
+    }
+}
@@ -0,0 +1,255 @@
+use std::{
+    convert::TryFrom,
+    net::{SocketAddr, TcpStream, ToSocketAddrs},
+    path::PathBuf,
+    time::Duration,
+};
+
+use anyhow::{anyhow, Result};
+use colored::Colorize;
+use openssl::{
+    hash::MessageDigest,
+    ssl::{SslConnector, SslMethod, SslVerifyMode},
+    x509::{X509NameRef, X509Ref, X509VerifyResult},
+};
+use reqwest::Url;
+
+use crate::{dns::*, output::Output};
+
+/// 没有 --timeout 时握手最多等待这么久
+const DEFAULT_TLS_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// --tls-info: 发送请求之前单独做一次TLS握手, 打印协商的结果和服务器的证书链
+/// 握手时不验证证书, 所以 --verify no 时也能看到验证失败的证书, 验证的结果单独打印
+/// 这次握手直接连接服务器, 不经过代理
+pub(crate) struct TlsInspector {
+    /// --verify 给出的CA证书文件, 和系统的根证书一起使用
+    pub(crate) ca_bundle: Option<PathBuf>,
+    /// --resolve 指定的地址
+    pub(crate) resolve: Vec<(String, SocketAddr)>,
+    pub(crate) timeout: Option<Duration>,
+    /// -4 / -6 限定的地址族
+    pub(crate) family: Option<IpFamily>,
+}
+
+/// 证书链中的一个证书
+#[derive(Debug)]
+pub(crate) struct CertSummary {
+    pub(crate) subject: String,
+    pub(crate) issuer: String,
+    pub(crate) sans: Vec<String>,
+    pub(crate) not_before: String,
+    pub(crate) not_after: String,
+    pub(crate) sha256: String,
+}
+
+#[derive(Debug)]
+pub(crate) struct TlsInfo {
+    pub(crate) version: String,
+    pub(crate) cipher: String,
+    pub(crate) chain: Vec<CertSummary>,
+    /// 证书链验证失败的原因, 验证通过时为None
+    pub(crate) verify_error: Option<String>,
+}
+
+impl TlsInspector {
+    /// 在单独的线程中握手, 不阻塞tokio
+    pub(crate) async fn inspect(&self, url: &Url) -> Result<TlsInfo> {
+        let host = url.host_str().ok_or_else(|| anyhow!("{} has no host", url))?.to_string();
+        let port = url.port_or_known_default().unwrap_or(443);
+        let addr = self.resolve.iter().find(|(h, _)| *h == host).map(|(_, addr)| *addr);
+        let ca_bundle = self.ca_bundle.clone();
+        let timeout = self.timeout.unwrap_or(DEFAULT_TLS_TIMEOUT);
+        let family = self.family;
+        tokio::task::spawn_blocking(move || handshake(&host, port, addr, family, ca_bundle, timeout)).await?
+    }
+}
+
+pub(crate) fn handshake(
+    host: &str,
+    port: u16,
+    addr: Option<SocketAddr>,
+    family: Option<IpFamily>,
+    ca_bundle: Option<PathBuf>,
+    timeout: Duration,
+) -> Result<TlsInfo> {
+    let mut builder = SslConnector::builder(SslMethod::tls())?;
+    if let Some(path) = &ca_bundle {
+        builder
+            .set_ca_file(path)
+            .map_err(|e| anyhow!("Failed to read CA bundle {}: {}", path.display(), e))?;
+    }
+    // 验证失败时也要完成握手, 结果从 verify_result 中取得
+    builder.set_verify(SslVerifyMode::NONE);
+    let connector = builder.build();
+
+    let addrs = match (addr, family) {
+        (Some(addr), _) => vec![addr],
+        (None, Some(family)) => family.filter(host, (host.trim_matches(['[', ']']), port).to_socket_addrs()?)?,
+        (None, None) => (host.trim_matches(['[', ']']), port).to_socket_addrs()?.collect(),
+    };
+    let mut last_error = None;
+    let stream = addrs.iter().find_map(|addr| {
+        TcpStream::connect_timeout(addr, timeout).map_err(|e| last_error = Some(e)).ok()
+    });
+    let stream = match (stream, last_error) {
+        (Some(stream), _) => stream,
+        (None, Some(e)) => return Err(anyhow!("Failed to connect to {}:{}: {}", host, port, e)),
+        (None, None) => return Err(anyhow!("Failed to resolve {}", host)),
+    };
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+    let stream = connector
+        .connect(host.trim_matches(['[', ']']), stream)
+        .map_err(|e| anyhow!("TLS handshake with {}:{} failed: {}", host, port, e))?;
+
+    let ssl = stream.ssl();
+    let chain = ssl
+        .peer_cert_chain()
+        .map(|chain| chain.iter().map(summarize).collect::<Result<Vec<_>>>())
+        .transpose()?
+        .unwrap_or_default();
+    let verify_error = match ssl.verify_result() {
+        X509VerifyResult::OK => None,
+        result => Some(result.error_string().to_string()),
+    };
+    Ok(TlsInfo {
+        version: ssl.version_str().to_string(),
+        cipher: ssl.current_cipher().map(|c| c.name().to_string()).unwrap_or_default(),
+        chain,
+        verify_error,
+    })
+}
+
+/// CN=example.com, O=Example 这样的格式
+fn format_name(name: &X509NameRef) -> String {
+    name.entries()
+        .map(|entry| {
+            let key = entry.object().nid().short_name().unwrap_or("?");
+            let value = entry
+                .data()
+                .to_string()
+                .unwrap_or_else(|_| String::from_utf8_lossy(entry.data().as_slice()).into_owned());
+            format!("{}={}", key, value)
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+pub(crate) fn summarize(cert: &X509Ref) -> Result<CertSummary> {
+    let sans = cert
+        .subject_alt_names()
+        .map(|names| {
+            names
+                .iter()
+                .filter_map(|name| {
+                    if let Some(dns) = name.dnsname() {
+                        return Some(format!("DNS:{}", dns));
+                    }
+                    let ip = match name.ipaddress()? {
+                        [a, b, c, d] => std::net::IpAddr::from([*a, *b, *c, *d]),
+                        bytes => std::net::IpAddr::from(<[u8; 16]>::try_from(bytes).ok()?),
+                    };
+                    Some(format!("IP:{}", ip))
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    let digest = cert.digest(MessageDigest::sha256())?;
+    Ok(CertSummary {
+        subject: format_name(cert.subject_name()),
+        issuer: format_name(cert.issuer_name()),
+        sans,
+        not_before: cert.not_before().to_string(),
+        not_after: cert.not_after().to_string(),
+        sha256: digest.iter().map(|b| format!("{:02X}", b)).collect::<Vec<_>>().join(":"),
+    })
+}
+
+/// 在响应之前打印, 格式和响应头一样
+pub(crate) fn print_tls_info(out: &mut impl Output, info: &TlsInfo) -> std::io::Result<()> {
+    let out = out.meta();
+    writeln!(out, "{}: {} {}", "TLS".green(), info.version, info.cipher)?;
+    match &info.verify_error {
+        None => writeln!(out, "{}: verified", "Certificate chain".green())?,
+        Some(e) => {
+            let status = format!("NOT verified ({})", e).red();
+            writeln!(out, "{}: {}", "Certificate chain".green(), status)?
+        }
+    }
+    for (i, cert) in info.chain.iter().enumerate() {
+        writeln!(out, "  [{}] {}: {}", i, "subject".green(), cert.subject)?;
+        writeln!(out, "      {}: {}", "issuer".green(), cert.issuer)?;
+        if !cert.sans.is_empty() {
+            writeln!(out, "      {}: {}", "SANs".green(), cert.sans.join(", "))?;
+        }
+        writeln!(out, "      {}: {} - {}", "valid".green(), cert.not_before, cert.not_after)?;
+        writeln!(out, "      {}: {}", "SHA-256".green(), cert.sha256)?;
+    }
+    writeln!(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use openssl::{
+        asn1::Asn1Time,
+        bn::BigNum,
+        pkey::PKey,
+        rsa::Rsa,
+        ssl::SslAcceptor,
+        x509::{extension::SubjectAlternativeName, X509Name, X509},
+    };
+    use std::net::TcpListener;
+
+    fn self_signed() -> (X509, PKey<openssl::pkey::Private>) {
+        let key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+        let mut name = X509Name::builder().unwrap();
+        name.append_entry_by_text("CN", "localhost").unwrap();
+        let name = name.build();
+        let mut builder = X509::builder().unwrap();
+        builder.set_version(2).unwrap();
+        builder.set_serial_number(&BigNum::from_u32(1).unwrap().to_asn1_integer().unwrap()).unwrap();
+        builder.set_subject_name(&name).unwrap();
+        builder.set_issuer_name(&name).unwrap();
+        builder.set_pubkey(&key).unwrap();
+        builder.set_not_before(&Asn1Time::days_from_now(0).unwrap()).unwrap();
+        builder.set_not_after(&Asn1Time::days_from_now(1).unwrap()).unwrap();
+        let san = SubjectAlternativeName::new()
+            .dns("localhost")
+            .ip("127.0.0.1")
+            .build(&builder.x509v3_context(None, None))
+            .unwrap();
+        builder.append_extension(san).unwrap();
+        builder.sign(&key, MessageDigest::sha256()).unwrap();
+        (builder.build(), key)
+    }
+
+    #[test]
+    fn self_signed_certificates_are_shown_but_not_verified() {
+        let (cert, key) = self_signed();
+        let mut acceptor = SslAcceptor::mozilla_intermediate_v5(SslMethod::tls()).unwrap();
+        acceptor.set_certificate(&cert).unwrap();
+        acceptor.set_private_key(&key).unwrap();
+        let acceptor = acceptor.build();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let _ = acceptor.accept(stream);
+        });
+
+        let info = handshake("localhost", addr.port(), Some(addr), None, None, DEFAULT_TLS_TIMEOUT).unwrap();
+        server.join().unwrap();
+        assert!(info.version.starts_with("TLSv1."), "{}", info.version);
+        assert!(!info.cipher.is_empty());
+        // OpenSSL 1.1 和 3 的措辞不一样: self signed / self-signed
+        let error = info.verify_error.unwrap();
+        assert!(error.contains("signed certificate"), "{}", error);
+        assert_eq!(info.chain.len(), 1);
+        assert_eq!(info.chain[0].subject, "CN=localhost");
+        assert_eq!(info.chain[0].issuer, "CN=localhost");
+        assert_eq!(info.chain[0].sans, ["DNS:localhost", "IP:127.0.0.1"]);
+        assert_eq!(info.chain[0].sha256.len(), 32 * 3 - 1);
+    }
+}
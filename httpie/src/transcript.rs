@@ -0,0 +1,199 @@
+use std::{
+    fs::OpenOptions,
+    io::Write,
+    path::PathBuf,
+    sync::Mutex,
+};
+
+use anyhow::{anyhow, Result};
+use mime::Mime;
+use reqwest::{
+    header::{self, HeaderMap, HeaderName},
+    Response, StatusCode, Version,
+};
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+
+use crate::{curl::*, output::*};
+
+/// 日志中每个body最多记录的字节数, 超过的部分省略
+pub(crate) const MAX_LOG_BODY: usize = 64 * 1024;
+
+/// --log-file 时把每次请求和响应追加到这个文件中, 内容是不带颜色的纯文本
+pub(crate) struct Transcript {
+    pub(crate) path: PathBuf,
+    /// 除了默认的敏感请求头以外, 还要替换掉值的请求头和响应头
+    pub(crate) redact: Vec<HeaderName>,
+    pub(crate) block: Mutex<Option<PendingBlock>>,
+}
+
+/// 还没有写入文件的一块记录, 响应的body在读取时逐块追加进来
+pub(crate) struct PendingBlock {
+    pub(crate) text: String,
+    pub(crate) response: Option<(Option<Mime>, Option<String>)>,
+    /// 收到的原始body, 可能是压缩过的
+    pub(crate) body: Vec<u8>,
+}
+
+impl Transcript {
+    pub(crate) fn new(path: PathBuf, redact: Vec<HeaderName>) -> Self {
+        Self { path, redact, block: Mutex::new(None) }
+    }
+
+    fn write_headers(&self, text: &mut String, headers: &HeaderMap) {
+        for (name, value) in headers {
+            let value = match is_secret(name) || self.redact.contains(name) {
+                true => redact(name, value.as_bytes()),
+                false => value.as_bytes().to_vec(),
+            };
+            text.push_str(&format!("{}: {}\n", name, String::from_utf8_lossy(&value)));
+        }
+    }
+
+    /// 发送前记录请求, headers是实际发送的请求头
+    pub(crate) fn request(&self, req: &reqwest::Request, headers: &HeaderMap) {
+        let time = OffsetDateTime::now_utc().format(&Rfc3339).unwrap_or_default();
+        let mut url = req.url().clone();
+        if url.password().is_some() {
+            let _ = url.set_password(Some(REDACTED));
+        }
+        let mut text = format!("===== {} =====\n", time);
+        text.push_str(&format!("{} {} {:?}\n", req.method(), url, req.version()));
+        self.write_headers(&mut text, headers);
+        text.push('\n');
+        match req.body().map(|body| body.as_bytes()) {
+            Some(Some(bytes)) if !bytes.is_empty() => {
+                let mime = headers
+                    .get(header::CONTENT_TYPE)
+                    .and_then(|v| v.to_str().ok())
+                    .and_then(|v| v.parse().ok());
+                text.push_str(&format_body(mime.as_ref(), bytes));
+            }
+            Some(None) => text.push_str("[streamed body, not recorded]\n\n"),
+            _ => {}
+        }
+        *self.block.lock().unwrap() = Some(PendingBlock { text, response: None, body: Vec::new() });
+    }
+
+    /// 收到响应头时记录状态行和响应头
+    pub(crate) fn response(&self, resp: &Response) {
+        if let Some(block) = self.block.lock().unwrap().as_mut() {
+            block.text.push_str(&status_line(resp.version(), resp.status()));
+            self.write_headers(&mut block.text, resp.headers());
+            block.text.push('\n');
+            let mime = get_content_type(resp).ok().flatten();
+            block.response = Some((mime, content_encoding(resp)));
+        }
+    }
+
+    /// 只保留解压后够用的部分, 压缩过的body无法截断, 超过上限时不再记录
+    pub(crate) fn body(&self, chunk: &[u8]) {
+        if let Some(block) = self.block.lock().unwrap().as_mut() {
+            let limit = match block.response {
+                Some((_, None)) => MAX_LOG_BODY + 1,
+                _ => 16 * MAX_LOG_BODY,
+            };
+            let n = chunk.len().min(limit.saturating_sub(block.body.len()));
+            block.body.extend_from_slice(&chunk[..n]);
+        }
+    }
+
+    /// 请求失败, 没有收到响应
+    pub(crate) fn error(&self, e: &anyhow::Error) -> Result<()> {
+        if let Some(block) = self.block.lock().unwrap().as_mut() {
+            block.text.push_str(&format!("[no response: {}]\n\n", e.root_cause()));
+        }
+        self.save()
+    }
+
+    /// 把这一块记录追加到文件末尾, 没有发送请求时 (例如 --offline) 什么都不做
+    pub(crate) fn save(&self) -> Result<()> {
+        let Some(PendingBlock { mut text, response, body }) = self.block.lock().unwrap().take() else {
+            return Ok(());
+        };
+        if let Some((mime, encoding)) = response {
+            let body = match encoding {
+                // 解压失败多半是因为body被截断了
+                Some(encoding) => decode_body(&encoding, &body).ok(),
+                None => Some(body),
+            };
+            match body {
+                Some(body) if body.is_empty() => {}
+                Some(body) => text.push_str(&format_body(mime.as_ref(), &body)),
+                None => text.push_str("[compressed body, not recorded]\n\n"),
+            }
+        }
+        // 整块一次写入, 同时运行的多个进程不会把记录写乱
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .and_then(|mut file| file.write_all(text.as_bytes()))
+            .map_err(|e| anyhow!("Failed to write {}: {}", self.path.display(), e))
+    }
+}
+
+fn status_line(version: Version, status: StatusCode) -> String {
+    format!("{:?} {}\n", version, status)
+}
+
+/// 文本body最多记录 MAX_LOG_BODY 字节, 二进制的只记录大小
+pub(crate) fn format_body(mime: Option<&Mime>, body: &[u8]) -> String {
+    if is_binary(mime, body) {
+        return format!("[binary body, {} bytes]\n\n", body.len());
+    }
+    let shown = &body[..body.len().min(MAX_LOG_BODY)];
+    let mut text = String::from_utf8_lossy(shown).into_owned();
+    if !text.ends_with('\n') {
+        text.push('\n');
+    }
+    if shown.len() < body.len() {
+        text.push_str(&format!("[truncated after {} bytes]\n", MAX_LOG_BODY));
+    }
+    text.push('\n');
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn secrets_and_redacted_headers_are_masked() {
+        let dir = std::env::temp_dir().join(format!("httpie-transcript-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("session.log");
+        let transcript = Transcript::new(path.clone(), vec![HeaderName::from_static("x-session")]);
+        let req = reqwest::Client::new()
+            .post("http://localhost/x")
+            .header("authorization", "Bearer abc")
+            .header("x-session", "s1")
+            .header("accept", "*/*")
+            .body("{}")
+            .build()
+            .unwrap();
+        transcript.request(&req, req.headers());
+        transcript.error(&anyhow!("connection refused")).unwrap();
+        let log = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_dir_all(dir).unwrap();
+        let block = log.split_once("=====\n").unwrap().1;
+        assert_eq!(
+            block,
+            "POST http://localhost/x HTTP/1.1\n\
+             authorization: Bearer <redacted>\n\
+             x-session: <redacted>\n\
+             accept: */*\n\
+             \n\
+             {}\n\
+             \n\
+             [no response: connection refused]\n\n"
+        );
+    }
+
+    #[test]
+    fn long_bodies_are_truncated() {
+        let body = "a".repeat(MAX_LOG_BODY + 10);
+        let text = format_body(None, body.as_bytes());
+        assert!(text.ends_with(&format!("a\n[truncated after {} bytes]\n\n", MAX_LOG_BODY)));
+        assert_eq!(format_body(None, &[0, 1, 2]), "[binary body, 3 bytes]\n\n");
+    }
+}
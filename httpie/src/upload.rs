@@ -0,0 +1,122 @@
+use std::time::Duration;
+
+use futures_util::{stream, Stream, StreamExt};
+use hyper::body::Bytes;
+use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
+use reqwest::{header, Body};
+
+/// body小于这个大小时上传很快, 不显示进度条
+pub(crate) const UPLOAD_PROGRESS_MIN: u64 = 1024 * 1024;
+
+/// 把内存中的body切成这么大的块发送, 这样可以更新进度
+const UPLOAD_CHUNK: usize = 64 * 1024;
+
+/// 上传请求body时stderr上的进度条, 发送完之后变成等待响应的spinner
+/// stderr不是终端或者 --quiet 时不显示, 收到响应头时清除, 不会和后面的输出混在一起
+#[derive(Clone)]
+pub(crate) struct UploadProgress {
+    bar: Option<ProgressBar>,
+}
+
+impl UploadProgress {
+    pub(crate) fn new(enabled: bool) -> Self {
+        let bar = ProgressBar::with_draw_target(Some(0), ProgressDrawTarget::hidden());
+        Self { bar: Some(bar).filter(|_| enabled) }
+    }
+
+    /// multipart中以流的方式上传的文件, 大小计入进度条的总长度
+    pub(crate) fn wrap_stream<S>(&self, len: u64, stream: S) -> impl Stream<Item = S::Item>
+    where
+        S: Stream<Item = std::io::Result<Bytes>>,
+    {
+        let bar = self.bar.clone();
+        if let Some(bar) = &bar {
+            bar.inc_length(len);
+        }
+        stream.inspect(move |chunk| {
+            if let (Some(bar), Ok(chunk)) = (&bar, chunk) {
+                advance(bar, chunk.len());
+            }
+        })
+    }
+
+    /// 每次发送 (包括重试) 之前调用, 内存中的body换成逐块发送的流
+    pub(crate) fn track(&self, mut req: reqwest::Request) -> reqwest::Request {
+        let Some(bar) = &self.bar else {
+            return req;
+        };
+        if let Some(bytes) = req.body().and_then(Body::as_bytes) {
+            let len = bytes.len() as u64;
+            bar.set_length(len);
+            if len < UPLOAD_PROGRESS_MIN {
+                return req;
+            }
+            let bytes = Bytes::copy_from_slice(bytes);
+            let chunks = (0..bytes.len()).step_by(UPLOAD_CHUNK).map(move |start| {
+                let end = (start + UPLOAD_CHUNK).min(bytes.len());
+                Ok::<_, std::io::Error>(bytes.slice(start..end))
+            });
+            let bar = bar.clone();
+            let body = stream::iter(chunks).inspect(move |chunk| {
+                if let Ok(chunk) = chunk {
+                    advance(&bar, chunk.len());
+                }
+            });
+            *req.body_mut() = Some(Body::wrap_stream(body));
+            // 流式的body默认使用chunked编码, 这里保留原来的长度
+            req.headers_mut().entry(header::CONTENT_LENGTH).or_insert(len.into());
+        }
+        if bar.length().unwrap_or(0) >= UPLOAD_PROGRESS_MIN {
+            bar.set_position(0);
+            let template = "{bar:30} {bytes}/{total_bytes} {percent}% {bytes_per_sec} ETA {eta}";
+            if let Ok(style) = ProgressStyle::with_template(template) {
+                bar.set_style(style);
+            }
+            bar.set_draw_target(ProgressDrawTarget::stderr());
+        }
+        req
+    }
+
+    /// 收到响应头或者请求失败时清除进度条, 为下一个请求重置
+    pub(crate) fn finish(&self) {
+        if let Some(bar) = &self.bar {
+            bar.finish_and_clear();
+            bar.disable_steady_tick();
+            bar.set_draw_target(ProgressDrawTarget::hidden());
+            bar.reset();
+            bar.set_length(0);
+        }
+    }
+}
+
+/// body发送完了, 服务器还在处理时显示spinner
+fn advance(bar: &ProgressBar, n: usize) {
+    bar.inc(n as u64);
+    if !bar.is_hidden() && bar.length().is_some_and(|len| bar.position() >= len) {
+        if let Ok(style) = ProgressStyle::with_template("{spinner} waiting for response {elapsed}") {
+            bar.set_style(style);
+        }
+        bar.enable_steady_tick(Duration::from_millis(100));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn large_bodies_are_sent_in_chunks_with_their_length() {
+        let progress = UploadProgress::new(true);
+        let body = vec![b'a'; UPLOAD_PROGRESS_MIN as usize + 1];
+        let req = reqwest::Client::new().post("http://localhost/x").body(body).build().unwrap();
+        let req = progress.track(req);
+        assert!(req.body().unwrap().as_bytes().is_none());
+        assert_eq!(req.headers()[header::CONTENT_LENGTH], "1048577");
+        progress.finish();
+
+        let req = reqwest::Client::new().post("http://localhost/x").body("{}").build().unwrap();
+        let req = progress.track(req);
+        assert_eq!(req.body().unwrap().as_bytes(), Some(&b"{}"[..]));
+        assert!(!req.headers().contains_key(header::CONTENT_LENGTH));
+    }
+}
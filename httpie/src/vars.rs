@@ -0,0 +1,151 @@
+use std::{collections::BTreeSet, env};
+
+use anyhow::{anyhow, Result};
+use reqwest::header::HeaderValue;
+use serde_json::Value;
+
+use crate::cli::*;
+
+/// 变量名由字母, 数字和下划线组成, 不以数字开头
+fn is_var_name(s: &str) -> bool {
+    let mut chars = s.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// 变量的值, --var 优先于同名的环境变量, name:=json 中的字符串不带引号
+fn lookup(vars: &[RequestItem], name: &str) -> Option<String> {
+    let value = vars.iter().rev().find_map(|var| match var {
+        RequestItem::Body(pair) if pair.k == name => Some(pair.v.clone()),
+        RequestItem::RawJson(k, Value::String(v)) if k == name => Some(v.clone()),
+        RequestItem::RawJson(k, v) if k == name => Some(v.to_string()),
+        _ => None,
+    });
+    value.or_else(|| env::var(name).ok())
+}
+
+/// 替换s中的 {name}, {{ 表示一个 {, 找不到的变量名记到missing中
+///
+/// { 后面不是 name} 时原样保留, 所以值中的 {"a": 1} 这样的json不受影响
+pub(crate) fn substitute(s: &str, vars: &[RequestItem], missing: &mut BTreeSet<String>) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(start) = rest.find('{') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        if let Some(after) = rest.strip_prefix("{{") {
+            out.push('{');
+            rest = after;
+            continue;
+        }
+        match rest[1..].split_once('}').filter(|(name, _)| is_var_name(name)) {
+            Some((name, after)) => {
+                match lookup(vars, name) {
+                    Some(value) => out.push_str(&value),
+                    None => {
+                        missing.insert(name.to_string());
+                    }
+                }
+                rest = after;
+            }
+            None => {
+                out.push('{');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// 替换 key:=json 中所有字符串里的变量, 包括对象的key, 数字和布尔值不变
+fn substitute_json(value: &mut Value, vars: &[RequestItem], missing: &mut BTreeSet<String>) {
+    match value {
+        Value::String(s) => *s = substitute(s, vars, missing),
+        Value::Array(values) => values.iter_mut().for_each(|value| substitute_json(value, vars, missing)),
+        Value::Object(map) => {
+            *map = std::mem::take(map)
+                .into_iter()
+                .map(|(k, mut v)| {
+                    substitute_json(&mut v, vars, missing);
+                    (substitute(&k, vars, missing), v)
+                })
+                .collect();
+        }
+        _ => {}
+    }
+}
+
+/// 替换URL, 请求头的值, body (包括 key:=json 中的字符串) 和查询参数中的变量
+///
+/// 要在展开URL之前调用, 这样替换之后的URL依然会被检查; 有找不到的变量时报错, 而不是发送 {name}
+pub(crate) fn substitute_vars(subcommand: &mut SubCommand, vars: &[RequestItem]) -> Result<()> {
+    let mut missing = BTreeSet::new();
+    if let Some(url) = subcommand.url_mut() {
+        *url = substitute(url, vars, &mut missing);
+    }
+    if let SubCommand::Diff(args) = subcommand {
+        args.other = substitute(&args.other, vars, &mut missing);
+    }
+    for item in subcommand.items_mut().into_iter().flatten() {
+        match item {
+            RequestItem::Header(name, value) => {
+                // 不是UTF-8的值里不会有变量
+                if let Ok(text) = value.to_str() {
+                    let text = substitute(text, vars, &mut missing);
+                    *value = HeaderValue::from_str(&text)
+                        .map_err(|e| anyhow!("Invalid value for header {}: {}", name, e))?;
+                }
+            }
+            RequestItem::Query(_, value) => *value = substitute(value, vars, &mut missing),
+            RequestItem::Body(pair) => pair.v = substitute(&pair.v, vars, &mut missing),
+            RequestItem::RawJson(_, value) => substitute_json(value, vars, &mut missing),
+            _ => {}
+        }
+    }
+    if missing.is_empty() {
+        return Ok(());
+    }
+    let names = missing.into_iter().map(|name| format!("{{{}}}", name)).collect::<Vec<_>>();
+    Err(anyhow!(
+        "Unresolved variables {}, set them with --var name=value or environment variables",
+        names.join(", ")
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn placeholders_are_replaced_escaped_or_reported() {
+        let vars = ["id=42".parse().unwrap(), "id:=7".parse().unwrap()];
+        let mut missing = BTreeSet::new();
+        let url = substitute("https://api.example.com/users/{id}/orders", &vars, &mut missing);
+        assert_eq!(url, "https://api.example.com/users/7/orders");
+        assert_eq!(substitute("{{id} {\"a\": 1} {x-y}", &vars, &mut missing), "{id} {\"a\": 1} {x-y}");
+        assert!(missing.is_empty());
+
+        substitute("/{HTTPIE_TEST_MISSING_VAR}/{a}", &vars, &mut missing);
+        assert_eq!(missing.into_iter().collect::<Vec<_>>(), ["HTTPIE_TEST_MISSING_VAR", "a"]);
+    }
+
+    #[test]
+    fn raw_json_strings_are_substituted() {
+        let vars = ["id=42".parse().unwrap()];
+        let post = |item: &str| {
+            let items = vec![item.parse().unwrap()];
+            SubCommand::Post(Post { form: false, url: "http://localhost".to_string(), items })
+        };
+        let mut cmd = post(r#"user:={"id":"{id}","n":[1,"{id}"]}"#);
+        substitute_vars(&mut cmd, &vars).unwrap();
+        match &cmd.items_mut().unwrap()[0] {
+            RequestItem::RawJson(_, value) => assert_eq!(*value, serde_json::json!({"id": "42", "n": [1, "42"]})),
+            item => panic!("unexpected item {:?}", item),
+        }
+
+        let mut cmd = post(r#"user:={"id":"{nope}"}"#);
+        let error = substitute_vars(&mut cmd, &vars).unwrap_err().to_string();
+        assert!(error.starts_with("Unresolved variables {nope}"), "{}", error);
+    }
+}
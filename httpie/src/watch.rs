@@ -0,0 +1,119 @@
+use std::{
+    io::{self, IsTerminal, Write},
+    time::Duration,
+};
+
+use anyhow::Result;
+use colored::Colorize;
+use reqwest::StatusCode;
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+use tokio::time::{interval, MissedTickBehavior};
+
+use crate::{cli::*, output::Output, request::*};
+
+/// 清屏并把光标移到左上角
+const CLEAR_SCREEN: &str = "\x1b[2J\x1b[H";
+
+/// 一次请求的输出, 先收集起来, 这样清屏之后一次写出, 也能和上一次的body比较
+#[derive(Default)]
+struct Capture {
+    body: Vec<u8>,
+    meta: Vec<u8>,
+}
+
+impl Write for Capture {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.body.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Output for Capture {
+    fn meta(&mut self) -> &mut dyn Write {
+        &mut self.meta
+    }
+}
+
+/// --watch 的设置
+#[derive(Debug, Clone)]
+pub(crate) struct Watch {
+    pub(crate) every: Duration,
+    /// --watch-append, 不清屏, 接着上一次的输出
+    pub(crate) append: bool,
+    /// --watch-until-status, 收到其中一个状态码时退出
+    pub(crate) until: Vec<StatusCode>,
+}
+
+/// --watch: 按照固定的间隔重复发送请求并打印响应, 直到 Ctrl-C 或者收到 --watch-until-status
+///
+/// 间隔从每次请求开始时算起, 请求比间隔还慢时跳过错过的那几次, 而不是连续补发
+/// 单次请求失败时打印错误, 继续下一次
+pub(crate) async fn run(ctx: &Context, cmd: &SubCommand, out: &mut impl Output) -> Result<()> {
+    let Some(watch) = &ctx.watch else {
+        return Ok(());
+    };
+    let clear = !watch.append && io::stdout().is_terminal();
+    let mut ticks = interval(watch.every);
+    ticks.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    let interrupted = tokio::signal::ctrl_c();
+    tokio::pin!(interrupted);
+    let mut previous: Option<Vec<u8>> = None;
+    loop {
+        tokio::select! {
+            _ = ticks.tick() => {}
+            _ = &mut interrupted => return Ok(()),
+        }
+        let mut capture = Capture::default();
+        let result = tokio::select! {
+            result = send_and_print(ctx, cmd, &mut capture) => result,
+            _ = &mut interrupted => return Ok(()),
+        };
+        let now = OffsetDateTime::now_utc().replace_nanosecond(0).unwrap_or_else(|_| OffsetDateTime::now_utc());
+        let mut title = format!("Every {}s: {}", watch.every.as_secs_f64(), now.format(&Rfc3339)?)
+            .dimmed()
+            .to_string();
+        // --check-status 的错误状态码也有body, 同样和上一次比较
+        if previous.as_ref().is_some_and(|body| *body != capture.body) {
+            title.push_str(&format!("  {}", "body changed".yellow().bold()));
+        }
+
+        if clear {
+            write!(out, "{}", CLEAR_SCREEN)?;
+            out.flush()?;
+        }
+        let meta = out.meta();
+        writeln!(meta, "{}", title)?;
+        meta.write_all(&capture.meta)?;
+        out.write_all(&capture.body)?;
+        match result {
+            Ok(Some(status)) if watch.until.contains(&status) => {
+                out.flush()?;
+                return Ok(());
+            }
+            Ok(_) => {}
+            Err(e) => writeln!(out.meta(), "{}", format!("Error: {:#}", e).red())?,
+        }
+        previous = Some(capture.body);
+        if watch.append {
+            writeln!(out)?;
+        }
+        out.flush()?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn capture_keeps_the_body_and_metadata_apart() {
+        let mut capture = Capture::default();
+        write!(capture, "body").unwrap();
+        write!(capture.meta(), "HTTP/1.1 200 OK").unwrap();
+        assert_eq!(capture.body, b"body");
+        assert_eq!(capture.meta, b"HTTP/1.1 200 OK");
+    }
+}
@@ -0,0 +1,187 @@
+use std::{
+    io::{self, Write},
+    time::Duration,
+};
+
+use anyhow::{anyhow, Result};
+use colored::Colorize;
+use futures_util::{future, SinkExt, StreamExt};
+use reqwest::{header, StatusCode, Url};
+use serde_json::Value;
+use tokio::io::{AsyncBufReadExt, BufReader, Lines, Stdin};
+use tokio_tungstenite::{
+    connect_async_tls_with_config,
+    tungstenite::{
+        client::IntoClientRequest,
+        http::{HeaderName, HeaderValue},
+        Message,
+    },
+    Connector,
+};
+
+use crate::{cli::*, output::*, request::*};
+
+/// 这些请求头由WebSocket握手自己设置
+const HANDSHAKE_HEADERS: &[header::HeaderName] = &[
+    header::HOST,
+    header::CONNECTION,
+    header::UPGRADE,
+    header::CONTENT_LENGTH,
+    header::ACCEPT_ENCODING,
+    header::SEC_WEBSOCKET_KEY,
+    header::SEC_WEBSOCKET_VERSION,
+];
+
+/// ws 子命令: 握手之后把标准输入的每一行作为文本消息发送, 同时打印收到的消息
+///
+/// 握手请求和普通请求一样带上认证信息, 会话和配置文件中的请求头
+/// 标准输入结束, 使用 --message 并且等待超时, 或者 Ctrl-C 时发送Close并等待服务器的Close
+pub(crate) async fn run(ctx: &Context, args: &Ws, out: &mut impl Output) -> Result<()> {
+    if ctx.unix_socket.is_some() {
+        return Err(anyhow!("--unix-socket can't be used with ws"));
+    }
+    let mut url: Url = args.url.parse()?;
+    let secure = match url.scheme() {
+        "ws" | "http" => false,
+        "wss" | "https" => true,
+        scheme => return Err(anyhow!("Unsupported scheme {}, expected ws:// or wss://", scheme)),
+    };
+    // 先构造一个普通的GET请求, 得到认证信息, 请求头和查询参数
+    let _ = url.set_scheme(if secure { "https" } else { "http" });
+    let req = ctx.build(ctx.client.get(url), &args.items)?;
+    let mut url = req.url().clone();
+    let _ = url.set_scheme(if secure { "wss" } else { "ws" });
+    let mut request = url.as_str().into_client_request()?;
+    for (name, value) in ctx.request_headers(&req).iter() {
+        if HANDSHAKE_HEADERS.contains(name) {
+            continue;
+        }
+        let name = HeaderName::from_bytes(name.as_ref())?;
+        request.headers_mut().append(name, HeaderValue::from_bytes(value.as_bytes())?);
+    }
+    if ctx.print.request_headers {
+        let mut target = url.path().to_string();
+        if let Some(query) = url.query() {
+            target.push('?');
+            target.push_str(query);
+        }
+        let meta = out.meta();
+        writeln!(meta, "{}", format!("GET {} HTTP/1.1", target).blue())?;
+        for (name, value) in request.headers() {
+            writeln!(meta, "{}: {:?}", name.to_string().green(), value)?;
+        }
+        writeln!(meta)?;
+    }
+
+    let tls = native_tls::TlsConnector::builder()
+        .danger_accept_invalid_certs(ctx.insecure)
+        .build()?;
+    let connector = Some(Connector::NativeTls(tls));
+    let (stream, resp) = connect_async_tls_with_config(request, None, true, connector)
+        .await
+        .map_err(|e| anyhow!("WebSocket handshake with {} failed: {}", url, e))?;
+    if ctx.print.response_headers {
+        // tungstenite用的http版本和reqwest的不同, 按照数字转换
+        let status = StatusCode::from_u16(resp.status().as_u16())?;
+        let meta = out.meta();
+        writeln!(meta, "{} {}\n", format!("{:?}", resp.version()).dimmed(), styled_status(status))?;
+        for (name, value) in resp.headers() {
+            writeln!(meta, "{}: {:?}", name.to_string().green(), value)?;
+        }
+        writeln!(meta)?;
+    }
+
+    let (mut sink, mut stream) = stream.split();
+    for message in args.messages.iter() {
+        sink.send(Message::text(message.as_str())).await?;
+    }
+    let one_shot = !args.messages.is_empty();
+    let mut stdin = match one_shot || ctx.ignore_stdin {
+        true => None,
+        false => Some(BufReader::new(tokio::io::stdin()).lines()),
+    };
+    let wait = Duration::from_secs_f64(args.wait);
+    let interrupted = tokio::signal::ctrl_c();
+    tokio::pin!(interrupted);
+    // 已经发送了Close, 等待服务器回复Close
+    let mut closing = false;
+    loop {
+        // 每收到一条消息都重新计时
+        let idle = async {
+            match one_shot || closing {
+                true => tokio::time::sleep(wait).await,
+                false => future::pending().await,
+            }
+        };
+        tokio::select! {
+            line = next_line(&mut stdin) => match line? {
+                Some(line) => sink.send(Message::text(line)).await?,
+                None => {
+                    stdin = None;
+                    sink.send(Message::Close(None)).await?;
+                    closing = true;
+                }
+            },
+            message = stream.next() => match message {
+                Some(message) => {
+                    if !print_message(ctx, out, message?)? {
+                        break;
+                    }
+                }
+                None => break,
+            },
+            _ = idle, if !closing => {
+                sink.send(Message::Close(None)).await?;
+                closing = true;
+            }
+            _ = tokio::time::sleep(wait), if closing => break,
+            _ = &mut interrupted, if !closing => {
+                stdin = None;
+                sink.send(Message::Close(None)).await?;
+                closing = true;
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn next_line(lines: &mut Option<Lines<BufReader<Stdin>>>) -> io::Result<Option<String>> {
+    match lines {
+        Some(lines) => lines.next_line().await,
+        None => future::pending().await,
+    }
+}
+
+/// 打印收到的消息, 收到Close时返回false
+///
+/// tungstenite 会自动回复Ping, 这里只是提示一下
+pub(crate) fn print_message(ctx: &Context, out: &mut impl Write, message: Message) -> Result<bool> {
+    match message {
+        Message::Text(text) => match serde_json::from_str::<Value>(&text) {
+            Ok(Value::Object(_) | Value::Array(_)) => {
+                print_body(ctx, out, Some(mime::APPLICATION_JSON), &text)?
+            }
+            _ => writeln!(out, "{}", text.as_str())?,
+        },
+        Message::Binary(data) => {
+            writeln!(out, "{}", format!("+ binary message, {} bytes +", data.len()).dimmed())?
+        }
+        Message::Ping(_) | Message::Pong(_) if ctx.notes() => {
+            let kind = if matches!(message, Message::Ping(_)) { "ping" } else { "pong" };
+            eprintln!("{}", kind.dimmed());
+        }
+        Message::Close(frame) => {
+            if ctx.notes() {
+                let note = match frame {
+                    Some(frame) => format!("closed: {} {}", u16::from(frame.code), frame.reason),
+                    None => "closed".to_string(),
+                };
+                eprintln!("{}", note.trim_end().dimmed());
+            }
+            return Ok(false);
+        }
+        _ => {}
+    }
+    out.flush()?;
+    Ok(true)
+}
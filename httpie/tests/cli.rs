@@ -0,0 +1,1332 @@
+use std::{path::Path, process::Output};
+
+use tokio::process::Command;
+use wiremock::{
+    matchers::{body_json, header, method, path, query_param},
+    Mock, MockServer, ResponseTemplate,
+};
+
+/// 运行编译好的httpie, 使用一个空的配置目录, 不读取本机的config.toml和 ~/.netrc
+async fn httpie(args: &[&str]) -> Output {
+    httpie_with_env(args, &[]).await
+}
+
+async fn httpie_with_env(args: &[&str], env: &[(&str, &Path)]) -> Output {
+    let tmp = Path::new(env!("CARGO_TARGET_TMPDIR"));
+    Command::new(env!("CARGO_BIN_EXE_httpie"))
+        .args(args)
+        .env("XDG_CONFIG_HOME", tmp.join("config"))
+        .env("NETRC", tmp.join("no-netrc"))
+        .envs(env.iter().copied())
+        .env("NO_COLOR", "1")
+        .stdin(std::process::Stdio::null())
+        .output()
+        .await
+        .unwrap()
+}
+
+async fn server_with_status(status: u16) -> MockServer {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/status"))
+        .respond_with(ResponseTemplate::new(status).set_body_string("body"))
+        .mount(&server)
+        .await;
+    server
+}
+
+#[tokio::test]
+async fn prints_status_headers_and_body() {
+    let server = server_with_status(200).await;
+    let url = format!("{}/status", server.uri());
+    // stdout不是终端时默认只打印body, 可以直接用管道交给其他程序
+    let output = httpie(&["get", &url]).await;
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "body\n");
+    assert!(output.stderr.is_empty());
+
+    // 状态行和响应头在stderr中, stdout中仍然只有body
+    let output = httpie(&["--print", "hb", "get", &url]).await;
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "body\n");
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.starts_with("HTTP/1.1 200 OK\n"));
+    assert!(stderr.contains("\ncontent-length: \"4\"\n"));
+}
+
+#[tokio::test]
+async fn error_statuses_exit_zero_without_check_status() {
+    let server = server_with_status(500).await;
+    let output = httpie(&["get", &format!("{}/status", server.uri())]).await;
+    assert_eq!(output.status.code(), Some(0));
+}
+
+#[tokio::test]
+async fn check_status_sets_exit_code() {
+    for (status, code) in [(200, 0), (302, 3), (404, 4), (503, 5)] {
+        let server = server_with_status(status).await;
+        let url = format!("{}/status", server.uri());
+        let output = httpie(&["--check-status", "-qq", "get", &url]).await;
+        assert_eq!(output.status.code(), Some(code), "status {}", status);
+        assert!(output.stderr.is_empty());
+    }
+}
+
+#[tokio::test]
+async fn retry_after_is_honored_up_to_the_cap() {
+    let server = MockServer::start().await;
+    Mock::given(path("/limited"))
+        .respond_with(ResponseTemplate::new(429).insert_header("retry-after", "0"))
+        .up_to_n_times(1)
+        .mount(&server)
+        .await;
+    Mock::given(path("/limited"))
+        .respond_with(ResponseTemplate::new(200).set_body_string("ok"))
+        .mount(&server)
+        .await;
+    let url = format!("{}/limited", server.uri());
+    let output = httpie(&["--respect-retry-after", "--print", "hb", "get", &url]).await;
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.starts_with("retry 1/3 in 0.00s: 429 Too Many Requests (Retry-After)"));
+    assert!(stderr.contains("\nHTTP/1.1 200 OK\n"));
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "ok\n");
+
+    let server = MockServer::start().await;
+    Mock::given(path("/limited"))
+        .respond_with(ResponseTemplate::new(503).insert_header("retry-after", "300"))
+        .expect(1)
+        .mount(&server)
+        .await;
+    let url = format!("{}/limited", server.uri());
+    let output = httpie(&["--retry", "2", "--retry-after-max", "1", "-p", "hb", "get", &url]).await;
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("Retry-After is 300s, more than --retry-after-max 1s"), "{}", stderr);
+    assert!(stderr.contains("\nHTTP/1.1 503 Service Unavailable\n"));
+    assert!(stderr.contains("retry-after: \"300\""));
+}
+
+#[tokio::test]
+async fn filter_prints_only_the_selected_value() {
+    let server = MockServer::start().await;
+    Mock::given(path("/items"))
+        .respond_with(ResponseTemplate::new(200).set_body_raw(
+            r#"{"data": {"items": [{"id": 1, "name": "tom"}]}}"#,
+            "application/json",
+        ))
+        .mount(&server)
+        .await;
+    let url = format!("{}/items", server.uri());
+    let output = httpie(&["--filter", "data.items[0].name", "get", &url]).await;
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "tom\n");
+    let output = httpie(&["--filter", "/data/items/0", "get", &url]).await;
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "{\"id\":1,\"name\":\"tom\"}\n");
+
+    let output = httpie(&["--filter", "data.items[0].age", "get", &url]).await;
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("data.items[0].age not found in data.items[0]"), "{}", stderr);
+
+    let server = server_with_status(200).await;
+    let output = httpie(&["--filter", "id", "get", &format!("{}/status", server.uri())]).await;
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("--filter needs a JSON response, but the Content-Type is text/plain"));
+}
+
+#[tokio::test]
+async fn dump_headers_includes_redirects_and_can_append() {
+    let server = MockServer::start().await;
+    Mock::given(path("/old"))
+        .respond_with(ResponseTemplate::new(302).insert_header("location", "/new"))
+        .mount(&server)
+        .await;
+    Mock::given(path("/new"))
+        .respond_with(ResponseTemplate::new(200).insert_header("x-rate-limit", "10"))
+        .mount(&server)
+        .await;
+    let file = Path::new(env!("CARGO_TARGET_TMPDIR")).join("dump-headers.txt");
+    let file = file.to_str().unwrap();
+    let url = format!("{}/old", server.uri());
+    httpie(&["--follow", "--dump-headers", file, "get", &url, "--body"]).await;
+    let content = std::fs::read_to_string(file).unwrap();
+    let expected = format!("HTTP/1.1 302 Found\r\nlocation: {}/new\r\n\r\nHTTP/1.1 200 OK\r\n", server.uri());
+    assert!(content.starts_with(&expected), "{}", content);
+    assert!(content.contains("\r\nx-rate-limit: 10\r\n"));
+
+    httpie(&["--dump-headers", file, "--dump-headers-append", "get", &url, "--body"]).await;
+    let appended = std::fs::read_to_string(file).unwrap();
+    assert!(appended.starts_with(&content));
+    assert!(appended[content.len()..].starts_with("HTTP/1.1 302 Found\r\nlocation: /new\r\n"));
+}
+
+#[tokio::test]
+async fn all_prints_each_redirect_and_carries_cookies_forward() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/login"))
+        .respond_with(
+            ResponseTemplate::new(303).insert_header("location", "/moved").insert_header("set-cookie", "sid=1"),
+        )
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/moved"))
+        .and(header("cookie", "sid=1"))
+        .respond_with(ResponseTemplate::new(307).insert_header("location", "/final"))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/final"))
+        .respond_with(ResponseTemplate::new(200).set_body_string("done"))
+        .mount(&server)
+        .await;
+
+    let url = format!("{}/login", server.uri());
+    let output = httpie(&["--follow", "--all", "--print", "hb", "post", &url, "name=tom"]).await;
+    assert!(output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    let statuses = stderr.lines().filter(|line| line.starts_with("HTTP/1.1")).collect::<Vec<_>>();
+    assert_eq!(statuses, ["HTTP/1.1 303 See Other", "HTTP/1.1 307 Temporary Redirect", "HTTP/1.1 200 OK"]);
+    assert!(stderr.contains("set-cookie: \"sid=1\""));
+    assert!(stderr.contains("location: \"/final\""));
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "done\n");
+
+    let output = httpie(&["--follow", "--all", "--max-redirects", "1", "post", &url, "name=tom"]).await;
+    assert!(String::from_utf8(output.stderr).unwrap().contains("too many redirects (--max-redirects=1)"));
+}
+
+#[tokio::test]
+async fn compress_sends_gzip_bodies_and_does_not_retry_415() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/ingest"))
+        .and(header("content-encoding", "gzip"))
+        .respond_with(ResponseTemplate::new(415))
+        .expect(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/ingest"))
+        .respond_with(ResponseTemplate::new(201))
+        .mount(&server)
+        .await;
+    let url = format!("{}/ingest", server.uri());
+    let long = format!("text={}", "a".repeat(2000));
+
+    let args = ["--compress", "--retry", "2", "--retry-on", "415", "-p", "Bh", "post", &url, &long];
+    let output = httpie(&args).await;
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.starts_with("[gzip compressed body, 2011 bytes, "), "{}", stderr);
+    assert!(stderr.contains("HTTP/1.1 415 Unsupported Media Type"));
+    assert!(stderr.contains("try again without --compress"));
+    assert!(!stderr.contains("retry 1/2"));
+    assert!(String::from_utf8(output.stdout).unwrap().contains(&"a".repeat(2000)));
+
+    // 太小的body不压缩
+    let output = httpie(&["--compress=zstd", "-p", "h", "post", &url, "text=a"]).await;
+    assert!(String::from_utf8(output.stderr).unwrap().starts_with("HTTP/1.1 201 Created"));
+}
+
+#[tokio::test]
+async fn chunked_streams_stdin_without_content_length() {
+    use tokio::io::AsyncWriteExt;
+
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/logs"))
+        .and(header("transfer-encoding", "chunked"))
+        .and(wiremock::matchers::body_string("line 1\nline 2\n"))
+        .respond_with(ResponseTemplate::new(202))
+        .expect(1)
+        .mount(&server)
+        .await;
+    let url = format!("{}/logs", server.uri());
+    let mut child = Command::new(env!("CARGO_BIN_EXE_httpie"))
+        .args(["--chunked", "-p", "h", "post", &url])
+        .env("XDG_CONFIG_HOME", Path::new(env!("CARGO_TARGET_TMPDIR")).join("config"))
+        .env("NO_COLOR", "1")
+        .stdin(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdin = child.stdin.take().unwrap();
+    stdin.write_all(b"line 1\n").await.unwrap();
+    stdin.flush().await.unwrap();
+    stdin.write_all(b"line 2\n").await.unwrap();
+    drop(stdin);
+    let output = child.wait_with_output().await.unwrap();
+    assert!(output.status.success());
+    assert!(String::from_utf8(output.stderr).unwrap().starts_with("HTTP/1.1 202 Accepted"));
+
+    let output = httpie(&["--chunked", "post", &url, "name=tom"]).await;
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("--chunked sends stdin as the body"), "{}", stderr);
+}
+
+#[tokio::test]
+async fn vars_are_substituted_from_flags_and_environment() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/users/42/orders"))
+        .and(header("x-trace", "abc"))
+        .and(body_json(serde_json::json!({"note": "{literal}", "by": "tom"})))
+        .respond_with(ResponseTemplate::new(201))
+        .expect(1)
+        .mount(&server)
+        .await;
+    let url = format!("{}/users/{{id}}/orders", server.uri());
+    let env = [("HTTPIE_TEST_TRACE", Path::new("abc"))];
+    let args = ["--var", "id=42", "--var", "user=tom", "-p", "h", "post", &url];
+    let items = ["X-Trace:{HTTPIE_TEST_TRACE}", "note={{literal}", "by={user}"];
+    let output = httpie_with_env(&[&args[..], &items[..]].concat(), &env).await;
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    let output = httpie(&["get", "{scheme}://example.com/{id}/{id}"]).await;
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("Unresolved variables {id}, {scheme}"), "{}", stderr);
+}
+
+#[tokio::test]
+async fn run_executes_a_collection_and_fails_on_unexpected_status() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/health"))
+        .respond_with(ResponseTemplate::new(200).set_body_string("ok"))
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/users"))
+        .and(header("x-env", "staging"))
+        .and(body_json(serde_json::json!({"name": "tom"})))
+        .respond_with(ResponseTemplate::new(500).set_body_string("database is down"))
+        .expect(1)
+        .mount(&server)
+        .await;
+    let file = Path::new(env!("CARGO_TARGET_TMPDIR")).join("smoke.yaml");
+    let content = format!(
+        "vars:\n  base: {}\n  env: dev\nrequests:\n  - name: health\n    url: \"{{base}}/health\"\n  \
+         - name: create\n    method: POST\n    url: \"{{base}}/users\"\n    items: [\"name=tom\", \"X-Env:{{env}}\"]\n    \
+         status: 201\n",
+        server.uri()
+    );
+    std::fs::write(&file, content).unwrap();
+    let file = file.to_str().unwrap();
+
+    let output = httpie(&["run", file, "--var", "env=staging"]).await;
+    assert_eq!(output.status.code(), Some(1));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let lines = stdout.lines().collect::<Vec<_>>();
+    assert!(lines[0].starts_with(&format!("PASS health GET {}/health 200 OK ", server.uri())), "{}", stdout);
+    assert!(lines[1].contains("500 Internal Server Error (expected 201)"), "{}", stdout);
+    assert!(stdout.contains("database is down"));
+    assert!(stdout.ends_with("1 passed, 1 failed\n"));
+
+    let output = httpie(&["run", file, "health"]).await;
+    assert!(output.status.success());
+    assert!(String::from_utf8(output.stdout).unwrap().ends_with("1 passed, 0 failed\n"));
+}
+
+#[tokio::test]
+async fn run_uses_the_config_file() {
+    let server = MockServer::start().await;
+    Mock::given(path("/health"))
+        .and(header("x-cfg", "yes"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&server)
+        .await;
+    let home = config_home("run-config", "default-scheme = \"https\"\n\n[default_headers]\nX-Cfg = \"yes\"\n");
+    let file = home.join("smoke.toml");
+    let host = server.uri().trim_start_matches("http://").to_string();
+    let content = format!(
+        "[[requests]]\nname = \"health\"\nurl = \"{}/health\"\n\n[[requests]]\nname = \"bare\"\nurl = \"{}/health\"\n",
+        server.uri(),
+        host
+    );
+    std::fs::write(&file, content).unwrap();
+
+    let env = [("XDG_CONFIG_HOME", home.as_path())];
+    let output = httpie_with_env(&["run", file.to_str().unwrap()], &env).await;
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.starts_with("PASS health "), "{}", stdout);
+    // 没有scheme的URL按照配置文件中的 default-scheme 展开
+    assert!(stdout.contains(&format!("FAIL bare   GET https://{}/health", host)), "{}", stdout);
+}
+
+#[tokio::test]
+async fn expect_flags_validate_the_response_after_retries() {
+    let server = MockServer::start().await;
+    Mock::given(path("/status"))
+        .respond_with(ResponseTemplate::new(503))
+        .up_to_n_times(1)
+        .mount(&server)
+        .await;
+    Mock::given(path("/status"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({"ok": true})))
+        .mount(&server)
+        .await;
+    let url = format!("{}/status", server.uri());
+
+    // 第一次的503重试之后通过, -q 时什么都不输出
+    let args = [
+        "-q", "--retry", "1", "--retry-delay", "0.01", "--expect-status", "200",
+        "--expect-header", "Content-Type: application/json", "--expect-body-contains", r#""ok":true"#,
+        "get", &url,
+    ];
+    let output = httpie(&args).await;
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(output.stdout.is_empty() && output.stderr.is_empty());
+
+    let args = ["-q", "--expect-status", "201", "--expect-body-contains", "error", "get", &url];
+    let output = httpie(&args).await;
+    assert_eq!(output.status.code(), Some(7));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("assertion failed: expected status 201, got 200 OK"), "{}", stderr);
+    assert!(stderr.contains(r#"assertion failed: expected the body to contain error, got: {"ok":true}"#));
+}
+
+#[tokio::test]
+async fn repl_refuses_to_start_without_a_terminal() {
+    let output = httpie(&["repl", "http://localhost:1"]).await;
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("repl needs an interactive terminal, stdin is not a TTY"), "{}", stderr);
+}
+
+#[tokio::test]
+async fn watch_polls_until_the_expected_status() {
+    let server = MockServer::start().await;
+    Mock::given(path("/deploy"))
+        .respond_with(ResponseTemplate::new(503).set_body_string("starting"))
+        .up_to_n_times(2)
+        .mount(&server)
+        .await;
+    Mock::given(path("/deploy"))
+        .respond_with(ResponseTemplate::new(200).set_body_string("ready"))
+        .mount(&server)
+        .await;
+    let url = format!("{}/deploy", server.uri());
+
+    let args = ["--watch", "0.05", "--watch-until-status", "200", "--check-status", "-p", "hb", "get", &url];
+    let output = httpie(&args).await;
+    assert!(output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert_eq!(stderr.matches("Every 0.05s: ").count(), 3, "{}", stderr);
+    // 单次失败不会结束循环, body变化时标出来
+    assert_eq!(stderr.matches("Error: HTTP 503 Service Unavailable").count(), 2);
+    assert_eq!(stderr.matches("body changed").count(), 1);
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "starting\nstarting\nready\n");
+}
+
+#[tokio::test]
+async fn url_file_fetches_concurrently_and_saves_successful_bodies() {
+    let server = MockServer::start().await;
+    Mock::given(path("/users/1"))
+        .and(header("x-tenant", "acme"))
+        .respond_with(ResponseTemplate::new(200).set_body_string("alice"))
+        .mount(&server)
+        .await;
+    Mock::given(path("/users/2"))
+        .respond_with(ResponseTemplate::new(404).set_body_string("missing"))
+        .mount(&server)
+        .await;
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("url-file");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let urls = dir.join("urls.txt");
+    let content = format!("# users\n{0}/users/1\n\n{0}/users/2\n{0}/users/1\n", server.uri());
+    std::fs::write(&urls, content).unwrap();
+    let out = dir.join("out");
+
+    let args = ["get", "--url-file", urls.to_str().unwrap(), "-C", "2", "--output-dir", out.to_str().unwrap(), "X-Tenant:acme"];
+    let output = httpie(&args).await;
+    assert_eq!(output.status.code(), Some(1));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("2 succeeded, 1 failed"), "{}", stdout);
+    assert!(stdout.contains(&format!("  {}/users/2: 404 Not Found", server.uri())), "{}", stdout);
+    let mut saved = std::fs::read_dir(&out)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .collect::<Vec<_>>();
+    saved.sort();
+    assert_eq!(saved, ["127.0.0.1_users_1", "127.0.0.1_users_1-1"]);
+    assert_eq!(std::fs::read_to_string(out.join("127.0.0.1_users_1")).unwrap(), "alice");
+}
+
+#[tokio::test]
+async fn url_file_uses_the_config_file() {
+    let server = MockServer::start().await;
+    Mock::given(path("/cfg"))
+        .and(header("x-cfg", "yes"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&server)
+        .await;
+    let home = config_home("url-file-config", "[default_headers]\nX-Cfg = \"yes\"\n");
+    let urls = home.join("urls.txt");
+    std::fs::write(&urls, format!("{0}/cfg\n{0}/cfg?page=2\n", server.uri())).unwrap();
+
+    let env = [("XDG_CONFIG_HOME", home.as_path())];
+    let output = httpie_with_env(&["get", "--url-file", urls.to_str().unwrap()], &env).await;
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stdout));
+}
+
+#[tokio::test]
+async fn serve_echoes_requests_and_stops_on_ctrl_c() {
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt, BufReader};
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_httpie"))
+        .args(["serve", "--port", "0"])
+        .env("NO_COLOR", "1")
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stderr = BufReader::new(child.stderr.take().unwrap());
+    let mut line = String::new();
+    stderr.read_line(&mut line).await.unwrap();
+    let base = line.trim_start_matches("Listening on ").split(',').next().unwrap().to_string();
+    assert!(base.starts_with("http://127.0.0.1:"), "{}", line);
+
+    let url = format!("{}/anything?tag=a&tag=b", base);
+    let output = httpie(&["-p", "b", "post", &url, "name=alice", "X-Trace:1"]).await;
+    let echo: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(echo["method"], "POST");
+    assert_eq!(echo["query"]["tag"], serde_json::json!(["a", "b"]));
+    assert_eq!(echo["headers"]["x-trace"], "1");
+    assert_eq!(echo["body"], serde_json::json!({ "name": "alice" }));
+
+    let teapot = httpie(&["--check-status", "get", &format!("{}/status/418", base)]).await;
+    assert_eq!(teapot.status.code(), Some(4));
+    let gzip = httpie(&["-p", "b", "get", &format!("{}/gzip", base)]).await;
+    let echo: serde_json::Value = serde_json::from_slice(&gzip.stdout).unwrap();
+    assert_eq!(echo["gzipped"], true);
+    let slow = httpie(&["--timeout", "0.2", "get", &format!("{}/delay/2", base)]).await;
+    assert_eq!(slow.status.code(), Some(2));
+
+    let killed = std::process::Command::new("kill").args(["-INT", &child.id().unwrap().to_string()]).status();
+    assert!(killed.unwrap().success());
+    assert!(child.wait().await.unwrap().success());
+    let mut log = String::new();
+    stderr.read_to_string(&mut log).await.unwrap();
+    assert!(log.contains("POST /anything?tag=a&tag=b 200 OK"), "{}", log);
+    assert!(log.contains("GET /status/418 418 I'm a teapot"), "{}", log);
+    assert!(log.ends_with("Stopped\n"), "{}", log);
+}
+
+/// 写一个只包含 content 的配置目录, 返回用作 XDG_CONFIG_HOME 的路径
+fn config_home(name: &str, content: &str) -> std::path::PathBuf {
+    let home = Path::new(env!("CARGO_TARGET_TMPDIR")).join(name);
+    std::fs::create_dir_all(home.join("httpie-rs")).unwrap();
+    std::fs::write(home.join("httpie-rs/config.toml"), content).unwrap();
+    home
+}
+
+#[tokio::test]
+async fn broken_config_only_spares_the_subcommands_without_requests() {
+    let home = config_home("broken-config", "[default_headers\n");
+    let env = [("XDG_CONFIG_HOME", home.as_path())];
+    assert!(httpie_with_env(&["config", "path"], &env).await.status.success());
+    assert!(httpie_with_env(&["completions", "bash"], &env).await.status.success());
+    for args in [&["--offline", "get", "localhost"][..], &["run", "missing.yaml"][..], &["replay", "1"][..]].iter() {
+        let output = httpie_with_env(args, &env).await;
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert!(stderr.contains("Invalid config file"), "{:?}: {}", args, stderr);
+    }
+}
+
+#[tokio::test]
+async fn config_default_headers_are_merged_and_removable() {
+    let server = MockServer::start().await;
+    Mock::given(path("/items")).respond_with(ResponseTemplate::new(200)).mount(&server).await;
+    let content = "[default_headers]\nX-Tenant = \"acme\"\nX-Env = \"staging\"\nUser-Agent = \"ci-bot/1\"\n";
+    let config = config_home("default-headers", content);
+    let env = [("XDG_CONFIG_HOME", config.as_path())];
+    let url = format!("{}/items", server.uri());
+
+    // 命令行 > 配置文件 > 内置的 User-Agent, X-Env: 去掉配置文件中的请求头
+    let args = ["--offline", "get", &url, "X-Env:", "X-Tenant:beta"];
+    let offline = String::from_utf8(httpie_with_env(&args, &env).await.stderr).unwrap();
+    let printed = offline.lines().skip(1).filter(|line| !line.is_empty()).collect::<Vec<_>>();
+    assert!(printed.contains(&"user-agent: ci-bot/1"), "{}", offline);
+    assert!(printed.contains(&"x-tenant: beta"), "{}", offline);
+    assert!(!offline.contains("x-env") && !offline.contains("x-powerd-by"), "{}", offline);
+
+    // 实际发送的请求头和 --offline 打印的完全相同
+    assert!(httpie_with_env(&args[1..], &env).await.status.success());
+    let received = server.received_requests().await.unwrap();
+    let mut sent = received[0]
+        .headers
+        .iter()
+        .map(|(name, value)| format!("{}: {}", name, value.to_str().unwrap()))
+        .collect::<Vec<_>>();
+    let mut printed = printed.iter().map(|line| line.to_string()).collect::<Vec<_>>();
+    sent.sort();
+    printed.sort();
+    assert_eq!(sent, printed);
+
+    let output = httpie(&["--offline", "get", &url]).await;
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains(&format!("user-agent: httpie-rs/{}\n", env!("CARGO_PKG_VERSION"))), "{}", stderr);
+}
+
+#[tokio::test]
+async fn items_file_adds_headers_and_query_parameters() {
+    let server = MockServer::start().await;
+    Mock::given(path("/items"))
+        .and(header("x-tenant", "acme"))
+        .and(header("authorization", "Bearer from-file"))
+        .and(query_param("limit", "50"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&server)
+        .await;
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR"));
+    let (items, token) = (dir.join("common.http"), dir.join("token.txt"));
+    std::fs::write(&token, "Bearer from-file\n").unwrap();
+    let content = format!("# shared headers\nX-Tenant:acme\nAuthorization:@{}\nlimit==10\n", token.display());
+    std::fs::write(&items, content).unwrap();
+    let url = format!("{}/items", server.uri());
+    let output = httpie(&["--items-file", items.to_str().unwrap(), "get", &url, "limit==50"]).await;
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+}
+
+#[tokio::test]
+async fn repeated_body_keys_are_kept() {
+    let server = MockServer::start().await;
+    Mock::given(path("/json"))
+        .and(body_json(serde_json::json!({"tag": ["a", "b"]})))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&server)
+        .await;
+    Mock::given(path("/form"))
+        .and(wiremock::matchers::body_string("tag=a&tag=b"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&server)
+        .await;
+    let json = httpie(&["post", &format!("{}/json", server.uri()), "tag=a", "tag=b"]).await;
+    assert!(json.status.success());
+    let form = httpie(&["post", "--form", &format!("{}/form", server.uri()), "tag=a", "tag=b"]).await;
+    assert!(form.status.success());
+}
+
+#[tokio::test]
+async fn content_type_overrides_the_json_type() {
+    let server = MockServer::start().await;
+    Mock::given(path("/typed"))
+        .and(header("content-type", "application/vnd.api+json"))
+        .and(body_json(serde_json::json!({"name": "foo"})))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&server)
+        .await;
+    let url = format!("{}/typed", server.uri());
+    let output = httpie(&["--content-type", "application/vnd.api+json", "post", &url, "name=foo"]).await;
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    let output = httpie(&["--content-type", "text/plain", "post", "--form", &url, "name=foo"]).await;
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("--content-type can't be used together with --form"), "{}", stderr);
+    assert!(!httpie(&["--content-type", "not a mime", "get", &url]).await.status.success());
+}
+
+#[tokio::test]
+async fn patch_uploads_keep_the_multipart_content_type() {
+    let server = MockServer::start().await;
+    Mock::given(method("PATCH")).respond_with(ResponseTemplate::new(200)).mount(&server).await;
+    let file = Path::new(env!("CARGO_TARGET_TMPDIR")).join("patch-upload.txt");
+    std::fs::write(&file, "hello").unwrap();
+    let url = format!("{}/x", server.uri());
+    let upload = format!("file@{}", file.display());
+    assert!(httpie(&["patch", "--merge-type", "merge-patch", &url, "name=a", &upload]).await.status.success());
+    assert!(httpie(&["patch", "--merge-type", "merge-patch", &url, "name=a"]).await.status.success());
+
+    let requests = server.received_requests().await.unwrap();
+    let content_type = |i: usize| requests[i].headers.get("content-type").unwrap().to_str().unwrap().to_string();
+    let boundary = content_type(0);
+    let boundary = boundary.strip_prefix("multipart/form-data; boundary=").unwrap();
+    assert!(String::from_utf8_lossy(&requests[0].body).starts_with(&format!("--{}", boundary)));
+    assert_eq!(content_type(1), "application/merge-patch+json");
+}
+
+#[tokio::test]
+async fn accept_shortcuts_yield_to_explicit_headers() {
+    let server = MockServer::start().await;
+    Mock::given(path("/negotiate"))
+        .and(wiremock::matchers::headers("accept", vec!["application/json", "*/*;q=0.5"]))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&server)
+        .await;
+    Mock::given(path("/negotiate"))
+        .and(header("accept", "text/html"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&server)
+        .await;
+    let url = format!("{}/negotiate", server.uri());
+    assert!(httpie(&["--json", "get", &url]).await.status.success());
+
+    let output = httpie(&["--accept", "text/csv", "--body", "get", &url, "Accept:text/html"]).await;
+    assert!(output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert_eq!(stderr, "warning: Accept:text/html from the command line overrides --accept/--json\n");
+}
+
+#[tokio::test]
+async fn digest_auth_answers_the_challenge() {
+    let server = MockServer::start().await;
+    Mock::given(path("/digest"))
+        .and(wiremock::matchers::header_exists("authorization"))
+        .respond_with(ResponseTemplate::new(200).set_body_string("ok"))
+        .expect(1)
+        .mount(&server)
+        .await;
+    let challenge = r#"Digest realm="test", qop="auth", nonce="abc", opaque="xyz""#;
+    Mock::given(path("/digest"))
+        .respond_with(ResponseTemplate::new(401).insert_header("www-authenticate", challenge))
+        .expect(1)
+        .mount(&server)
+        .await;
+    let url = format!("{}/digest", server.uri());
+    let output = httpie(&["--auth-type", "digest", "-a", "user:pass", "get", &url, "--body"]).await;
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "ok\n");
+
+    let requests = server.received_requests().await.unwrap();
+    let authorization = requests[1].headers["authorization"].to_str().unwrap();
+    assert!(authorization.starts_with(r#"Digest username="user", realm="test", nonce="abc", uri="/digest""#));
+    assert!(authorization.ends_with(r#"opaque="xyz""#), "{}", authorization);
+}
+
+#[tokio::test]
+async fn aws4_signs_requests() {
+    let server = MockServer::start().await;
+    Mock::given(path("/signed"))
+        .and(query_param("q", "a b"))
+        .and(header("x-amz-security-token", "TOKEN"))
+        .and(wiremock::matchers::header_regex(
+            "authorization",
+            r"^AWS4-HMAC-SHA256 Credential=AK/\d{8}/eu-west-1/execute-api/aws4_request, SignedHeaders=[a-z;-]*host;x-amz-content-sha256;x-amz-date;x-amz-security-token[a-z;-]*, Signature=[0-9a-f]{64}$",
+        ))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&server)
+        .await;
+    let url = format!("{}/signed", server.uri());
+    let args = ["--auth-type", "aws4", "-a", "AK:SK", "--aws-region", "eu-west-1", "--aws-service", "execute-api"];
+    let output = httpie(&[&args[..], &["--aws-session-token", "TOKEN", "get", &url, "q==a b"]].concat()).await;
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    let output = httpie(&["--auth-type", "aws4", "-a", "AK:SK", "--aws-region", "eu-west-1", "get", &url]).await;
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("--auth-type aws4 needs --aws-service"), "{}", stderr);
+}
+
+#[tokio::test]
+async fn oauth2_tokens_are_fetched_once_and_cached() {
+    let server = MockServer::start().await;
+    Mock::given(path("/token"))
+        .and(method("POST"))
+        .and(wiremock::matchers::body_string_contains("grant_type=client_credentials"))
+        .and(wiremock::matchers::body_string_contains("scope=read"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "access_token": "T1",
+            "expires_in": 3600,
+        })))
+        .expect(2)
+        .mount(&server)
+        .await;
+    Mock::given(path("/bad-token"))
+        .respond_with(ResponseTemplate::new(401).set_body_string(r#"{"error":"invalid_client"}"#))
+        .mount(&server)
+        .await;
+    Mock::given(path("/api"))
+        .and(header("authorization", "Bearer T1"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(3)
+        .mount(&server)
+        .await;
+    let token_url = format!("{}/token", server.uri());
+    let url = format!("{}/api", server.uri());
+    let args = ["--oauth2-client-id", "id", "--oauth2-client-secret", "secret", "--oauth2-scope", "read"];
+    let oauth2 = [&args[..], &["--oauth2-token-url", &token_url]].concat();
+    // 第二次使用缓存的token, --oauth2-force-refresh 时重新获取
+    for extra in [&[][..], &[], &["--oauth2-force-refresh"]] {
+        let output = httpie(&[&oauth2[..], extra, &["--check-status", "get", &url]].concat()).await;
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    }
+
+    let bad_url = format!("{}/bad-token", server.uri());
+    let output = httpie(&[&args[..], &["--oauth2-token-url", &bad_url, "get", &url]].concat()).await;
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("401 Unauthorized") && stderr.contains("invalid_client"), "{}", stderr);
+}
+
+#[tokio::test]
+async fn netrc_supplies_basic_auth_unless_given_on_the_command_line() {
+    let server = MockServer::start().await;
+    // alice:s3cret 和 bob:pw
+    for credentials in ["YWxpY2U6czNjcmV0", "Ym9iOnB3"] {
+        Mock::given(path("/private"))
+            .and(header("authorization", format!("Basic {}", credentials).as_str()))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&server)
+            .await;
+    }
+    let netrc = Path::new(env!("CARGO_TARGET_TMPDIR")).join("netrc");
+    std::fs::write(&netrc, "machine 127.0.0.1\n  login alice\n  password s3cret\n").unwrap();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&netrc, std::fs::Permissions::from_mode(0o600)).unwrap();
+    }
+    let url = format!("{}/private", server.uri());
+    let env = [("NETRC", netrc.as_path())];
+    for args in [&["get", &url][..], &["-a", "bob:pw", "get", &url]] {
+        let output = httpie_with_env(&[&["--check-status"][..], args].concat(), &env).await;
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    }
+
+    let output = httpie_with_env(&["--auth-type", "netrc", "get", "http://localhost:1/"], &env).await;
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("No entry for localhost in"), "{}", stderr);
+}
+
+#[tokio::test]
+async fn proxies_get_credentials_and_407_is_reported_separately() {
+    let proxy = MockServer::start().await;
+    Mock::given(path("/via-proxy"))
+        .and(header("proxy-authorization", "Basic dXNlcjpwQHNz"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&proxy)
+        .await;
+    Mock::given(path("/via-proxy"))
+        .respond_with(ResponseTemplate::new(407))
+        .mount(&proxy)
+        .await;
+    let address = proxy.address();
+    let with_credentials = format!("http:http://user:p%40ss@{}", address);
+    let output = httpie(&["--proxy", &with_credentials, "get", "http://example.invalid/via-proxy"]).await;
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    let output = httpie(&["--proxy", &format!("http://{}", address), "get", "http://example.invalid/via-proxy"]).await;
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("407 Proxy Authentication Required came from the proxy, not from example.invalid"));
+
+    // https请求通过CONNECT隧道, 代理拒绝时是一个错误
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let tunnel = format!("https:http://{}", listener.local_addr().unwrap());
+    std::thread::spawn(move || {
+        use std::io::{Read, Write};
+        let (mut stream, _) = listener.accept().unwrap();
+        let _ = stream.read(&mut [0; 1024]);
+        let _ = stream.write_all(b"HTTP/1.1 407 Proxy Authentication Required\r\ncontent-length: 0\r\n\r\n");
+    });
+    let output = httpie(&["--proxy", &tunnel, "get", "https://example.invalid/"]).await;
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("The proxy rejected the credentials while opening a tunnel to example.invalid"), "{}", stderr);
+}
+
+#[tokio::test]
+async fn interface_binds_the_local_address() {
+    let server = server_with_status(200).await;
+    let url = format!("{}/status", server.uri());
+    for timings in [&[][..], &["--timings"]] {
+        let output = httpie(&[&["--interface", "127.0.0.1"][..], timings, &["get", &url]].concat()).await;
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    }
+    let requests = server.received_requests().await.unwrap();
+    assert_eq!(requests.len(), 2);
+
+    // 192.0.2.0/24 是文档专用的地址, 不会分配给本机
+    for timings in [&[][..], &["--timings"]] {
+        let output = httpie(&[&["--interface", "192.0.2.1"][..], timings, &["get", &url]].concat()).await;
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert!(stderr.contains("Failed to bind to --interface 192.0.2.1"), "{}", stderr);
+    }
+    let output = httpie(&["--interface", "eth0", "get", &url]).await;
+    assert!(String::from_utf8(output.stderr).unwrap().contains("Invalid interface eth0"));
+}
+
+#[tokio::test]
+async fn address_family_flags_never_fall_back() {
+    let server = server_with_status(200).await;
+    let port = server.address().port().to_string();
+    let resolved = format!("example.test:{}:127.0.0.1", port);
+    let url = format!("http://example.test:{}/status", port);
+    let output = httpie(&["-4", "--resolve", &resolved, "-v", "get", &url]).await;
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains(&format!("Remote address: 127.0.0.1:{}", port)), "{}", stderr);
+
+    let output = httpie(&["-6", "--resolve", &resolved, "get", &url]).await;
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("no IPv6 address for example.test"), "{}", stderr);
+    let output = httpie(&["-6", "get", &format!("{}/status", server.uri())]).await;
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("127.0.0.1 is not an IPv6 address (-6)"), "{}", stderr);
+    assert!(!httpie(&["-4", "-6", "get", &url]).await.status.success());
+}
+
+#[tokio::test]
+async fn max_body_size_aborts_and_can_show_the_partial_body() {
+    let server = MockServer::start().await;
+    Mock::given(path("/big"))
+        .respond_with(ResponseTemplate::new(200).set_body_string("a".repeat(2048)))
+        .mount(&server)
+        .await;
+    let url = format!("{}/big", server.uri());
+
+    let output = httpie(&["--max-body-size", "1KiB", "get", &url]).await;
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("Response body is 2048 bytes, larger than --max-body-size 1024 bytes"));
+    assert!(stderr.contains("-o/--download"));
+
+    let output = httpie(&["--max-body-size", "1K", "--show-partial", "get", &url]).await;
+    assert!(!output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), format!("{}\n", "a".repeat(1024)));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("Aborted after receiving 2048 bytes"));
+
+    let output = httpie(&["--max-body-size", "0", "get", &url]).await;
+    assert!(output.status.success());
+    let tmp = Path::new(env!("CARGO_TARGET_TMPDIR")).join("max-body-size");
+    let output = httpie(&["--max-body-size", "1K", "-o", tmp.to_str().unwrap(), "get", &url]).await;
+    assert!(output.status.success());
+    assert_eq!(std::fs::read(&tmp).unwrap().len(), 2048);
+}
+
+#[tokio::test]
+async fn pretty_controls_formatting_and_colors_separately() {
+    let server = MockServer::start().await;
+    Mock::given(path("/json"))
+        .respond_with(ResponseTemplate::new(200).set_body_raw(r#"{"b":1,"a":[2]}"#, "application/json"))
+        .mount(&server)
+        .await;
+    let url = format!("{}/json", server.uri());
+    let body = |args: &[&str]| {
+        let mut all = vec!["--body", "--sort-keys"];
+        all.extend_from_slice(args);
+        all.extend_from_slice(&["get", &url]);
+        all.into_iter().map(str::to_string).collect::<Vec<_>>()
+    };
+    let run = |args: Vec<String>| async move {
+        let args = args.iter().map(String::as_str).collect::<Vec<_>>();
+        String::from_utf8(httpie(&args).await.stdout).unwrap()
+    };
+
+    assert_eq!(run(body(&[])).await, "{\"b\":1,\"a\":[2]}\n");
+    assert_eq!(run(body(&["--pretty", "none"])).await, "{\"b\":1,\"a\":[2]}\n");
+    let formatted = "{\n  \"a\": [\n    2\n  ],\n  \"b\": 1\n}\n";
+    assert_eq!(run(body(&["--pretty", "format"])).await, formatted);
+
+    // 只加颜色, 去掉ANSI转义序列之后和收到的body完全一样
+    let colored = run(body(&["--pretty", "colors", "--color", "always"])).await;
+    assert!(colored.contains('\x1b'));
+    let plain = colored.split('\x1b').enumerate().map(|(i, part)| {
+        if i == 0 { part } else { part.split_once('m').map_or("", |(_, rest)| rest) }
+    });
+    assert_eq!(plain.collect::<String>(), "{\"b\":1,\"a\":[2]}\n");
+    assert_eq!(run(body(&["--pretty", "all", "--color", "always"])).await.lines().count(), 6);
+}
+
+#[tokio::test]
+async fn timings_break_down_the_request_phases() {
+    let server = MockServer::start().await;
+    Mock::given(path("/timed"))
+        .respond_with(ResponseTemplate::new(200).set_body_raw(r#"{"a":1}"#, "application/json"))
+        .mount(&server)
+        .await;
+    let url = format!("{}/timed", server.uri());
+    let output = httpie(&["--timings", "get", &url, "--body"]).await;
+    // 输出不是终端, 默认 --pretty none, body原样输出
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "{\"a\":1}\n");
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    let rows = stderr.lines().map(|line| line.split_whitespace().next().unwrap()).collect::<Vec<_>>();
+    assert_eq!(rows, ["DNS", "TCP", "TLS", "Waiting", "Content", "Total"], "{}", stderr);
+    // 服务器地址是IP, 不需要DNS查询, http也没有TLS握手
+    assert!(stderr.starts_with("DNS lookup               -  |"), "{}", stderr);
+
+    let output = httpie(&["--timings-json", "get", &url, "--body"]).await;
+    let timings: serde_json::Value = serde_json::from_slice(&output.stderr).unwrap();
+    assert_eq!(timings["reused"], false);
+    assert!(timings["total"].as_f64().unwrap() >= timings["waiting"].as_f64().unwrap());
+}
+
+#[tokio::test]
+async fn log_file_records_plain_text_exchanges() {
+    let server = MockServer::start().await;
+    Mock::given(path("/logged"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("x-session", "s1")
+                .set_body_raw(r#"{"ok":true}"#, "application/json"),
+        )
+        .mount(&server)
+        .await;
+    let file = Path::new(env!("CARGO_TARGET_TMPDIR")).join("session.log");
+    let _ = std::fs::remove_file(&file);
+    let file = file.to_str().unwrap();
+    let url = format!("{}/logged", server.uri());
+    let args = ["--color", "always", "--log-file", file, "--log-redact", "x-session"];
+    httpie(&[&args[..], &["post", &url, "x-api-key:abc", "name=tom"]].concat()).await;
+    let log = std::fs::read_to_string(file).unwrap();
+    assert!(!log.contains('\x1b'));
+    assert!(log.contains(&format!("\nPOST {} HTTP/1.1\n", url)));
+    assert!(log.contains("\nx-api-key: <redacted>\n"));
+    assert!(log.contains("\n{\"name\":\"tom\"}\n\nHTTP/1.1 200 OK\n"));
+    assert!(log.contains("\nx-session: <redacted>\n"));
+    assert!(log.ends_with("\n{\"ok\":true}\n\n"));
+}
+
+#[tokio::test]
+async fn diff_compares_json_bodies_and_sets_the_exit_code() {
+    // MockServer被drop之后端口可能被下一个复用, 所以两个都要留着
+    let (mut mocks, mut servers) = (Vec::new(), Vec::new());
+    for (version, name) in [("1", "tom"), ("2", "jerry")] {
+        let server = MockServer::start().await;
+        Mock::given(path("/users/1"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("x-version", version)
+                    .set_body_raw(format!(r#"{{"id": 1, "name": "{}"}}"#, name), "application/json"),
+            )
+            .mount(&server)
+            .await;
+        servers.push(format!("{}/users/1", server.uri()));
+        mocks.push(server);
+    }
+    let ignored = "date,content-length";
+    let output = httpie(&["diff", &servers[0], &servers[1], "--ignore-header", ignored]).await;
+    assert_eq!(output.status.code(), Some(1));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let expected = "Headers:\n- x-version: 1\n+ x-version: 2\nBody:\n- /name: \"tom\"\n+ /name: \"jerry\"\n";
+    assert!(stdout.ends_with(expected), "{}", stdout);
+
+    let args = ["diff", &servers[0], &servers[1], "--ignore-header", "date,content-length,x-version"];
+    let output = httpie(&[&args[..], &["--ignore-path", "name"]].concat()).await;
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(String::from_utf8(output.stderr).unwrap(), "No differences\n");
+}
+
+#[tokio::test]
+async fn har_entries_are_appended() {
+    let server = server_with_status(404).await;
+    let url = format!("{}/status", server.uri());
+    let har = Path::new(env!("CARGO_TARGET_TMPDIR")).join("appended.har");
+    let _ = std::fs::remove_file(&har);
+    for _ in 0..2 {
+        let output = httpie(&["-q", "--har", har.to_str().unwrap(), "get", &url]).await;
+        assert!(output.status.success());
+    }
+
+    let har: serde_json::Value = serde_json::from_slice(&std::fs::read(har).unwrap()).unwrap();
+    assert_eq!(har["log"]["version"], "1.2");
+    let entries = har["log"]["entries"].as_array().unwrap();
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[0]["request"]["method"], "GET");
+    assert_eq!(entries[0]["request"]["url"], url);
+    assert_eq!(entries[0]["response"]["status"], 404);
+    assert_eq!(entries[0]["response"]["content"]["text"], "body");
+    assert_eq!(entries[0]["response"]["bodySize"], 4);
+}
+
+#[tokio::test]
+async fn offline_curl_prints_only_the_command() {
+    let output = httpie(&[
+        "--offline",
+        "--curl",
+        "--curl-redact",
+        "--bearer",
+        "abc",
+        "put",
+        "example.com/a?b=1",
+        "msg=it's",
+    ])
+    .await;
+    assert!(output.status.success());
+    let expected = "curl \\
+  -X PUT \\
+  'http://example.com/a?b=1' \\
+  -H 'content-type: application/json' \\
+  -H 'authorization: Bearer <redacted>' \\
+  -H 'user-agent: httpie-rs/0.1.0' \\
+  -H 'accept-encoding: gzip, deflate, br, zstd' \\
+  -H 'accept: */*' \\
+  --compressed \\
+  --data-raw '{\"msg\":\"it'\\''s\"}'
+";
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), expected);
+}
+
+#[tokio::test]
+async fn replay_overrides_query_parameters() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/items"))
+        .and(query_param("limit", "50"))
+        .respond_with(ResponseTemplate::new(200).set_body_string("replayed"))
+        .mount(&server)
+        .await;
+    let url = format!("{}/items?limit=10", server.uri());
+    httpie(&["get", &url]).await;
+
+    // 其他测试也在写入同一个历史文件, 按照URL找到这次请求的编号
+    let history = httpie(&["history", "-n", "1000"]).await;
+    let history = String::from_utf8(history.stdout).unwrap();
+    let line = history.lines().rev().find(|line| line.ends_with(&url)).unwrap();
+    let id = line.split_whitespace().next().unwrap();
+
+    let output = httpie(&["--body", "replay", id, "limit==50"]).await;
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "replayed\n");
+}
+
+#[tokio::test]
+async fn replay_uses_the_config_file() {
+    let server = MockServer::start().await;
+    Mock::given(path("/gone"))
+        .and(header("x-cfg", "yes"))
+        .respond_with(ResponseTemplate::new(404))
+        .expect(2)
+        .mount(&server)
+        .await;
+    // 历史文件也在这个配置目录中, 每次从空的历史开始
+    let home = config_home("replay-config", "check-status = true\n\n[default_headers]\nX-Cfg = \"yes\"\n");
+    let _ = std::fs::remove_file(home.join("httpie-rs/history.jsonl"));
+    let env = [("XDG_CONFIG_HOME", home.as_path())];
+    let url = format!("{}/gone", server.uri());
+    assert_eq!(httpie_with_env(&["-qq", "get", &url], &env).await.status.code(), Some(4));
+
+    let history = httpie_with_env(&["history"], &env).await;
+    let history = String::from_utf8(history.stdout).unwrap();
+    let id = history.lines().find(|line| line.ends_with(&url)).unwrap().split_whitespace().next().unwrap().to_string();
+    assert_eq!(httpie_with_env(&["-qq", "replay", &id], &env).await.status.code(), Some(4));
+}
+
+#[tokio::test]
+async fn repeat_prints_a_summary_instead_of_the_responses() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/status"))
+        .respond_with(ResponseTemplate::new(404).set_body_string("body"))
+        .expect(3)
+        .mount(&server)
+        .await;
+    let url = format!("{}/status", server.uri());
+    let output = httpie(&["--repeat", "3", "-C", "2", "get", &url]).await;
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.starts_with("Requests:     3 completed, 3 non-2xx, 0 failed\n"));
+    assert!(stdout.contains("\nTransferred:  12 B\n"));
+    assert!(stdout.ends_with("Status codes:\n  404     3\n"));
+}
+
+#[tokio::test]
+async fn pagination_follows_next_links_and_merges_arrays() {
+    let server = MockServer::start().await;
+    let page = |n: u32, next: Option<&str>| {
+        let mut resp =
+            ResponseTemplate::new(200).set_body_raw(format!("[{}]", n), "application/json");
+        if let Some(next) = next {
+            resp = resp.insert_header("link", format!(r#"<{}>; rel="next""#, next).as_str());
+        }
+        resp
+    };
+    Mock::given(method("GET"))
+        .and(path("/items"))
+        .and(query_param("page", "2"))
+        .respond_with(page(2, Some("/items?page=3")))
+        .mount(&server)
+        .await;
+    // 第3页又指回了第2页
+    Mock::given(method("GET"))
+        .and(path("/items"))
+        .and(query_param("page", "3"))
+        .respond_with(page(3, Some("/items?page=2")))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/items"))
+        .respond_with(page(1, Some("/items?page=2")))
+        .expect(2)
+        .mount(&server)
+        .await;
+
+    let url = format!("{}/items", server.uri());
+    let args = ["--body", "--pretty", "format", "get", &url, "--follow-pagination", "--merge-json"];
+    let output = httpie(&args).await;
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "[\n  1,\n  2,\n  3\n]\n");
+    assert!(String::from_utf8(output.stderr).unwrap().contains("again, stopping"));
+
+    let args = ["--body", "get", &url, "--follow-pagination", "--max-pages", "2"];
+    let output = httpie(&args).await;
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "[1]\n\n[2]\n");
+}
+
+#[tokio::test]
+async fn graphql_sends_the_envelope_and_reports_errors() {
+    let server = MockServer::start().await;
+    let query = "query($id: ID!) { user(id: $id) { name } }";
+    Mock::given(method("POST"))
+        .and(path("/graphql"))
+        .and(body_json(serde_json::json!({"query": query, "variables": {"id": 42, "name": "foo"}})))
+        .respond_with(ResponseTemplate::new(200).set_body_raw(
+            r#"{"data":{"user":null},"errors":[{"message":"not found","path":["user"]}]}"#,
+            "application/json",
+        ))
+        .expect(1)
+        .mount(&server)
+        .await;
+    let url = format!("{}/graphql", server.uri());
+    let args = ["--body", "--pretty", "format", "graphql", &url, "--query", query, "--var", "id:=42", "--var", "name=foo"];
+    let output = httpie(&args).await;
+    assert!(String::from_utf8(output.stdout).unwrap().contains("\"user\": null"));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert_eq!(stderr, "GraphQL errors (1):\n  - not found (at user)\n");
+}
+
+#[tokio::test]
+async fn event_streams_are_printed_event_by_event() {
+    let server = MockServer::start().await;
+    let body = "event: tick\nid: 1\ndata: {\"n\":1}\n\n: ping\ndata: two\n\ndata: three\n\n";
+    Mock::given(method("GET"))
+        .and(path("/events"))
+        .respond_with(ResponseTemplate::new(200).set_body_raw(body, "text/event-stream"))
+        .mount(&server)
+        .await;
+    let url = format!("{}/events", server.uri());
+    let output = httpie(&["--body", "--pretty", "format", "--max-events", "2", "get", &url]).await;
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let lines = stdout.lines().filter(|line| !line.starts_with('[')).collect::<Vec<_>>();
+    assert_eq!(lines, ["{", "  \"n\": 1", "}", "two"]);
+    assert!(stdout.contains("Z] event: tick, id: 1\n"));
+}
+
+#[tokio::test]
+async fn etag_cache_sends_validators_and_replays_the_body_on_304() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/cached"))
+        .and(header("if-none-match", "\"v1\""))
+        .respond_with(ResponseTemplate::new(304))
+        .with_priority(1)
+        .expect(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/cached"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("etag", "\"v1\"")
+                .set_body_raw(r#"{"a":1}"#, "application/json"),
+        )
+        .expect(1)
+        .mount(&server)
+        .await;
+    let url = format!("{}/cached", server.uri());
+    let first = httpie(&["--etag-cache", "--pretty", "format", "get", &url, "--body"]).await;
+    assert_eq!(String::from_utf8(first.stdout).unwrap(), "{\n  \"a\": 1\n}\n");
+
+    let args = ["--etag-cache", "--pretty", "format", "--show-cached", "get", &url, "--body"];
+    let second = httpie(&args).await;
+    assert!(second.status.success());
+    let stderr = String::from_utf8(second.stderr).unwrap();
+    assert!(stderr.starts_with("Not Modified (cached copy from "), "{}", stderr);
+    assert_eq!(String::from_utf8(second.stdout).unwrap(), "{\n  \"a\": 1\n}\n");
+}
+
+#[tokio::test]
+async fn cache_serves_fresh_responses_without_a_request() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/fresh"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("cache-control", "max-age=600")
+                .set_body_raw(r#"{"a":1}"#, "application/json"),
+        )
+        .expect(2)
+        .mount(&server)
+        .await;
+    let url = format!("{}/fresh", server.uri());
+    let first = httpie(&["--cache", "get", &url]).await;
+    assert!(first.status.success());
+    let first = String::from_utf8(first.stdout).unwrap();
+
+    let second = httpie(&["--cache", "--body", "get", &url]).await;
+    let stderr = String::from_utf8(second.stderr).unwrap();
+    assert!(stderr.starts_with("from cache, age "), "{}", stderr);
+    assert_eq!(String::from_utf8(second.stdout).unwrap(), first);
+
+    // --cache-bypass 总是发送请求
+    let third = httpie(&["--cache", "--cache-bypass", "--body", "get", &url]).await;
+    assert!(String::from_utf8(third.stderr).unwrap().is_empty());
+}
+
+/// 回复每条文本消息两次, 一次原样, 一次是二进制, 结束时返回握手请求中的Sec-Key
+async fn ws_echo_server() -> (String, tokio::task::JoinHandle<Option<String>>) {
+    use futures_util::{SinkExt, StreamExt};
+    use tokio_tungstenite::tungstenite::{handshake::server, Message};
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("ws://{}/", listener.local_addr().unwrap());
+    let server = tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut key = None;
+        #[allow(clippy::result_large_err)]
+        let callback = |req: &server::Request, resp| {
+            key = req.headers().get("sec-key").map(|v| v.to_str().unwrap().to_string());
+            Ok(resp)
+        };
+        let mut ws = tokio_tungstenite::accept_hdr_async(stream, callback).await.unwrap();
+        while let Some(Ok(message)) = ws.next().await {
+            if message.is_text() {
+                ws.send(message.clone()).await.unwrap();
+                ws.send(Message::binary(vec![0; 3])).await.unwrap();
+            }
+        }
+        key
+    });
+    (url, server)
+}
+
+#[tokio::test]
+async fn ws_sends_messages_and_prints_replies() {
+    let (url, server) = ws_echo_server().await;
+    let args = ["--body", "--pretty", "format", "ws", &url, "Sec-Key:abc", "--message", r#"{"a":1}"#, "--wait", "0.5"];
+    let output = httpie(&args).await;
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(stdout, "{\n  \"a\": 1\n}\n+ binary message, 3 bytes +\n");
+    assert_eq!(server.await.unwrap().as_deref(), Some("abc"));
+}
+
+#[tokio::test]
+async fn ws_sends_each_line_of_stdin() {
+    use tokio::io::AsyncWriteExt;
+
+    let (url, server) = ws_echo_server().await;
+    let mut child = Command::new(env!("CARGO_BIN_EXE_httpie"))
+        .args(["--body", "ws", &url])
+        .env("XDG_CONFIG_HOME", Path::new(env!("CARGO_TARGET_TMPDIR")).join("config"))
+        .env("NO_COLOR", "1")
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .spawn()
+        .unwrap();
+    // 标准输入结束后发送Close, 服务器回复Close之后退出
+    let mut stdin = child.stdin.take().unwrap();
+    stdin.write_all(b"one\ntwo\n").await.unwrap();
+    drop(stdin);
+    let output = child.wait_with_output().await.unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let binary = "+ binary message, 3 bytes +";
+    assert_eq!(stdout.lines().collect::<Vec<_>>(), ["one", binary, "two", binary]);
+    assert_eq!(server.await.unwrap(), None);
+}
@@ -0,0 +1,87 @@
+use httpie::{error::ErrorStatus, fetch};
+use reqwest::StatusCode;
+use serde_json::json;
+use wiremock::{
+    matchers::{body_json, header, method, path, query_param},
+    Mock, MockServer, ResponseTemplate,
+};
+
+#[tokio::test]
+async fn get_returns_status_headers_and_body() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/users"))
+        .and(query_param("page", "2"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("x-total", "42")
+                .set_body_json(json!({"users": ["tom"]})),
+        )
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let url = format!("{}/users", server.uri());
+    let resp = fetch(["httpie", "get", &url, "page==2"]).await.unwrap();
+    assert_eq!(resp.status, StatusCode::OK);
+    assert_eq!(resp.headers["x-total"], "42");
+    assert_eq!(resp.url.path(), "/users");
+    assert_eq!(resp.json().unwrap(), json!({"users": ["tom"]}));
+}
+
+#[tokio::test]
+async fn post_sends_items_as_json_body() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/users"))
+        .and(header("content-type", "application/json"))
+        .and(body_json(json!({"name": "tom", "age": 3, "tags": ["a"]})))
+        .respond_with(ResponseTemplate::new(201).set_body_string("created"))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let url = format!("{}/users", server.uri());
+    // 测试的标准输入可能没有关闭, 不读取它
+    let args = ["httpie", "--ignore-stdin", "post", &url, "name=tom", "age:=3", "tags[]=a"];
+    let resp = fetch(args).await.unwrap();
+    assert_eq!(resp.status, StatusCode::CREATED);
+    assert_eq!(resp.text(), "created");
+}
+
+#[tokio::test]
+async fn header_items_are_sent() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(header("x-api-key", "secret"))
+        .and(header("user-agent", "test-agent"))
+        .respond_with(ResponseTemplate::new(204))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let url = server.uri();
+    let args = ["httpie", "--user-agent", "test-agent", "get", &url, "X-Api-Key:secret"];
+    let resp = fetch(args).await.unwrap();
+    assert_eq!(resp.status, StatusCode::NO_CONTENT);
+    assert!(resp.body.is_empty());
+}
+
+#[tokio::test]
+async fn error_statuses_are_returned_unless_checked() {
+    let server = MockServer::start().await;
+    Mock::given(path("/missing"))
+        .respond_with(ResponseTemplate::new(404).set_body_string("not found"))
+        .mount(&server)
+        .await;
+
+    let url = format!("{}/missing", server.uri());
+    let resp = fetch(["httpie", "get", &url]).await.unwrap();
+    assert_eq!(resp.status, StatusCode::NOT_FOUND);
+    assert_eq!(resp.text(), "not found");
+
+    let err = fetch(["httpie", "--check-status", "get", &url]).await.unwrap_err();
+    let status = err.downcast_ref::<ErrorStatus>().unwrap();
+    assert_eq!(status.0, StatusCode::NOT_FOUND);
+    assert_eq!(status.exit_code(), 4);
+}